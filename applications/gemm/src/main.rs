@@ -6,6 +6,7 @@ pub mod single_strassen;
 pub mod utils;
 
 use std::{fs::File, time::Instant, io::Write};
+use drust::drust_std::utils::ResourceManager;
 use tokio::runtime::Runtime;
 
 use conf::*;
@@ -1,12 +1,13 @@
 use std::{borrow::Borrow, thread};
 
+use drust::drust_std::utils::Resource;
 use tokio::{runtime::Runtime, time::Instant};
 
 use crate::{conf::*, matrix::*, single_strassen::*, BRANCHES, COMPUTES};
 
 use num::integer::Roots;
 
-pub async fn single_strassen_mul(a: Vec<i32>, b: Vec<i32>, m0: usize, resource: usize) -> Vec<i32> {
+pub async fn single_strassen_mul(a: Vec<i32>, b: Vec<i32>, m0: usize, resource: Resource) -> Vec<i32> {
     let matrix_a = Matrix::from_vec(a, m0);
     let matrix_b = Matrix::from_vec(b, m0);
     let result = strassen_mul(matrix_a, matrix_b).to_vec();
@@ -1,7 +1,8 @@
 #include "rdma-common.h"
 
 // static const long long RDMA_BUFFER_SIZE = 1024*1024*1024*4ULL;
-struct rdma_cm_id *global_conn[NUM_SERVERS + 2] = {NULL};
+struct rdma_cm_id *global_conn[MAX_SERVERS + 1] = {NULL};
+size_t total_num_servers = 2;
 size_t remote_region_start_addr = 0;
 size_t region_size = 0;
 // the index for the total number of connection (active+passive)
@@ -77,7 +78,7 @@ static void post_receives(struct connection *conn);
 static void register_memory(struct connection *conn);
 static void send_message(struct connection *conn);
 
-static struct context *s_ctx[NUM_SERVERS + 2] = {NULL};
+static struct context *s_ctx[MAX_SERVERS + 1] = {NULL};
 static enum mode s_mode = M_WRITE;
 
 void die(const char *reason)
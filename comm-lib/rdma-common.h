@@ -24,11 +24,14 @@
     if (!(x))                                            \
       die("error: " #x " failed (returned zero/null)."); \
   } while (0)
-#define TOTAL_NUM_SERVERS 2
+#define MAX_SERVERS 16
+// Set at startup through drust_set_num_servers, so one build serves any cluster size.
+extern size_t total_num_servers;
+#define TOTAL_NUM_SERVERS total_num_servers
 #define NUM_SERVERS (TOTAL_NUM_SERVERS - 1)
 #define THREAD_FLAG_NUM (1024 * 1024)
 
-extern struct rdma_cm_id *global_conn[NUM_SERVERS + 2];
+extern struct rdma_cm_id *global_conn[MAX_SERVERS + 1];
 extern size_t remote_region_start_addr;
 extern size_t region_size;
 extern size_t cur_id;
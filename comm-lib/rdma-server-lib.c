@@ -14,6 +14,37 @@ static void make_passive_connection(const char *passive_ip_str, const char *port
 static void usage(const char *argv0);
 
 static size_t global_server_id = 0;
+// Filled in by drust_set_server_addr for every server before drust_start_server.
+static char server_ips[MAX_SERVERS][INET6_ADDRSTRLEN];
+static uint16_t server_base_ports[MAX_SERVERS];
+
+void drust_set_num_servers(size_t num_servers)
+{
+  if (num_servers < 1 || num_servers > MAX_SERVERS)
+  {
+    printf("num_servers should be between 1 and %d\n", MAX_SERVERS);
+    printf("num_servers: %ld\n", num_servers);
+    exit(1);
+  }
+  total_num_servers = num_servers;
+}
+
+// A server listens on `ip` at `base_port + i` for the connection from server i.
+void drust_set_server_addr(size_t server_id, const char *ip, uint16_t base_port)
+{
+  if (server_id >= MAX_SERVERS || strlen(ip) >= INET6_ADDRSTRLEN)
+  {
+    printf("invalid address %s for server %ld\n", ip, server_id);
+    exit(1);
+  }
+  strcpy(server_ips[server_id], ip);
+  server_base_ports[server_id] = base_port;
+}
+
+static void port_of(size_t server_id, size_t peer_id, char *port_str)
+{
+  snprintf(port_str, 8, "%u", (unsigned)(server_base_ports[server_id] + peer_id));
+}
 
 int drust_start_server(size_t heap_start, size_t heap_size, size_t server_id)
 {
@@ -31,9 +62,9 @@ int drust_start_server(size_t heap_start, size_t heap_size, size_t server_id)
 
   global_server_id = server_id;
 
-  const char *ip_str[9] = {"10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4", "10.0.0.5", "10.0.0.6", "10.0.0.10", "10.0.0.11", "10.0.0.1"};
-  const char *port_str[8] = {"9400", "9401", "9402", "9403", "9404", "9405", "9406", "9407"};
-  printf("%s, trying to bind to %s:%s.\n", __func__, ip_str[server_id], port_str[cur_id]);
+  char port_str[8];
+  port_of(server_id, cur_id, port_str);
+  printf("%s, trying to bind to %s:%s.\n", __func__, server_ips[server_id], port_str);
 
   // init_mem_region();
   // For every server, with a given `server_id` i and `NUM_SERVERS` n
@@ -42,7 +73,8 @@ int drust_start_server(size_t heap_start, size_t heap_size, size_t server_id)
   // Make the passive connections
   while (cur_id < server_id)
   {
-    make_passive_connection(ip_str[server_id], port_str[cur_id]);
+    port_of(server_id, cur_id, port_str);
+    make_passive_connection(server_ips[server_id], port_str);
     wait_until_connection_established(global_conn[cur_id]);
     cur_id++;
   }
@@ -54,7 +86,8 @@ int drust_start_server(size_t heap_start, size_t heap_size, size_t server_id)
   {
     // Connect to the servers backwards, starting from server[NUM_SERVERS-1]
     printf("passive_ip_idx: %lu\n", passive_ip_idx);
-    make_active_connection(ip_str[passive_ip_idx], port_str[server_id]);
+    port_of(passive_ip_idx, server_id, port_str);
+    make_active_connection(server_ips[passive_ip_idx], port_str);
     wait_until_connection_established(global_conn[cur_id]);
     cur_id++;
     passive_ip_idx--;
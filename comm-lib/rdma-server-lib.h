@@ -2,6 +2,8 @@
 #define RDMA_SERVER_H
 #include "rdma-common.h"
 
+void drust_set_num_servers(size_t num_servers);
+void drust_set_server_addr(size_t server_id, const char *ip, uint16_t base_port);
 int drust_start_server(size_t heap_start, size_t heap_size, size_t server_id);
 void drust_server_ready(void);
 
@@ -0,0 +1,156 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Index, Result};
+
+// #[derive(DRust)] implements drust's DRust trait and registers the type, so boxes and vectors of
+// it can be dropped on any server. The type id is a hash of the type's path. migrate and
+// drop_local_copies are forwarded to every field; plain data fields that do not implement DRust
+// are marked #[drust(skip)].
+#[proc_macro_derive(DRust, attributes(drust))]
+pub fn derive_drust(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    if input.generics.lt_token.is_some() || input.generics.where_clause.is_some() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "derive(DRust) needs a concrete type to register; implement DRust by hand for generic types",
+        ));
+    }
+    let ident = &input.ident;
+    let migrate = for_each_field(&input.data, |binding| {
+        quote! {
+            embedded |= ::drust::drust_std::primitives::DRust::migrate(#binding, dst);
+        }
+    })?;
+    let drop_local_copies = for_each_field(&input.data, |binding| {
+        quote! {
+            ::drust::drust_std::primitives::DRust::drop_local_copies(#binding);
+        }
+    })?;
+    Ok(quote! {
+        impl ::drust::drust_std::primitives::DRust for #ident {
+            fn static_typeid() -> u32 {
+                const TYPEID: u32 = ::drust::drust_std::primitives::registry::derived_typeid(
+                    concat!(module_path!(), "::", stringify!(#ident)),
+                );
+                TYPEID
+            }
+            fn typeid(&self) -> u32 {
+                <Self as ::drust::drust_std::primitives::DRust>::static_typeid()
+            }
+            #[allow(unused_mut, unused_variables)]
+            fn migrate(&mut self, dst: ::drust::drust_std::primitives::Destination) -> bool {
+                let mut embedded = false;
+                #migrate
+                embedded
+            }
+            fn drop_local_copies(&mut self) {
+                #drop_local_copies
+            }
+        }
+
+        ::drust::inventory::submit! {
+            ::drust::drust_std::primitives::registry::TypeEntry::new::<#ident>(
+                concat!(module_path!(), "::", stringify!(#ident)),
+            )
+        }
+    })
+}
+
+fn skipped(attrs: &[Attribute]) -> Result<bool> {
+    let mut skip = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("drust")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown drust attribute, expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+// Applies `call` to a `&mut` binding of every field that is not skipped.
+fn for_each_field(data: &Data, call: impl Fn(TokenStream2) -> TokenStream2) -> Result<TokenStream2> {
+    match data {
+        Data::Struct(data) => struct_fields(&data.fields, &call),
+        Data::Enum(data) => {
+            let arms = data
+                .variants
+                .iter()
+                .map(|variant| variant_fields(&variant.ident, &variant.fields, &call))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(quote! {
+                match self {
+                    #(#arms)*
+                }
+            })
+        }
+        Data::Union(data) => Err(Error::new_spanned(
+            data.union_token,
+            "derive(DRust) does not support unions",
+        )),
+    }
+}
+
+fn struct_fields(
+    fields: &Fields,
+    call: &impl Fn(TokenStream2) -> TokenStream2,
+) -> Result<TokenStream2> {
+    let mut body = TokenStream2::new();
+    for (i, field) in fields.iter().enumerate() {
+        if skipped(&field.attrs)? {
+            continue;
+        }
+        let member = match &field.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = Index::from(i);
+                quote!(#index)
+            }
+        };
+        body.extend(call(quote!(&mut self.#member)));
+    }
+    Ok(body)
+}
+
+fn variant_fields(
+    variant: &syn::Ident,
+    fields: &Fields,
+    call: &impl Fn(TokenStream2) -> TokenStream2,
+) -> Result<TokenStream2> {
+    let mut patterns = Vec::new();
+    let mut body = TokenStream2::new();
+    for (i, field) in fields.iter().enumerate() {
+        let skip = skipped(&field.attrs)?;
+        match &field.ident {
+            Some(ident) if !skip => {
+                patterns.push(quote!(#ident));
+                body.extend(call(quote!(#ident)));
+            }
+            Some(_) => {}
+            None if skip => patterns.push(quote!(_)),
+            None => {
+                let binding = format_ident!("field_{}", i);
+                patterns.push(quote!(#binding));
+                body.extend(call(quote!(#binding)));
+            }
+        }
+    }
+    let pattern = match fields {
+        Fields::Named(_) => quote!({ #(#patterns,)* .. }),
+        Fields::Unnamed(_) => quote!((#(#patterns),*)),
+        Fields::Unit => quote!(),
+    };
+    Ok(quote! {
+        Self::#variant #pattern => { #body }
+    })
+}
@@ -1,7 +1,10 @@
 fn main() {
-    println!("cargo:rustc-link-lib=static=myrdma");
-    println!("cargo:rustc-link-lib=rdmacm");
-    println!("cargo:rustc-link-lib=ibverbs");
+    if std::env::var_os("CARGO_FEATURE_RDMA").is_some() {
+        println!("cargo:rustc-link-lib=static=myrdma");
+        println!("cargo:rustc-link-lib=rdmacm");
+        println!("cargo:rustc-link-lib=ibverbs");
+        // Absolute, so crates that depend on drust link it from their own workspace.
+        println!("cargo:rustc-link-search={}", std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    }
     println!("cargo:rustc-link-lib=pthread");
-    println!("cargo:rustc-link-search=./drust");
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
+use crate::drust_std::connect::config::log_dir;
 use futures::{future, prelude::*};
 use serde::{Deserialize, Serialize};
 use std::{
@@ -12,7 +13,7 @@ use super::super::{frame, prelude::DataType};
 use super::super::prelude::*;
 use super::utils::*;
 
-use crate::drust_std::utils::*;
+use crate::drust_std::{thread::capacity, utils::*};
 use crate::conf::*;
 
 async fn groupby_work(
@@ -64,7 +65,7 @@ async fn groupby_work(
 
 pub async fn h2oai_groupby_benchmark(dataset_size: DSize) {
     unsafe{
-        COMPUTES = Some(ResourceManager::new(NUM_SERVERS));
+        COMPUTES = Some(capacity::shared_computes());
     }
 
     let (f, line_cnt) = match dataset_size {
@@ -95,7 +96,7 @@ pub async fn h2oai_groupby_benchmark(dataset_size: DSize) {
     print(&mut x).await;
 
     let file_name = format!(
-        "{}/DRust_home/logs/dataframe_drust_{}.txt", dirs::home_dir().unwrap().display(), NUM_SERVERS
+        "{}/dataframe_drust_{}.txt", log_dir(), unsafe { NUM_SERVERS }
     );
     let mut wrt_file = File::create(file_name).expect("file");
 
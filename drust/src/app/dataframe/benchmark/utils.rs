@@ -1,3 +1,4 @@
+use crate::drust_std::connect::config::dataset_dir;
 use std::{
     fs::File,
     io::{Write, self},
@@ -5,12 +6,12 @@ use std::{
 };
 
 use tarpc::context;
-use tokio::task::JoinHandle;
+use crate::drust_std::thread::DJoinHandle;
 
 use super::super::series::input::read_series;
 
 use super::super::prelude::*;
-use crate::{conf::*, drust_std::{collections::dvec::DVec, thread::{dlink::get_dclient, dspawn, dspawn_to}}};
+use crate::{conf::*, drust_std::{collections::dvec::DVec, thread::{dlink::get_dclient, dspawn, dspawn_pooled_to}}};
 
 #[derive(Debug)]
 pub enum DSize {
@@ -27,7 +28,7 @@ pub async fn read_csv_from_file(
     line_cnt: usize,
 ) -> Result<DataFrame, PolarsError> {
     let full_path = format!(
-        "{}/DRust_home/dataset/dataframe/my_{}", dirs::home_dir().unwrap().display(),
+        "{}/dataframe/my_{}", dataset_dir(),
         file_name
     );
     let mut reader = csv::Reader::from_path(full_path).unwrap();
@@ -47,7 +48,7 @@ pub async fn read_csv_from_file(
         let line_count = line_cnt;
         let series_id = index;
 
-        let handle: JoinHandle<DVec<Chunk>> = dspawn_to(read_series(datatype, series_id, line_count), GLOBAL_HEAP_START + index % NUM_SERVERS * WORKER_UNIT_SIZE);
+        let handle: DJoinHandle<DVec<Chunk>> = dspawn_pooled_to(read_series(datatype, series_id, line_count), unsafe { index % NUM_SERVERS });
         jobs.push(handle);
         index += 1;
     }
@@ -1,4 +1,4 @@
-use crate::{dprintln, drust_std::{alloc::LOCAL_ALLOCATOR, collections::dvec::DVec, primitives::dref::*}, exclude};
+use crate::{dprintln, drust_std::{alloc::local_allocator, collections::dvec::DVec, primitives::dref::*}, exclude};
 use super::super::{prelude::*, datatypes::AnyType, chunked_array::conf::CHUNK_SIZE};
 
 pub struct Chunk {
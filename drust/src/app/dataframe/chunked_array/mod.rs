@@ -11,7 +11,7 @@ use serde::{ Deserialize, Serialize };
 use utils::*;
 use super::{prelude::*, utils::*};
 
-use crate::{dprintln, drust_std::{alloc::LOCAL_ALLOCATOR, collections::dvec::{DVec, DVecMutRef, DVecRef}}};
+use crate::{dprintln, drust_std::{alloc::local_allocator, collections::dvec::{DVec, DVecMutRef, DVecRef}}};
 
 pub struct ChunkedArray<T> {
     pub(crate) field: Field,
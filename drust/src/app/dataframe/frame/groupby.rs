@@ -5,7 +5,7 @@ use enum_dispatch::enum_dispatch;
 use num::{ Num, NumCast };
 use rayon::prelude::*;
 use std::{ borrow::Borrow, collections::HashMap, hash::Hash, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{ Duration, SystemTime } };
-use crate::{conf::*, dprintln, drust_std::{alloc::LOCAL_ALLOCATOR, collections::dvec::{DVec, DVecRef}, thread::{dscope_spawn, dspawn, dspawn_to}}};
+use crate::{conf::*, dprintln, drust_std::{alloc::local_allocator, collections::dvec::{DVec, DVecRef}, thread::{dscope_spawn, dspawn, dspawn_to}}};
 
 use self::utils::to_chunked_index;
 
@@ -184,7 +184,7 @@ impl<T> NumericAggSync
     async fn agg_min(&self, indices: DVecRef<'_, usize>, groups: DVecRef<'_, usize>) -> Series {
         let src_ref = self.get_ref();
         let datatype = self.dtype().clone();
-        let array = dspawn_to(agg_min(datatype, src_ref, indices, groups), GLOBAL_HEAP_START + WORKER_UNIT_SIZE * self.chunks.server_idx()).await.unwrap();
+        let array = dspawn_to(agg_min(datatype, src_ref, indices, groups), unsafe { GLOBAL_HEAP_START + WORKER_UNIT_SIZE * self.chunks.server_idx() }).await.unwrap();
         let field = Field::new(self.name(), DataType::Float64, true);
         Series::from_raw(field, array)
     }
@@ -192,7 +192,7 @@ impl<T> NumericAggSync
     async fn agg_sum(&self, indices: DVecRef<'_, usize>, groups: DVecRef<'_, usize>) -> Series {
         let src_ref = self.get_ref();
         let datatype = self.dtype().clone();
-        let array = dspawn_to(agg_sum(datatype, src_ref, indices, groups), GLOBAL_HEAP_START + WORKER_UNIT_SIZE * self.chunks.server_idx()).await.unwrap();
+        let array = dspawn_to(agg_sum(datatype, src_ref, indices, groups), unsafe { GLOBAL_HEAP_START + WORKER_UNIT_SIZE * self.chunks.server_idx() }).await.unwrap();
         let field = Field::new(self.name(), DataType::Float64, true);
         Series::from_raw(field, array)
     }
@@ -359,7 +359,7 @@ impl<T> IntoGroupTuples for ChunkedArray<T> where T: PolarsIntegerType, T::Nativ
         datatypes: DVec<DataType>,
         arrays: DVec<DVecRef<'_, Chunk>>
     ) -> (DVec<usize>, DVec<usize>, DVec<usize>) {
-        let re = dscope_spawn(groupby_work(datatypes, arrays)).await;
+        let re = dscope_spawn(groupby_work(datatypes, arrays)).await.unwrap();
         re
     }
 }
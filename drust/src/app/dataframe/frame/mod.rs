@@ -7,12 +7,12 @@ use itertools::Itertools;
 use serde::{ Serialize, Deserialize };
 use rayon::{ prelude::*, vec };
 use csv;
-use tokio::task::JoinHandle;
+use crate::drust_std::thread::DJoinHandle;
 
 use super::prelude::*;
 use crate::dprintln;
 use crate::drust_std::collections::dvec::DVec;
-use crate::drust_std::thread::dspawn;
+use crate::drust_std::thread::dspawn_near;
 use std::any::Any;
 use std::collections::HashMap;
 use std::mem;
@@ -179,7 +179,7 @@ impl DataFrame {
                 let iter_ref = iter.as_dref();
                 let printname = src_serie_name.clone();
                 dprintln!("In send taking iter unchecked request: {}", printname);
-                let handle: JoinHandle<DVec<Chunk>> = dspawn(take::take_unchecked(datatype, src_ref, iter_ref));
+                let handle: DJoinHandle<DVec<Chunk>> = dspawn_near((src_ref, iter_ref), |(src_ref, iter_ref)| take::take_unchecked(datatype, src_ref, iter_ref));
                 vec_handles.push((handle, src_serie_name, s.dtype().clone()));
             }
         }
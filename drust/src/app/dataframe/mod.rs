@@ -10,6 +10,7 @@ pub mod frame;
 
 
 
+use crate::drust_std::application::{AppFuture, DrustApp};
 use benchmark::{utils::*, groupby::h2oai_groupby_benchmark};
 
 
@@ -18,6 +19,18 @@ use benchmark::{utils::*, groupby::h2oai_groupby_benchmark};
 pub const DATASET_ID: usize = 2;
 pub const DATASET_NAME: &str = "G1_1e8_1e2_0_0.csv";
 
+pub struct Dataframe;
+
+impl DrustApp for Dataframe {
+    fn name(&self) -> &'static str {
+        "dataframe"
+    }
+
+    fn run(&self) -> AppFuture {
+        Box::pin(run())
+    }
+}
+
 pub async fn run() {
     match DATASET_ID {
         0 => h2oai_groupby_benchmark(DSize::Small).await,
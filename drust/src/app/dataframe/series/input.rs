@@ -1,9 +1,10 @@
+use crate::drust_std::connect::config::dataset_dir;
 use crate::{datatype_to_anytype, drust_std::collections::dvec::DVec};
 
 use super::{super::{prelude::*, self_arrow::DataType, DATASET_NAME}, chunk::Chunk, AnyType, Series};
 
 pub async fn read_series(datatype: DataType, series_id: usize, line_count: usize) -> DVec<Chunk> {
-    let full_path = format!("{}/DRust_home/dataset/dataframe/my_{}", dirs::home_dir().unwrap().display(), DATASET_NAME);
+    let full_path = format!("{}/dataframe/my_{}", dataset_dir(), DATASET_NAME);
     let mut reader = csv::Reader::from_path(full_path).unwrap();
     let headers = reader.headers().unwrap();
     if headers.len() < series_id {
@@ -287,11 +287,11 @@ impl Series {
     }
 
 
-    // pub unsafe fn agg_sum_numeric_unchecked(&mut self, datatype: DataType, src_series: &Vec<chunk::Chunk, &'static good_memory_allocator::SpinLockedAllocator>, indices: &Vec<usize,&'static good_memory_allocator::SpinLockedAllocator>, groups: &Vec<usize,&'static good_memory_allocator::SpinLockedAllocator>) {
+    // pub unsafe fn agg_sum_numeric_unchecked(&mut self, datatype: DataType, src_series: &Vec<chunk::Chunk, &'static HeapAllocator>, indices: &Vec<usize,&'static HeapAllocator>, groups: &Vec<usize,&'static HeapAllocator>) {
     //     apply_method_all_series!(self, agg_sum, datatype, src_series, indices, groups)
     // }
 
-    // pub unsafe fn agg_min_numeric_unchecked(&mut self, datatype: DataType, src_series: &Vec<chunk::Chunk, &'static good_memory_allocator::SpinLockedAllocator>, indices: &Vec<usize,&'static good_memory_allocator::SpinLockedAllocator>, groups: &Vec<usize,&'static good_memory_allocator::SpinLockedAllocator>) {
+    // pub unsafe fn agg_min_numeric_unchecked(&mut self, datatype: DataType, src_series: &Vec<chunk::Chunk, &'static HeapAllocator>, indices: &Vec<usize,&'static HeapAllocator>, groups: &Vec<usize,&'static HeapAllocator>) {
     //     apply_method_all_series!(self, agg_min, datatype, src_series, indices, groups)
     // }
 
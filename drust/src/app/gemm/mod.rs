@@ -3,6 +3,8 @@ pub mod par_strassen;
 pub mod single_strassen;
 pub mod utils;
 
+use crate::drust_std::application::{AppFuture, DrustApp};
+use crate::drust_std::connect::config::log_dir;
 use std::{fs::File, time::Instant, io::Write};
 
 use conf::*;
@@ -10,15 +12,27 @@ use par_strassen::*;
 use utils::*;
 
 
-use crate::drust_std::{collections::dvec::*, utils::{ResourceManager, COMPUTES}};
+use crate::drust_std::{collections::dvec::*, thread::capacity, utils::{ResourceManager, COMPUTES}};
 use crate::{conf::*, dconnect};
 use crate::drust_std::connect::dsafepoint::*;
 
 
+pub struct Gemm;
+
+impl DrustApp for Gemm {
+    fn name(&self) -> &'static str {
+        "gemm"
+    }
+
+    fn run(&self) -> AppFuture {
+        Box::pin(run())
+    }
+}
+
 pub async fn run() {
     unsafe {
         BRANCHES = Some(ResourceManager::new(BRANCH_NUM));
-        COMPUTES = Some(ResourceManager::new(THREADS_NUM));
+        COMPUTES = Some(capacity::shared_computes());
     }
     let mut matrix_a = DVec::with_capacity(MATRIX_SIZE * MATRIX_SIZE);
     let mut matrix_b = DVec::with_capacity(MATRIX_SIZE * MATRIX_SIZE);
@@ -39,7 +53,7 @@ pub async fn run() {
         duration
     );
     let file_name = format!(
-        "{}/DRust_home/logs/gemm_drust_{}.txt", dirs::home_dir().unwrap().display(), NUM_SERVERS
+        "{}/gemm_drust_{}.txt", log_dir(), unsafe { NUM_SERVERS }
     );
     let mut wrt_file = File::create(file_name).expect("file");
     let milli_seconds = duration.as_millis();
@@ -285,7 +285,7 @@ pub async fn par_strassen_mul(
 
     let thread_diverge: u32 = 7;
 
-    // if thread_diverge.pow(level + 1) < THREADS_NUM as u32 {
+    // if thread_diverge.pow(level + 1) < threads_num() as u32 {
     if level == 1 {
         let m1_handle = thread::spawn(move || {
             Runtime::new()
@@ -343,7 +343,7 @@ pub async fn par_strassen_mul(
         return constitute(m7, m5, m4, m1);
     }
     if level == 2
-    /*&& THREADS_NUM >= 32*/
+    /*&& threads_num() >= 32*/
     {
         let branch_manager = unsafe { BRANCHES.as_ref().unwrap() };
         let thread_resource = branch_manager.get_resource(0);
@@ -426,13 +426,13 @@ pub async fn par_strassen_mul(
     // let aa_vec = vec![aa1, aa2, aa3, aa4, aa5, aa6, aa7];
     // let bb_vec = vec![bb1, bb2, bb3, bb4, bb5, bb6, bb7];
     // let mut handles_vec = vec![];
-    let m1_handle = dspawn(single_strassen_mul(aa1, bb1, m));
-    let m2_handle = dspawn(single_strassen_mul(aa2, bb2, m));
-    let m3_handle = dspawn(single_strassen_mul(aa3, bb3, m));
-    let m4_handle = dspawn(single_strassen_mul(aa4, bb4, m));
-    let m5_handle = dspawn(single_strassen_mul(aa5, bb5, m));
-    let m6_handle = dspawn(single_strassen_mul(aa6, bb6, m));
-    let m7_handle = dspawn(single_strassen_mul(aa7, bb7, m));
+    let m1_handle = dspawn_pooled(single_strassen_mul(aa1, bb1, m));
+    let m2_handle = dspawn_pooled(single_strassen_mul(aa2, bb2, m));
+    let m3_handle = dspawn_pooled(single_strassen_mul(aa3, bb3, m));
+    let m4_handle = dspawn_pooled(single_strassen_mul(aa4, bb4, m));
+    let m5_handle = dspawn_pooled(single_strassen_mul(aa5, bb5, m));
+    let m6_handle = dspawn_pooled(single_strassen_mul(aa6, bb6, m));
+    let m7_handle = dspawn_pooled(single_strassen_mul(aa7, bb7, m));
 
     let mut m1: DVec<i32> = m1_handle.await.unwrap();
     let mut m2: DVec<i32> = m2_handle.await.unwrap();
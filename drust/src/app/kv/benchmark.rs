@@ -1,11 +1,13 @@
+use crate::drust_std::connect::config::{dataset_dir, log_dir};
 use std::{fs::File, sync::Arc, io::Write};
 
 use rand::{
     distributions::{Distribution, Uniform}, rngs::StdRng, thread_rng, SeedableRng
 };
-use tokio::{runtime::Runtime, task::JoinHandle};
+use tokio::runtime::Runtime;
+use crate::drust_std::thread::DJoinHandle;
 
-use super::{dmap::KVStore, dmap::*, entry::GlobalEntry, conf::{bucket, READ_RATIO, UNIT_BUCKET_NUM, UNIT_THREAD_BUCKET_NUM, THREAD_NUM}};
+use super::{dmap::KVStore, dmap::*, entry::GlobalEntry, conf::{bucket, READ_RATIO, unit_bucket_num, unit_thread_bucket_num, THREAD_NUM}};
 
 
 use crate::{conf::{GLOBAL_HEAP_START, NUM_SERVERS, SERVER_INDEX, WORKER_UNIT_SIZE}, drust_std::{collections::dvec::DVecRef, sync::dmutex::DMutex, thread::dspawn_to}};
@@ -15,7 +17,7 @@ static mut KEYS: Option<Vec<Vec<(usize, i32)>>> = None;
 
 pub async fn populate(map: DVecRef<'_, DMutex<GlobalEntry>>) {
     let v = ['x' as u8; 32];
-    let csv_file = format!("{}/DRust_home/dataset/dht/zipf/gam_data_0.99_100000000_{}_{}.csv", dirs::home_dir().unwrap().display(), NUM_SERVERS, unsafe{SERVER_INDEX % NUM_SERVERS});
+    let csv_file = format!("{}/dht/zipf/gam_data_0.99_100000000_{}_{}.csv", dataset_dir(), unsafe { NUM_SERVERS }, unsafe{SERVER_INDEX % NUM_SERVERS});
     let mut rdr = csv::Reader::from_path(csv_file).unwrap();
     let mut cnt = 0;
     let popstart = tokio::time::Instant::now();
@@ -30,7 +32,7 @@ pub async fn populate(map: DVecRef<'_, DMutex<GlobalEntry>>) {
     }
 
     // cnt = 0;
-    // let csv_file = format!("{}/DRust_home/dataset/dht/zipf/gam_data_0.99_100000000_{}_{}.csv", dirs::home_dir().unwrap().display(), NUM_SERVERS, unsafe{(SERVER_INDEX + 1) % NUM_SERVERS});
+    // let csv_file = format!("{}/dht/zipf/gam_data_0.99_100000000_{}_{}.csv", dataset_dir(), unsafe { NUM_SERVERS }, unsafe{(SERVER_INDEX + 1) % NUM_SERVERS});
     // rdr = csv::Reader::from_path(csv_file).unwrap();
     // let mut rng = thread_rng();
     // let range = Uniform::from(0..100);
@@ -44,8 +46,8 @@ pub async fn populate(map: DVecRef<'_, DMutex<GlobalEntry>>) {
     //     let key: usize = record[0].parse().unwrap();
     //     let r = range.sample(&mut rng);
     //     let bucket_id = bucket(key);
-    //     let bucket_s_offset = bucket_id % UNIT_BUCKET_NUM;
-    //     let thread_id = bucket_s_offset / UNIT_THREAD_BUCKET_NUM;
+    //     let bucket_s_offset = bucket_id % unit_bucket_num();
+    //     let thread_id = bucket_s_offset / unit_thread_bucket_num();
     //     keys_vec[thread_id].push((key, r));
     //     cnt += 1;
     // }
@@ -57,7 +59,7 @@ pub async fn benchmark(map: DVecRef<'_, DMutex<GlobalEntry>>) {
     let v = ['x' as u8; 32];
     let start = tokio::time::Instant::now();
 
-    let csv_file = format!("{}/DRust_home/dataset/dht/zipf/gam_data_0.99_100000000_{}_{}.csv", dirs::home_dir().unwrap().display(), NUM_SERVERS, unsafe{(SERVER_INDEX + 1) % NUM_SERVERS});
+    let csv_file = format!("{}/dht/zipf/gam_data_0.99_100000000_{}_{}.csv", dataset_dir(), unsafe { NUM_SERVERS }, unsafe{(SERVER_INDEX + 1) % NUM_SERVERS});
     let mut rdr = csv::Reader::from_path(csv_file).unwrap();
     let mut rng = StdRng::seed_from_u64(0);
     let range = Uniform::from(0..100000000);
@@ -88,9 +90,9 @@ pub async fn zipf_bench() {
 
     let popstart = tokio::time::Instant::now();
     let mut handles = vec![];
-    for i in 0..NUM_SERVERS {
+    for i in 0..unsafe { NUM_SERVERS } {
         let map_ref = map.as_dref();
-        let handle: JoinHandle<()> = dspawn_to(populate(map_ref), GLOBAL_HEAP_START + i * WORKER_UNIT_SIZE);
+        let handle: DJoinHandle<()> = dspawn_to(populate(map_ref), unsafe { GLOBAL_HEAP_START + i * WORKER_UNIT_SIZE });
         handles.push(handle);
     }
     for handle in handles {
@@ -103,9 +105,9 @@ pub async fn zipf_bench() {
     
     let mut handles = vec![];
     let start = tokio::time::Instant::now();
-    for i in 0..NUM_SERVERS {
+    for i in 0..unsafe { NUM_SERVERS } {
         let map_ref = map.as_dref();
-        let handle: JoinHandle<()> = dspawn_to(benchmark(map_ref), GLOBAL_HEAP_START + i * WORKER_UNIT_SIZE);
+        let handle: DJoinHandle<()> = dspawn_to(benchmark(map_ref), unsafe { GLOBAL_HEAP_START + i * WORKER_UNIT_SIZE });
         handles.push(handle);
     }
     
@@ -117,7 +119,7 @@ pub async fn zipf_bench() {
     println!("Total Throughput: {:?}", 100000000 as f64 / time.as_secs_f64());   
     
     let file_name = format!(
-        "{}/DRust_home/logs/kv_drust_{}.txt", dirs::home_dir().unwrap().display(), NUM_SERVERS
+        "{}/kv_drust_{}.txt", log_dir(), unsafe { NUM_SERVERS }
     );
     let mut wrt_file = File::create(file_name).expect("file");
     let milli_seconds = time.as_millis();
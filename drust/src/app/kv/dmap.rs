@@ -7,8 +7,8 @@ pub struct KVStore(DVec<DMutex<GlobalEntry>>);
 
 impl KVStore {
     pub fn new() -> DVec<DMutex<GlobalEntry>> {
-        let mut store = DVec::with_capacity(BUCKET_NUM);
-        for _ in 0..BUCKET_NUM {
+        let mut store = DVec::with_capacity(bucket_num());
+        for _ in 0..bucket_num() {
             store.push(DMutex::new(GlobalEntry {key: 0, value: [0; 32],}));
         }
         store
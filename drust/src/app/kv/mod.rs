@@ -1,4 +1,5 @@
-use crate::{conf::NUM_SERVERS, drust_std::utils::{ResourceManager, COMPUTES}};
+use crate::drust_std::application::{AppFuture, DrustApp};
+use crate::drust_std::{thread::capacity, utils::COMPUTES};
 
 pub mod entry;
 pub mod conf;
@@ -6,10 +7,22 @@ pub mod benchmark;
 pub mod dmap;
 
 
+pub struct Kv;
+
+impl DrustApp for Kv {
+    fn name(&self) -> &'static str {
+        "kv"
+    }
+
+    fn run(&self) -> AppFuture {
+        Box::pin(run())
+    }
+}
+
 // load column from file and return a Column struct
 pub async fn run() {
     unsafe{
-        COMPUTES = Some(ResourceManager::new(NUM_SERVERS));
+        COMPUTES = Some(capacity::shared_computes());
     }
     benchmark::zipf_bench().await;
 }
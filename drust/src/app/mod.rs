@@ -1,5 +1,22 @@
 pub mod test;
+#[cfg(feature = "gemm")]
 pub mod gemm;
+#[cfg(feature = "dataframe")]
 pub mod dataframe;
+#[cfg(feature = "kv")]
 pub mod kv;
-pub mod socialnet;
\ No newline at end of file
+#[cfg(feature = "socialnet")]
+pub mod socialnet;
+
+// Adds the bundled apps to the registry, each behind its cargo feature. main calls it before
+// drust_std::prepare so `--application` can be checked against the registered names.
+pub fn register_apps() {
+    #[cfg(feature = "gemm")]
+    crate::drust_std::application::register_app(&gemm::Gemm);
+    #[cfg(feature = "dataframe")]
+    crate::drust_std::application::register_app(&dataframe::Dataframe);
+    #[cfg(feature = "kv")]
+    crate::drust_std::application::register_app(&kv::Kv);
+    #[cfg(feature = "socialnet")]
+    crate::drust_std::application::register_app(&socialnet::SocialNet);
+}
@@ -1,18 +1,15 @@
+use crate::drust_std::connect::config::log_dir;
 use std::{cmp, fs::File, io::Write, sync::Arc, time::SystemTime};
 
 use arr_macro::arr;
 use super::{conf::*, media::utils::decode_and_extract};
 
-use crate::{ app::socialnet::{compose, media::{init_media_storage, MEDIA}}, conf::{NUM_SERVERS, SERVER_INDEX}, drust_std::{collections::dstring::DString, connect::dsafepoint::set_ready, thread::{dspawn_to_relaxed, dspawn_to_strictly}, utils::{SimpleResourceManager, SIMPLE_COMPUTES}}};
+use crate::{ app::socialnet::{compose, media::{init_media_storage, MEDIA}}, conf::{NUM_SERVERS, SERVER_INDEX}, drust_std::{collections::dstring::DString, connect::dsafepoint::set_ready, thread::{capacity, dspawn_to_relaxed, dspawn_to_strictly}, utils::SIMPLE_COMPUTES}};
 
 
 pub async fn socialnet_benchmark() {
   unsafe {
-    let mut simple_computes = Vec::new();
-    for i in 0..NUM_SERVERS {
-      simple_computes.push(SimpleResourceManager::new(THREAD_NUM));
-    }
-    SIMPLE_COMPUTES = Some(simple_computes);
+    SIMPLE_COMPUTES = Some(capacity::per_server_computes());
   }
   for i in VIDEO_STORAGE_SERVER_START..(VIDEO_STORAGE_SERVER_START + VIDEO_STORAGE_SERVER_NUM) {
     let result: () = dspawn_to_relaxed(init_media_storage(), i).await.unwrap();
@@ -22,7 +19,7 @@ pub async fn socialnet_benchmark() {
   let start = SystemTime::now();
   let mut id = 0;
   let total_reqs = 18432;
-  let unit_reqs = cmp::min(256 * NUM_SERVERS, 1024);
+  let unit_reqs = cmp::min(256 * unsafe { NUM_SERVERS }, 1024);
   for i in 0..(total_reqs / unit_reqs){
       let mut handles = Vec::new();
       for id in (i * unit_reqs)..(i * unit_reqs + unit_reqs) {
@@ -43,7 +40,7 @@ pub async fn socialnet_benchmark() {
   println!("Elapsed Time: {:?}", time);
   
   let file_name = format!(
-    "{}/DRust_home/logs/sn_drust_{}.txt", dirs::home_dir().unwrap().display(), NUM_SERVERS
+    "{}/sn_drust_{}.txt", log_dir(), unsafe { NUM_SERVERS }
   );
   let mut wrt_file = File::create(file_name).expect("file");
   let milli_seconds = time.as_millis();
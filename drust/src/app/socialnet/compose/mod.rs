@@ -1,7 +1,7 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use ffmpeg_next::ffi::dprintf;
-use tokio::task::JoinHandle;
+use crate::drust_std::thread::DJoinHandle;
 
 use crate::{conf::NUM_SERVERS, dprintln, drust_std::{collections::{dstring::DString, dvec::DVec}, thread::{dspawn, dspawn_to, dspawn_to_relaxed, dspawn_to_strictly}}};
 
@@ -14,11 +14,12 @@ pub async fn compose_post(req_id: usize, user_id: usize, mut text: DString, medi
     let unique_id = uniqueid::unique_id().await;
     dprintln!("unique_id: {}", unique_id);
     let mut media_id = (unique_id + 17) * 107 % 1000000007;
-    let compute_server_idx = unique_id % NUM_SERVERS * 107 % NUM_SERVERS;
+    let num_servers = unsafe { NUM_SERVERS };
+    let compute_server_idx = unique_id % num_servers * 107 % num_servers;
     dprintln!("compute_server_idx: {}", compute_server_idx);
-    let media_service_handle: JoinHandle<usize> = dspawn_to_relaxed(media_service(media_id), compute_server_idx);
+    let media_service_handle: DJoinHandle<usize> = dspawn_to_relaxed(media_service(media_id), compute_server_idx);
     let text_mut = text.as_dmut();
-    let compute_server_idx2 = unique_id % NUM_SERVERS * 113 % NUM_SERVERS;
+    let compute_server_idx2 = unique_id % num_servers * 113 % num_servers;
     let text_results: DVec<DString> = dspawn_to_relaxed(text_service(text_mut), compute_server_idx2).await.unwrap();
     // println!("text_results: {:?}", text.as_ref());
     let processed_media_id = media_service_handle.await.unwrap();
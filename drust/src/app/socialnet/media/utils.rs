@@ -1,3 +1,4 @@
+use crate::drust_std::connect::config::dataset_dir;
 use ffmpeg_next::format::{input, Pixel};
 use ffmpeg_next::media::Type;
 use ffmpeg_next::software::scaling::{context::Context, flag::Flags};
@@ -17,7 +18,7 @@ pub fn decode_and_extract() -> Result<DVec<Image>, ffmpeg_next::Error> {
     let mut a = DVec::with_capacity(FRAME_NUM);
     let mut height = 0;
     let mut width = 0;
-    let video_full_path = format!("{}/{}", dirs::home_dir().unwrap().display(), VIDEO_PATH);
+    let video_full_path = format!("{}/{}", dataset_dir(), VIDEO_PATH);
     if let Ok(mut ictx) = input(&video_full_path) {
         let input = ictx
             .streams()
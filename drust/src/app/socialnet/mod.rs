@@ -7,6 +7,7 @@ pub mod benchmark;
 pub mod media;
 pub mod uniqueid;
 
+use crate::drust_std::application::{AppFuture, DrustApp};
 use std::{net::SocketAddr, io, time::{SystemTime, Duration, UNIX_EPOCH}, sync::{Arc, atomic::AtomicU64}};
 use dashmap::DashMap;
 use tokio::runtime::Runtime;
@@ -21,6 +22,18 @@ use crate::{conf::SERVER_INDEX, prelude::*};
 
 
 
+pub struct SocialNet;
+
+impl DrustApp for SocialNet {
+    fn name(&self) -> &'static str {
+        "sn"
+    }
+
+    fn run(&self) -> AppFuture {
+        Box::pin(run())
+    }
+}
+
 pub async fn run() {
     if unsafe{SERVER_INDEX} == 0 {
         socialnet_benchmark().await;
@@ -1,10 +1,33 @@
-pub const NUM_SERVERS: usize = 1;
-pub const GLOBAL_HEAP_START: usize = 0x400000000000 as usize;
-pub const UNIT_HEAP_SIZE_GB: usize = 96;
-pub const WORKER_HEAP_SIZE: usize = NUM_SERVERS * UNIT_HEAP_SIZE_GB as usize * 1024 * 1024 * 1024; // 48 for GEMM & 16 for all others
-pub const WORKER_UNIT_SIZE: usize = WORKER_HEAP_SIZE / NUM_SERVERS;
 pub const MAX_SERVERS: usize = 16;
+pub const DEFAULT_GLOBAL_HEAP_START: usize = 0x400000000000;
+pub const DEFAULT_UNIT_HEAP_SIZE_GB: usize = 96; // 48 for GEMM & 16 for all others
+// Cluster shape, set once at startup by init_cluster from drust.json and the command line.
+pub static mut NUM_SERVERS: usize = 1;
+pub static mut GLOBAL_HEAP_START: usize = DEFAULT_GLOBAL_HEAP_START;
+pub static mut WORKER_UNIT_SIZE: usize = DEFAULT_UNIT_HEAP_SIZE_GB * 1024 * 1024 * 1024;
+pub static mut WORKER_HEAP_SIZE: usize = DEFAULT_UNIT_HEAP_SIZE_GB * 1024 * 1024 * 1024;
 pub static mut SERVER_INDEX: usize = 0;
 pub static mut LOCAL_HEAP_START: usize = 0;
 pub const RPC_WAIT: u64 = 5000;
-pub static mut APPLICATION_NAME: Option<String> = None; // or gemm or kvstore or socialnet
\ No newline at end of file
+pub static mut APPLICATION_NAME: Option<String> = None; // or gemm or kvstore or socialnet
+
+pub fn init_cluster(num_servers: usize, unit_heap_size_gb: usize, global_heap_start: usize) {
+    assert!(
+        num_servers > 0 && num_servers <= MAX_SERVERS,
+        "number of servers {} must be between 1 and {}",
+        num_servers,
+        MAX_SERVERS
+    );
+    assert!(unit_heap_size_gb > 0, "heap size per server must be at least 1GB");
+    assert!(
+        global_heap_start % 4096 == 0,
+        "global heap start {:x} must be page aligned",
+        global_heap_start
+    );
+    unsafe {
+        NUM_SERVERS = num_servers;
+        GLOBAL_HEAP_START = global_heap_start;
+        WORKER_UNIT_SIZE = unit_heap_size_gb * 1024 * 1024 * 1024;
+        WORKER_HEAP_SIZE = num_servers * WORKER_UNIT_SIZE;
+    }
+}
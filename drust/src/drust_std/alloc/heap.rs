@@ -0,0 +1,101 @@
+use std::{
+    alloc::{AllocError, Allocator, Layout},
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use good_memory_allocator::SpinLockedAllocator;
+
+// A server's heap allocator: good_memory_allocator with running totals for stats(), so the
+// numbers never depend on how the allocator lays out its chunks. The totals count the requested
+// bytes, without chunk headers and padding.
+pub struct HeapAllocator {
+    inner: SpinLockedAllocator,
+    heap_end: AtomicUsize,
+    allocated_bytes: AtomicUsize,
+    allocated_chunks: AtomicUsize,
+    // End of the highest allocation so far. The heap above it has never been handed out.
+    high_water: AtomicUsize,
+}
+
+impl HeapAllocator {
+    pub const fn empty() -> Self {
+        HeapAllocator {
+            inner: SpinLockedAllocator::empty(),
+            heap_end: AtomicUsize::new(0),
+            allocated_bytes: AtomicUsize::new(0),
+            allocated_chunks: AtomicUsize::new(0),
+            high_water: AtomicUsize::new(0),
+        }
+    }
+
+    pub unsafe fn init(&self, heap_start: usize, heap_size: usize) {
+        self.inner.init(heap_start, heap_size);
+        self.heap_end.store(heap_start + heap_size, Ordering::Relaxed);
+        self.high_water.store(heap_start, Ordering::Relaxed);
+    }
+
+    // Bytes and allocations currently live.
+    pub fn allocated(&self) -> (usize, usize) {
+        (
+            self.allocated_bytes.load(Ordering::Relaxed),
+            self.allocated_chunks.load(Ordering::Relaxed),
+        )
+    }
+
+    // Bytes between the highest allocation and the end of the heap. They form one free block.
+    pub fn untouched(&self) -> usize {
+        self.heap_end
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.high_water.load(Ordering::Relaxed))
+    }
+
+    fn handed_out(&self, ptr: NonNull<[u8]>) {
+        let end = ptr.as_mut_ptr() as usize + ptr.len();
+        self.high_water.fetch_max(end, Ordering::Relaxed);
+    }
+}
+
+unsafe impl Allocator for HeapAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = self.inner.allocate(layout)?;
+        self.allocated_bytes.fetch_add(layout.size(), Ordering::Relaxed);
+        self.allocated_chunks.fetch_add(1, Ordering::Relaxed);
+        self.handed_out(ptr);
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.inner.deallocate(ptr, layout);
+        self.allocated_bytes.fetch_sub(layout.size(), Ordering::Relaxed);
+        self.allocated_chunks.fetch_sub(1, Ordering::Relaxed);
+        super::oom::memory_freed();
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = self.inner.grow(ptr, old_layout, new_layout)?;
+        self.allocated_bytes
+            .fetch_add(new_layout.size() - old_layout.size(), Ordering::Relaxed);
+        self.handed_out(ptr);
+        Ok(ptr)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = self.inner.shrink(ptr, old_layout, new_layout)?;
+        self.allocated_bytes
+            .fetch_sub(old_layout.size() - new_layout.size(), Ordering::Relaxed);
+        self.handed_out(ptr);
+        super::oom::memory_freed();
+        Ok(ptr)
+    }
+}
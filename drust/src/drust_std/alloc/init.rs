@@ -1,23 +1,33 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
 use futures::{future, prelude::*};
 use tarpc::{
     client,
     server::{self, incoming::Incoming, Channel},
-    tokio_serde::formats::Json,
 };
-use tokio::{runtime::Runtime, time::sleep};
+use tokio::{
+    runtime::{Handle, Runtime},
+    time::sleep,
+};
 
 use super::*;
 use crate::{
     conf::*,
     dprintln,
-    drust_std::{alloc::INIT, connect::dsafepoint::set_ready, sync::dmutex::LOCAL_MUTEX_CACHE},
+    drust_std::{
+        alloc::INIT,
+        connect::codec,
+        connect::bootstrap::{bootstrap_failed, connect_transport, phase_timeout, set_phase, Phase},
+        sync::dmutex::LOCAL_MUTEX_CACHE,
+    },
 };
 
 pub async fn start_alloc_server(server_addr: SocketAddr) -> anyhow::Result<()> {
-    let mut listener = tarpc::serde_transport::tcp::listen(&server_addr, Json::default).await?;
-    listener.config_mut().max_frame_length(usize::MAX);
+    let listener = codec::listen(server_addr).await?;
+    set_phase(Phase::AllocServerUp);
     listener
         // Ignore accept errors.
         .filter_map(|r| future::ready(r.ok()))
@@ -38,14 +48,16 @@ pub async fn start_alloc_server(server_addr: SocketAddr) -> anyhow::Result<()> {
 }
 
 pub fn init_heap(server_addr: SocketAddr) {
-    let heap_start = GLOBAL_HEAP_START + unsafe { SERVER_INDEX } * WORKER_UNIT_SIZE;
-    let buffer_size = WORKER_UNIT_SIZE;
+    let heap_start = unsafe { GLOBAL_HEAP_START + SERVER_INDEX * WORKER_UNIT_SIZE };
+    let buffer_size = unsafe { WORKER_UNIT_SIZE };
     dprintln!("heap_start: {:x}", heap_start);
     unsafe {
         LOCAL_ALLOCATOR.init(heap_start, buffer_size);
     }
+    crate::drust_std::primitives::init_staging();
+    crate::drust_std::primitives::registry::init();
     dprintln!("local allocator: {:x}", unsafe {
-        &LOCAL_ALLOCATOR as *const _ as usize
+        local_allocator() as *const _ as usize
     });
     Runtime::new()
         .unwrap()
@@ -53,26 +65,26 @@ pub fn init_heap(server_addr: SocketAddr) {
         .unwrap();
 }
 
-pub async fn init_connections(alloc_server_addrs: [SocketAddr; NUM_SERVERS]) {
+pub async fn init_connections(alloc_server_addrs: Vec<SocketAddr>) {
     dprintln!("init connections");
+    reclaim::set_runtime(Handle::current());
     let alloc_server_index = unsafe { SERVER_INDEX };
+    let deadline = Instant::now() + phase_timeout(Phase::AllocClientsConnected);
     let mut clients = Vec::new();
-    for i in 0..NUM_SERVERS {
+    for i in 0..alloc_server_addrs.len() {
         dprintln!("Connecting to server {}", i);
-        let mut transport =
-            tarpc::serde_transport::tcp::connect(&alloc_server_addrs[i], Json::default);
-        transport.config_mut().max_frame_length(usize::MAX);
-        // let fut_transport = Runtime::new().unwrap().block_on(transport).expect("failed to connect");
-        let fut_transport = transport.await.expect("failed to connect");
+        let fut_transport = connect_transport(alloc_server_addrs[i], "alloc server", i, deadline)
+            .await
+            .unwrap_or_else(|e| bootstrap_failed(e));
         let client =
             Arc::new(DAllocatorClient::new(client::Config::default(), fut_transport).spawn());
         clients.push(client);
     }
 
     let mut remote_allocators = Vec::new();
-    for i in 0..NUM_SERVERS {
+    for i in 0..alloc_server_addrs.len() {
         let aref = if i == alloc_server_index {
-            unsafe { &LOCAL_ALLOCATOR as *const _ as usize }
+            unsafe { local_allocator() as *const _ as usize }
         } else {
             let client_ref = Arc::clone(&clients[i]);
             std::thread::spawn(move || {
@@ -98,7 +110,10 @@ pub async fn init_connections(alloc_server_addrs: [SocketAddr; NUM_SERVERS]) {
 
     // Testing the allocator at initilization
     let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[0]);
-    let (addr, allocated_size) = client.rallocate(context::current(), 32, 8).await.unwrap();
+    let (addr, allocated_size) = client
+        .rallocate(context::current(), unsafe { SERVER_INDEX }, 32, 8)
+        .await
+        .unwrap();
     dprintln!("addr: {}, allocated_size: {}", addr, allocated_size);
     let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[0]);
     client
@@ -111,9 +126,10 @@ pub async fn init_connections(alloc_server_addrs: [SocketAddr; NUM_SERVERS]) {
         allocated_size
     );
 
-    set_ready(1);
+    set_phase(Phase::AllocClientsConnected);
 
     loop {
-        sleep(Duration::from_secs(1)).await;
+        sleep(reclaim::FLUSH_INTERVAL).await;
+        reclaim::flush_all().await;
     }
 }
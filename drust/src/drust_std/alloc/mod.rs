@@ -2,7 +2,8 @@ use std::{
     alloc::{Allocator, Layout},
     net::SocketAddr,
     ptr::{self, NonNull},
-    sync::{Arc, Once},
+    collections::BTreeMap,
+    sync::{Arc, Mutex, Once},
 };
 
 use dashmap::DashMap;
@@ -12,18 +13,35 @@ use tokio::runtime::Runtime;
 use crate::{
     conf::*,
     dprintln,
-    drust_std::alloc::init::start_alloc_server,
-    spec::{drop_vec_with_id, from_id_to_type},
+    drust_std::sim,
+    spec::{drop_vec_with_id_in, from_id_to_type_in},
 };
 
+pub mod heap;
+#[allow(unused, clippy::all)]
 pub mod init;
+pub mod oom;
+pub mod placement;
+pub mod reclaim;
+pub mod slab;
+pub mod stats;
 
-pub static mut LOCAL_ALLOCATOR: good_memory_allocator::SpinLockedAllocator =
-    good_memory_allocator::SpinLockedAllocator::empty();
-static mut DALLOCTOR: Option<Vec<Arc<DAllocatorClient>>> = None;
+pub use heap::HeapAllocator;
+use reclaim::Reclaim;
+pub use oom::{local_allocate, try_allocate, AllocError};
+pub use placement::{allocate_on, Placement};
+pub use stats::{stats, HeapStats};
+
+pub static mut LOCAL_ALLOCATOR: HeapAllocator = HeapAllocator::empty();
+pub(crate) static mut DALLOCTOR: Option<Vec<Arc<DAllocatorClient>>> = None;
+// One allocator per virtual server when running as a simulated cluster.
+pub(crate) static mut SIM_ALLOCATORS: Option<Vec<HeapAllocator>> = None;
 pub static mut REMOTE_ALLOCATORS: Option<Vec<usize>> = None;
 static INIT: Once = Once::new();
 
+// Objects peers allocated on this server outside slabs: addr -> (size, requester).
+static DIRECT: Mutex<BTreeMap<usize, (usize, usize)>> = Mutex::new(BTreeMap::new());
+
 pub static mut REF_MAP: Option<Arc<DashMap<usize, (usize, usize)>>> = None;
 
 // pub static mut RDROP_CNT: std::sync::Mutex<usize> = std::sync::Mutex::new(0);
@@ -31,12 +49,16 @@ pub static mut REF_MAP: Option<Arc<DashMap<usize, (usize, usize)>>> = None;
 #[tarpc::service]
 pub trait DAllocator {
     /// Returns a greeting for name.
-    async fn rallocate(size: usize, alignment: usize) -> (usize, usize);
+    async fn rallocate(requester: usize, size: usize, alignment: usize) -> (usize, usize);
     async fn rdeallocate(addr: usize, size: usize, alignment: usize);
     async fn rdrop(addr: usize, type_id: usize);
     async fn get_allocator() -> usize;
     async fn rdrop_vec(addr: usize, capacity: usize, len: usize, type_id: usize);
     async fn rupdate(owner_addr: usize, data_addr: usize);
+    async fn rlease_slab(lessee: usize, size: usize) -> usize;
+    async fn rreturn_slabs(starts: Vec<usize>);
+    async fn rreclaim_batch(ops: Vec<Reclaim>);
+    async fn heap_stats() -> HeapStats;
 }
 
 #[derive(Clone)]
@@ -44,43 +66,23 @@ struct DAllocServer(SocketAddr);
 
 #[tarpc::server]
 impl DAllocator for DAllocServer {
-    async fn rallocate(self, _: context::Context, size: usize, alignment: usize) -> (usize, usize) {
-        // println!("allocate size: {}, alignment: {}", size, alignment);
-        let layout = std::alloc::Layout::from_size_align(size, alignment).unwrap();
-        let alloc_ptr = unsafe { LOCAL_ALLOCATOR.allocate(layout) };
-        match alloc_ptr {
-            Ok(ptr) => {
-                let size = ptr.len();
-                let addr = ptr.as_mut_ptr() as usize;
-                (addr, size)
-            }
-            Err(_e) => (0, 0),
-        }
+    async fn rallocate(
+        self,
+        _: context::Context,
+        requester: usize,
+        size: usize,
+        alignment: usize,
+    ) -> (usize, usize) {
+        local_rallocate_for(requester, size, alignment)
     }
     async fn rdeallocate(self, _: context::Context, addr: usize, size: usize, alignment: usize) {
-        // unsafe {
-        //     let mut cnt = RDROP_CNT.lock().unwrap();
-        //     *cnt += 1;
-        //     if *cnt % 100 == 0 {
-        //         println!("rdrop cnt: {}", *cnt);
-        //     }
-        // }
-        dprintln!(
-            "deallocate addr: {}, size: {}, alignment: {}",
-            addr,
-            size,
-            alignment
-        );
-        let layout = std::alloc::Layout::from_size_align(size, alignment).unwrap();
-        unsafe { LOCAL_ALLOCATOR.deallocate(NonNull::new_unchecked(addr as *mut u8), layout) };
+        local_rdeallocate(addr, size, alignment);
     }
     async fn rdrop(self, _: context::Context, addr: usize, type_id: usize) {
-        dprintln!("drop addr: {}, type_id: {}", addr, type_id);
-        let obj = from_id_to_type(type_id as u32, addr);
-        drop(obj);
+        local_rdrop(addr, type_id);
     }
     async fn get_allocator(self, _: context::Context) -> usize {
-        unsafe { &LOCAL_ALLOCATOR as *const _ as usize }
+        local_allocator() as *const _ as usize
     }
     async fn rdrop_vec(
         self,
@@ -90,42 +92,151 @@ impl DAllocator for DAllocServer {
         len: usize,
         type_id: usize,
     ) {
-        dprintln!("drop vec addr: {}, type_id: {}", addr, type_id);
-        drop_vec_with_id(type_id as u32, addr, capacity, len);
+        local_rdrop_vec(addr, capacity, len, type_id);
     }
 
     async fn rupdate(self, _: context::Context, owner_addr: usize, data_addr: usize) {
-        unsafe {
-            let new_box = Some(Box::from_raw_in(data_addr as *mut u8, &LOCAL_ALLOCATOR,));
-            ptr::write_volatile(owner_addr as *mut Option<Box<u8, &good_memory_allocator::SpinLockedAllocator<20, 8>>>, new_box);
+        local_rupdate(owner_addr, data_addr);
+    }
+
+    async fn rlease_slab(self, _: context::Context, lessee: usize, size: usize) -> usize {
+        slab::local_rlease_slab(lessee, size)
+    }
+
+    async fn rreturn_slabs(self, _: context::Context, starts: Vec<usize>) {
+        slab::local_rreturn_slabs(starts);
+    }
+
+    async fn rreclaim_batch(self, _: context::Context, ops: Vec<Reclaim>) {
+        reclaim::local_reclaim(ops);
+    }
+
+    async fn heap_stats(self, _: context::Context) -> HeapStats {
+        stats()
+    }
+}
+
+// Handlers of the DAllocator service, shared by the tarpc server and the simulated cluster.
+pub(crate) fn local_rallocate(size: usize, alignment: usize) -> (usize, usize) {
+    // println!("allocate size: {}, alignment: {}", size, alignment);
+    let layout = std::alloc::Layout::from_size_align(size, alignment).unwrap();
+    let alloc_ptr = local_allocator().allocate(layout);
+    match alloc_ptr {
+        Ok(ptr) => {
+            let size = ptr.len();
+            let addr = ptr.as_mut_ptr() as usize;
+            (addr, size)
         }
+        Err(_e) => (0, 0),
     }
 }
 
-pub unsafe fn get_remote_allocator(
-    index: usize,
-) -> &'static good_memory_allocator::SpinLockedAllocator {
-    &*(REMOTE_ALLOCATORS.as_ref().unwrap()[index]
-        as *const good_memory_allocator::SpinLockedAllocator)
+// Allocation for a peer outside its slabs. It is tracked until freed, for the peer's stats.
+pub(crate) fn local_rallocate_for(requester: usize, size: usize, alignment: usize) -> (usize, usize) {
+    let (addr, allocated_size) = local_rallocate(size, alignment);
+    if allocated_size >= size && requester != unsafe { SERVER_INDEX } {
+        DIRECT.lock().unwrap().insert(addr, (size, requester));
+    }
+    (addr, allocated_size)
 }
 
-#[derive(Debug, Clone)]
-pub struct AllocError;
+// Bytes and objects `requester` allocated on this server outside slabs.
+pub(crate) fn direct_for(requester: usize) -> (usize, usize) {
+    DIRECT
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|(_, allocated_by)| *allocated_by == requester)
+        .fold((0, 0), |(bytes, objects), (size, _)| (bytes + size, objects + 1))
+}
+
+pub(crate) fn local_rdeallocate(addr: usize, size: usize, alignment: usize) {
+    // unsafe {
+    //     let mut cnt = RDROP_CNT.lock().unwrap();
+    //     *cnt += 1;
+    //     if *cnt % 100 == 0 {
+    //         println!("rdrop cnt: {}", *cnt);
+    //     }
+    // }
+    dprintln!(
+        "deallocate addr: {}, size: {}, alignment: {}",
+        addr,
+        size,
+        alignment
+    );
+    local_free(addr, size, alignment);
+}
+
+pub(crate) fn local_rdrop(addr: usize, type_id: usize) {
+    dprintln!("drop addr: {}, type_id: {}", addr, type_id);
+    drop(from_id_to_type_in(type_id as u32, addr, LocalFree));
+}
+
+pub(crate) fn local_rdrop_vec(addr: usize, capacity: usize, len: usize, type_id: usize) {
+    dprintln!("drop vec addr: {}, type_id: {}", addr, type_id);
+    drop_vec_with_id_in(type_id as u32, addr, capacity, len, LocalFree);
+}
+
+pub(crate) fn local_rupdate(owner_addr: usize, data_addr: usize) {
+    // The owner's previous data was already freed when the DMut took its copy. The allocator in
+    // the new box is never used to free it: DBox::drop frees local data through local_free.
+    unsafe {
+        let new_box = Some(Box::from_raw_in(data_addr as *mut u8, local_allocator(),));
+        ptr::write_volatile(owner_addr as *mut Option<Box<u8, &HeapAllocator>>, new_box);
+    }
+}
+
+// Frees memory of this server's heap. Every local free goes through here: memory in a slab that
+// another server leased from us was sub-allocated by that lessee, so it goes back to the lessee
+// instead of to our allocator.
+pub(crate) fn local_free(addr: usize, size: usize, alignment: usize) {
+    if let Some(lessee) = slab::lessee_of(addr) {
+        slab::forward_free(lessee, addr, size);
+        return;
+    }
+    DIRECT.lock().unwrap().remove(&addr);
+    let layout = Layout::from_size_align(size, alignment).unwrap();
+    unsafe { local_allocator().deallocate(NonNull::new_unchecked(addr as *mut u8), layout) };
+}
+
+// Allocator for dropping objects that live on this server, e.g. Box::from_raw_in(ptr, LocalFree).
+// Frees go through local_free.
+#[derive(Clone, Copy)]
+pub(crate) struct LocalFree;
+
+unsafe impl Allocator for LocalFree {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, std::alloc::AllocError> {
+        local_allocator().allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        local_free(ptr.as_ptr() as usize, layout.size(), layout.align());
+    }
+}
+
+pub fn local_allocator() -> &'static HeapAllocator {
+    unsafe {
+        match SIM_ALLOCATORS.as_ref() {
+            Some(allocators) => &allocators[SERVER_INDEX],
+            None => &LOCAL_ALLOCATOR,
+        }
+    }
+}
+
+pub unsafe fn get_remote_allocator(index: usize) -> &'static HeapAllocator {
+    &*(REMOTE_ALLOCATORS.as_ref().unwrap()[index] as *const HeapAllocator)
+}
 
 pub fn dallocate(layout: Layout, server_idx: usize) -> Result<NonNull<[u8]>, AllocError> {
     let size = layout.size();
     let alignment = layout.align();
-    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
-    let (addr, allocated_size) = std::thread::spawn(move || {
-        Runtime::new()
-            .unwrap()
-            .block_on(client.rallocate(context::current(), size, alignment))
-    })
-    .join()
-    .unwrap()
-    .unwrap();
+    let (addr, allocated_size) = if slab::fits(layout) {
+        slab::allocate(layout, server_idx).map_or((0, 0), |addr| (addr, size))
+    } else {
+        rallocate_blocking(size, alignment, server_idx)
+    };
     if allocated_size < size {
-        return Err(AllocError);
+        Err(AllocError::new(layout, server_idx))
     } else {
         unsafe {
             Ok(NonNull::slice_from_raw_parts(
@@ -136,77 +247,60 @@ pub fn dallocate(layout: Layout, server_idx: usize) -> Result<NonNull<[u8]>, All
     }
 }
 
+fn rallocate_blocking(size: usize, alignment: usize, server_idx: usize) -> (usize, usize) {
+    let requester = unsafe { SERVER_INDEX };
+    if sim::is_active() {
+        return sim::on_server(server_idx, || local_rallocate_for(requester, size, alignment));
+    }
+    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
+    std::thread::spawn(move || {
+        Runtime::new()
+            .unwrap()
+            .block_on(client.rallocate(context::current(), requester, size, alignment))
+    })
+    .join()
+    .unwrap()
+    .unwrap()
+}
+
 pub fn ddeallocate(ptr: NonNull<u8>, layout: Layout, server_idx: usize) {
     let size = layout.size();
     let alignment = layout.align();
-    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
-    // Runtime::new().unwrap().block_on(client.rdeallocate(context::current(), ptr.as_ptr() as usize, size, alignment)).unwrap();
-    let ptr_raw = ptr.as_ptr() as usize;
-    tokio::spawn(async move {
-        client
-            .rdeallocate(context::current(), ptr_raw, size, alignment)
-            .await
-            .unwrap();
-    });
-    // std::thread::spawn(move || {
-    //     Runtime::new().unwrap().block_on(client.rdeallocate(context::current(), ptr_raw, size, alignment))
-    // });
+    let addr = ptr.as_ptr() as usize;
+    // Memory in our own slabs is freed without asking the owner.
+    if slab::release(server_idx, &[(addr, size)]) {
+        return;
+    }
+    if sim::is_active() {
+        sim::on_server(server_idx, || local_rdeallocate(addr, size, alignment));
+        return;
+    }
+    reclaim::enqueue(server_idx, Reclaim::Deallocate { addr, size, alignment });
 }
 
 pub fn ddrop(ptr: NonNull<u8>, type_id: usize, server_idx: usize) {
     dprintln!("Dropping remote by dispatch remote drop call");
-    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
-    let ptr_raw = ptr.as_ptr() as usize;
-    tokio::spawn(async move {
-        client
-            .rdrop(context::current(), ptr_raw, type_id)
-            .await
-            .unwrap();
-    });
-    // std::thread::spawn(move || {
-    //     Runtime::new().unwrap().block_on(client.rdrop(context::current(), ptr_raw, type_id))
-    // });
+    let addr = ptr.as_ptr() as usize;
+    if sim::is_active() {
+        sim::on_server(server_idx, || local_rdrop(addr, type_id));
+        return;
+    }
+    reclaim::enqueue(server_idx, Reclaim::Drop { addr, type_id });
 }
 
 pub fn ddrop_vec(ptr: NonNull<u8>, type_id: usize, capacity: usize, len: usize, server_idx: usize) {
-    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
-    // Runtime::new().unwrap().block_on(client.rdrop_vec(context::current(), ptr.as_ptr() as usize, capacity, len, type_id)).unwrap();
-    let ptr_raw = ptr.as_ptr() as usize;
-    tokio::spawn(async move {
-        client
-            .rdrop_vec(context::current(), ptr_raw, capacity, len, type_id)
-            .await
-            .unwrap();
-    });
-    // std::thread::spawn(move || {
-    //     Runtime::new().unwrap().block_on(client.rdrop_vec(context::current(), ptr_raw, capacity, len, type_id))
-    // });
+    let addr = ptr.as_ptr() as usize;
+    if sim::is_active() {
+        sim::on_server(server_idx, || local_rdrop_vec(addr, capacity, len, type_id));
+        return;
+    }
+    reclaim::enqueue(server_idx, Reclaim::DropVec { addr, capacity, len, type_id });
 }
 
 pub fn dupdate(owner_addr: usize, data_addr: usize, server_idx: usize) {
-    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
-    let owner_addr = owner_addr as usize;
-    let data_addr = data_addr as usize;
-    tokio::spawn(async move {
-        client
-            .rupdate(context::current(), owner_addr, data_addr)
-            .await
-            .unwrap();
-    });
-}
-
-pub fn init_heap(server_addr: SocketAddr) {
-    let heap_start = GLOBAL_HEAP_START + unsafe { SERVER_INDEX } * WORKER_UNIT_SIZE;
-    let buffer_size = WORKER_UNIT_SIZE;
-    dprintln!("heap_start: {:x}", heap_start);
-    unsafe {
-        LOCAL_ALLOCATOR.init(heap_start, buffer_size);
+    if sim::is_active() {
+        sim::on_server(server_idx, || local_rupdate(owner_addr, data_addr));
+        return;
     }
-    dprintln!("local allocator: {:x}", unsafe {
-        &LOCAL_ALLOCATOR as *const _ as usize
-    });
-    Runtime::new()
-        .unwrap()
-        .block_on(start_alloc_server(server_addr))
-        .unwrap();
+    reclaim::enqueue(server_idx, Reclaim::Update { owner_addr, data_addr });
 }
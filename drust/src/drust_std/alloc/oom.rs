@@ -0,0 +1,168 @@
+use std::{
+    alloc::{Allocator, Layout},
+    fmt,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    task::block_in_place,
+};
+
+use super::{dallocate, local_allocator, placement, reclaim, stats};
+use crate::conf::*;
+
+pub const DEFAULT_OOM_WAIT_MS: u64 = 10_000;
+
+// What an allocation does when the local region is exhausted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OomPolicy {
+    // Report AllocError right away.
+    #[default]
+    Fail,
+    // Place the object on the peer with the most free memory. Only for allocations that may
+    // live remotely, such as DBox::try_new and DVec::try_with_capacity.
+    Spill,
+    // Retry until remote drops free enough memory, for at most oom_wait_ms. Inside a
+    // current-thread runtime there is no other worker to run the frees, so it fails right away.
+    Wait,
+}
+
+pub static mut OOM_POLICY: OomPolicy = OomPolicy::Fail;
+pub static mut OOM_WAIT: Duration = Duration::from_millis(DEFAULT_OOM_WAIT_MS);
+
+// Allocations waiting for memory, and the condvar the frees wake them with. A waiter holds FREED
+// from its allocation attempt until it sleeps, so a free in between cannot be missed.
+static WAITERS: AtomicUsize = AtomicUsize::new(0);
+static FREED: Mutex<()> = Mutex::new(());
+static FREED_CONDVAR: Condvar = Condvar::new();
+
+// Called after every free of heap memory. Only takes the lock while an allocation waits.
+pub(crate) fn memory_freed() {
+    if WAITERS.load(Ordering::SeqCst) > 0 {
+        let _freed = FREED.lock().unwrap();
+        FREED_CONDVAR.notify_all();
+    }
+}
+
+struct Waiting;
+
+impl Waiting {
+    fn start() -> Self {
+        WAITERS.fetch_add(1, Ordering::SeqCst);
+        Waiting
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        WAITERS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocError {
+    pub server_idx: usize,
+    pub size: usize,
+    pub align: usize,
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "out of memory on server {}: cannot allocate {} bytes (align {})",
+            self.server_idx, self.size, self.align
+        )
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+impl AllocError {
+    pub fn new(layout: Layout, server_idx: usize) -> Self {
+        AllocError {
+            server_idx,
+            size: layout.size(),
+            align: layout.align(),
+        }
+    }
+}
+
+fn try_local(layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    local_allocator()
+        .allocate(layout)
+        .map_err(|_| AllocError::new(layout, unsafe { SERVER_INDEX }))
+}
+
+// Allocates on this server, waiting for memory when the policy is Wait. The wait sleeps until a
+// free on this server wakes it. On a runtime worker it runs under block_in_place, so the worker's
+// other tasks, such as reclaim batches, move to another thread meanwhile.
+pub fn try_local_allocate(layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    let result = try_local(layout);
+    if result.is_ok() || unsafe { OOM_POLICY } != OomPolicy::Wait {
+        return result;
+    }
+    match Handle::try_current() {
+        Err(_) => wait_for(layout),
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            block_in_place(|| wait_for(layout))
+        }
+        Ok(_) => result,
+    }
+}
+
+fn wait_for(layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    let _waiting = Waiting::start();
+    // Frees queued here can be what a peer waits for before it frees memory on this server.
+    reclaim::flush_all_blocking();
+    let deadline = Instant::now() + unsafe { OOM_WAIT };
+    let mut freed = FREED.lock().unwrap();
+    loop {
+        let result = try_local(layout);
+        let now = Instant::now();
+        if result.is_ok() || now >= deadline {
+            return result;
+        }
+        freed = FREED_CONDVAR.wait_timeout(freed, deadline - now).unwrap().0;
+    }
+}
+
+// For memory that has to be on this server, like local copies of remote objects.
+pub fn local_allocate(layout: Layout) -> NonNull<[u8]> {
+    try_local_allocate(layout).unwrap_or_else(|e| oom(e))
+}
+
+// Allocates on this server, or on a peer if the policy is Spill. The result may be remote.
+pub fn try_allocate(layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    let result = try_local_allocate(layout);
+    if result.is_ok() || unsafe { OOM_POLICY } != OomPolicy::Spill {
+        return result;
+    }
+    match placement::roomiest(layout.size(), Some(unsafe { SERVER_INDEX })) {
+        Some(server_idx) => dallocate(layout, server_idx),
+        None => result,
+    }
+}
+
+pub fn oom(e: AllocError) -> ! {
+    if e.server_idx != unsafe { SERVER_INDEX } {
+        panic!("{}", e);
+    }
+    let local = stats::stats();
+    panic!(
+        "{}; {} of {} bytes free, {} never used (oom_policy {:?})",
+        e,
+        local.free_bytes,
+        local.heap_size,
+        local.untouched_tail,
+        unsafe { OOM_POLICY }
+    );
+}
@@ -0,0 +1,107 @@
+use std::{
+    alloc::Layout,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::runtime::Runtime;
+
+use super::{dallocate, oom::try_local_allocate, stats, AllocError, HeapStats};
+use crate::{
+    conf::*,
+    drust_std::primitives::{current_place, Destination},
+};
+
+// Which server a new object is allocated on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    Local,
+    On(usize),
+    // Cycles through all servers, starting after this one.
+    RoundRobin,
+    // The server with the most free memory. Uses heap stats of every server that are at most
+    // STATS_TTL old, so prefer RoundRobin when placing many small objects.
+    LeastLoaded,
+    // The server holding the object at this address, e.g. Placement::Near(dbox.get_addr()).
+    Near(usize),
+}
+
+impl From<usize> for Placement {
+    fn from(server_idx: usize) -> Self {
+        Placement::On(server_idx)
+    }
+}
+
+static ROUND_ROBIN: AtomicUsize = AtomicUsize::new(1);
+
+// Cluster stats are reused for this long, so a burst of spilled or LeastLoaded allocations costs
+// one heap_stats call per server instead of one per allocation.
+const STATS_TTL: Duration = Duration::from_millis(100);
+static CLUSTER_STATS: Mutex<Option<(Instant, Vec<HeapStats>)>> = Mutex::new(None);
+static REFRESHING: AtomicBool = AtomicBool::new(false);
+
+impl Placement {
+    // Picks the server for an allocation of `layout`.
+    pub fn server_idx(self, layout: Layout) -> usize {
+        let (server_idx, num_servers) = unsafe { (SERVER_INDEX, NUM_SERVERS) };
+        match self {
+            Placement::Local => server_idx,
+            Placement::On(idx) => {
+                assert!(idx < num_servers, "no server {} in a cluster of {}", idx, num_servers);
+                idx
+            }
+            Placement::RoundRobin => {
+                (server_idx + ROUND_ROBIN.fetch_add(1, Ordering::Relaxed)) % num_servers
+            }
+            Placement::LeastLoaded => roomiest(layout.size(), None).unwrap_or(server_idx),
+            Placement::Near(addr) => match current_place(addr) {
+                Destination::Local => server_idx,
+                Destination::Remote(idx) => idx,
+            },
+        }
+    }
+}
+
+// Allocates `layout` on the server picked by `placement`. The result may be remote.
+pub fn allocate_on(layout: Layout, placement: Placement) -> Result<NonNull<[u8]>, AllocError> {
+    let server_idx = placement.server_idx(layout);
+    if server_idx == unsafe { SERVER_INDEX } {
+        try_local_allocate(layout)
+    } else {
+        dallocate(layout, server_idx)
+    }
+}
+
+// The server with the most free memory, at least `size`, leaving out `skip`. The lock is only
+// held to read and update the cached stats. When they are stale one caller asks every server
+// again, while the others keep using the old stats.
+pub(crate) fn roomiest(size: usize, skip: Option<usize>) -> Option<usize> {
+    let (cached, fresh) = match CLUSTER_STATS.lock().unwrap().as_ref() {
+        Some((at, _)) => (true, at.elapsed() < STATS_TTL),
+        None => (false, false),
+    };
+    let refreshing = !fresh && !REFRESHING.swap(true, Ordering::AcqRel);
+    if refreshing || !cached {
+        let cluster =
+            std::thread::spawn(|| Runtime::new().unwrap().block_on(stats::cluster_stats()))
+                .join()
+                .unwrap();
+        if refreshing {
+            REFRESHING.store(false, Ordering::Release);
+        }
+        *CLUSTER_STATS.lock().unwrap() = Some((Instant::now(), cluster.ok()?));
+    }
+    let mut cached = CLUSTER_STATS.lock().unwrap();
+    let (_, cluster) = cached.as_mut()?;
+    let roomiest = cluster
+        .iter_mut()
+        .filter(|s| Some(s.server_idx) != skip && s.free_bytes >= size)
+        .max_by_key(|s| s.free_bytes)?;
+    // Count the allocation, so the next ones before the refresh spread out.
+    roomiest.free_bytes -= size;
+    Some(roomiest.server_idx)
+}
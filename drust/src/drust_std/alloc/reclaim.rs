@@ -0,0 +1,201 @@
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, OnceLock, RwLock,
+    },
+    time::Duration,
+};
+
+use futures::future;
+use serde::{Deserialize, Serialize};
+use tarpc::context;
+use tokio::{
+    runtime::{Handle, Runtime},
+    sync::Mutex as SendLock,
+};
+
+use super::{
+    local_rdeallocate, local_rdrop, local_rdrop_vec, local_rupdate, slab, DALLOCTOR,
+};
+use crate::{conf::*, dprintln, drust_std::sim};
+
+// Frees, drops and owner updates aimed at another server are queued per destination and sent in
+// one rreclaim_batch call. A queue is flushed once it holds FLUSH_BATCH ops, every
+// FLUSH_INTERVAL by the alloc runtime, and at shutdown. Batches to one destination are sent one
+// at a time, each after the previous one was run, so ops never overtake each other.
+const FLUSH_BATCH: usize = 1024;
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(2);
+
+// Ops run on the destination in the order they were queued.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Reclaim {
+    Deallocate { addr: usize, size: usize, alignment: usize },
+    Drop { addr: usize, type_id: usize },
+    DropVec { addr: usize, capacity: usize, len: usize, type_id: usize },
+    Update { owner_addr: usize, data_addr: usize },
+    // Memory of a slab leased from `owner`, freed on the owner.
+    SlabFree { owner: usize, addr: usize, size: usize },
+}
+
+const NO_OPS: Mutex<Vec<Reclaim>> = Mutex::new(Vec::new());
+static QUEUES: [Mutex<Vec<Reclaim>>; MAX_SERVERS] = [NO_OPS; MAX_SERVERS];
+// Held from taking a destination's queue until the batch was delivered. The lock is fair, so
+// batches go out in the order they were taken.
+const NO_SENDER: SendLock<()> = SendLock::const_new(());
+static SENDERS: [SendLock<()>; MAX_SERVERS] = [NO_SENDER; MAX_SERVERS];
+
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+static ERROR_HANDLER: RwLock<fn(usize, usize, &str)> = RwLock::new(report_error);
+// The alloc runtime, which also runs the timer flush.
+static RUNTIME: OnceLock<Handle> = OnceLock::new();
+
+fn report_error(server_idx: usize, ops: usize, error: &str) {
+    eprintln!("drust: lost {} reclaim ops for server {}: {}", ops, server_idx, error);
+}
+
+// Called with the destination, the number of ops in the failed batch and the error.
+pub fn set_error_handler(handler: fn(usize, usize, &str)) {
+    *ERROR_HANDLER.write().unwrap() = handler;
+}
+
+pub(crate) fn set_runtime(handle: Handle) {
+    let _ = RUNTIME.set(handle);
+}
+
+// Number of batches that could not be delivered.
+pub fn error_count() -> usize {
+    ERRORS.load(Ordering::Relaxed)
+}
+
+pub fn enqueue(server_idx: usize, op: Reclaim) {
+    // Owner updates publish a new copy, so they go out right away, after the ops queued before.
+    let urgent = matches!(op, Reclaim::Update { .. });
+    let mut queue = QUEUES[server_idx].lock().unwrap();
+    queue.push(op);
+    if !urgent && queue.len() < FLUSH_BATCH {
+        return;
+    }
+    drop(queue);
+    // Without a runtime on this thread the timer flush picks the batch up.
+    let Ok(handle) = Handle::try_current() else {
+        return;
+    };
+    handle.spawn(flush(server_idx));
+}
+
+// Sends the ops queued for `server_idx` and waits until the destination ran them. The queue is
+// only taken once the previous batch to that server was delivered.
+async fn flush(server_idx: usize) {
+    let _sending = SENDERS[server_idx].lock().await;
+    let batch = mem::take(&mut *QUEUES[server_idx].lock().unwrap());
+    if !batch.is_empty() {
+        send(server_idx, batch).await;
+    }
+}
+
+async fn send(server_idx: usize, batch: Vec<Reclaim>) {
+    if sim::is_active() {
+        sim::on_server(server_idx, || local_reclaim(batch));
+        return;
+    }
+    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
+    let ops = batch.len();
+    dprintln!("sending {} reclaim ops to server {}", ops, server_idx);
+    if let Err(e) = client.rreclaim_batch(context::current(), batch).await {
+        report_failure(server_idx, ops, &e.to_string());
+    }
+}
+
+// Counts a batch that could not be delivered to `server_idx` and passes it to the handler.
+pub(crate) fn report_failure(server_idx: usize, ops: usize, error: &str) {
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+    let handler = *ERROR_HANDLER.read().unwrap();
+    handler(server_idx, ops, error);
+}
+
+// Sends every queued op and waits until the destinations ran them.
+pub async fn flush_all() {
+    future::join_all((0..unsafe { NUM_SERVERS }).map(flush)).await;
+}
+
+// flush_all for code that cannot await, also inside a runtime. The flush runs on the alloc
+// runtime; before that is up, and in the simulator, it runs on a thread of its own.
+pub fn flush_all_blocking() {
+    let Some(handle) = RUNTIME.get() else {
+        std::thread::spawn(|| Runtime::new().unwrap().block_on(flush_all()))
+            .join()
+            .unwrap();
+        return;
+    };
+    let (done, flushed) = mpsc::channel();
+    handle.spawn(async move {
+        flush_all().await;
+        let _ = done.send(());
+    });
+    // The runtime only drops the task when it shuts down, and then there is nothing to wait for.
+    let _ = flushed.recv();
+}
+
+// Drops the queued ops, for a simulation that maps a fresh heap.
+pub(crate) fn reset() {
+    for queue in &QUEUES {
+        queue.lock().unwrap().clear();
+    }
+}
+
+// Handler of rreclaim_batch on the destination.
+pub(crate) fn local_reclaim(ops: Vec<Reclaim>) {
+    for op in ops {
+        match op {
+            Reclaim::Deallocate { addr, size, alignment } => local_rdeallocate(addr, size, alignment),
+            Reclaim::Drop { addr, type_id } => local_rdrop(addr, type_id),
+            Reclaim::DropVec { addr, capacity, len, type_id } => {
+                local_rdrop_vec(addr, capacity, len, type_id)
+            }
+            Reclaim::Update { owner_addr, data_addr } => local_rupdate(owner_addr, data_addr),
+            Reclaim::SlabFree { owner, addr, size } => {
+                slab::release(owner, &[(addr, size)]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{Allocator, Layout};
+
+    use super::*;
+    use crate::drust_std::alloc::{local_allocator, HeapAllocator};
+
+    fn allocate_on(server_idx: usize, layout: Layout) -> usize {
+        let allocate = || local_allocator().allocate(layout).unwrap().as_mut_ptr() as usize;
+        sim::on_server(server_idx, allocate)
+    }
+
+    #[test]
+    fn batches_run_in_queue_order() {
+        sim::test_cluster(2).run_test(async {
+            let word = Layout::new::<usize>();
+            let owner = allocate_on(1, Layout::new::<Option<Box<u8, &HeapAllocator>>>());
+            let (first, second, garbage) =
+                (allocate_on(1, word), allocate_on(1, word), allocate_on(1, word));
+            let before = sim::on_server(1, || local_allocator().allocated());
+
+            // A free waits in the queue for a full batch or the timer.
+            let (size, alignment) = (word.size(), word.align());
+            enqueue(1, Reclaim::Deallocate { addr: garbage, size, alignment });
+            assert_eq!(QUEUES[1].lock().unwrap().len(), 1);
+            // Updates flush right away, each behind what was queued before it.
+            enqueue(1, Reclaim::Update { owner_addr: owner, data_addr: first });
+            enqueue(1, Reclaim::Update { owner_addr: owner, data_addr: second });
+            flush_all().await;
+            assert!(QUEUES[1].lock().unwrap().is_empty());
+
+            let owned = unsafe { &*(owner as *const Option<Box<u8, &HeapAllocator>>) };
+            assert_eq!(owned.as_ref().map(|data| &**data as *const u8 as usize), Some(second));
+            let after = sim::on_server(1, || local_allocator().allocated());
+            assert_eq!(after, (before.0 - word.size(), before.1 - 1));
+        });
+    }
+}
@@ -0,0 +1,465 @@
+use std::{
+    alloc::{Allocator, Layout},
+    collections::BTreeMap,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tarpc::context;
+use tokio::runtime::{Handle, Runtime};
+
+use super::{
+    local_allocator, local_rallocate,
+    reclaim::{self, Reclaim},
+    DALLOCTOR,
+};
+use crate::{conf::*, dprintln, drust_std::sim};
+
+// Remote allocations are carved out of slabs leased from the owning server, so only a lease
+// costs an RPC. The lessee keeps the free ranges of its slabs locally: the slab memory lives on
+// the owner and cannot hold allocator headers written from here.
+pub const SLAB_SIZE: usize = 64 << 20;
+const SLAB_ALIGN: usize = 4096;
+// Larger objects are allocated by the owner directly, so one big buffer does not pin a slab.
+const SLAB_MAX_OBJECT: usize = SLAB_SIZE / 8;
+const MIN_ALIGN: usize = 16;
+// Empty slabs kept per owner before the rest are handed back.
+const IDLE_SLABS: usize = 1;
+
+struct Slab {
+    size: usize,
+    live: usize,
+    objects: usize,
+    // start -> length, never crossing the slab boundary
+    free: BTreeMap<usize, usize>,
+}
+
+impl Slab {
+    fn new(start: usize, size: usize) -> Self {
+        Slab {
+            size,
+            live: 0,
+            objects: 0,
+            free: BTreeMap::from([(start, size)]),
+        }
+    }
+}
+
+struct RemoteSlabs {
+    slabs: BTreeMap<usize, Slab>,
+}
+
+impl RemoteSlabs {
+    const fn new() -> Self {
+        RemoteSlabs {
+            slabs: BTreeMap::new(),
+        }
+    }
+
+    fn allocate(&mut self, size: usize, align: usize) -> Option<usize> {
+        for slab in self.slabs.values_mut() {
+            let fit = slab.free.iter().find_map(|(&start, &len)| {
+                let addr = round_up(start, align);
+                (addr + size <= start + len).then_some((start, len, addr))
+            });
+            if let Some((start, len, addr)) = fit {
+                slab.free.remove(&start);
+                if addr > start {
+                    slab.free.insert(start, addr - start);
+                }
+                if addr + size < start + len {
+                    slab.free.insert(addr + size, start + len - addr - size);
+                }
+                slab.live += size;
+                slab.objects += 1;
+                return Some(addr);
+            }
+        }
+        None
+    }
+
+    fn slab_of(&mut self, addr: usize) -> Option<(usize, &mut Slab)> {
+        self.slabs
+            .range_mut(..=addr)
+            .next_back()
+            .filter(|(start, slab)| addr < **start + slab.size)
+            .map(|(start, slab)| (*start, slab))
+    }
+
+    fn free(&mut self, addr: usize, size: usize) -> bool {
+        let Some((_, slab)) = self.slab_of(addr) else {
+            return false;
+        };
+        let (mut start, mut len) = (addr, size);
+        if let Some((&prev, &prev_len)) = slab.free.range(..start).next_back() {
+            if prev + prev_len == start {
+                slab.free.remove(&prev);
+                start = prev;
+                len += prev_len;
+            }
+        }
+        if let Some(next_len) = slab.free.remove(&(start + len)) {
+            len += next_len;
+        }
+        slab.free.insert(start, len);
+        slab.live -= size;
+        slab.objects -= 1;
+        true
+    }
+
+    // Takes out every empty slab beyond the IDLE_SLABS kept for the next allocations.
+    fn take_idle(&mut self) -> Vec<usize> {
+        let mut idle: Vec<usize> = self
+            .slabs
+            .iter()
+            .filter(|(_, slab)| slab.live == 0)
+            .map(|(start, _)| *start)
+            .collect();
+        if idle.len() <= IDLE_SLABS {
+            return Vec::new();
+        }
+        idle.truncate(idle.len() - IDLE_SLABS);
+        for start in &idle {
+            self.slabs.remove(start);
+        }
+        idle
+    }
+}
+
+const NO_SLABS: Mutex<RemoteSlabs> = Mutex::new(RemoteSlabs::new());
+const NO_OWNERS: [Mutex<RemoteSlabs>; MAX_SERVERS] = [NO_SLABS; MAX_SERVERS];
+// Slabs leased by each server, indexed by the lessee and then the owner. A real server only
+// uses its own row; the simulator runs every server in this process.
+static SLABS: [[Mutex<RemoteSlabs>; MAX_SERVERS]; MAX_SERVERS] = [NO_OWNERS; MAX_SERVERS];
+
+// Slabs of this server leased by others: start -> (size, lessee). Every local free looks its
+// address up here, so the table is sharded by SLAB_SIZE granule and a lease is filed in the
+// shard of each granule it overlaps. While nothing is leased, lookups take no lock at all.
+const LEASE_SHARDS: usize = 64;
+type Leases = BTreeMap<usize, (usize, usize)>;
+const NO_LEASES: Mutex<Leases> = Mutex::new(BTreeMap::new());
+static LEASES: [Mutex<Leases>; LEASE_SHARDS] = [NO_LEASES; LEASE_SHARDS];
+static LEASE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn slabs_from(owner: usize) -> &'static Mutex<RemoteSlabs> {
+    &SLABS[unsafe { SERVER_INDEX }][owner]
+}
+
+fn shard_of(addr: usize) -> usize {
+    addr / SLAB_SIZE % LEASE_SHARDS
+}
+
+// The shards a lease is filed in, starting with the one of its first granule.
+fn lease_shards(start: usize, size: usize) -> impl Iterator<Item = usize> {
+    let first = start / SLAB_SIZE;
+    let last = (start + size.max(1) - 1) / SLAB_SIZE;
+    (first..=last.min(first + LEASE_SHARDS - 1)).map(|granule| granule % LEASE_SHARDS)
+}
+
+fn round_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+fn slab_size(layout: Layout) -> usize {
+    round_up(layout.size().max(1), MIN_ALIGN)
+}
+
+pub fn fits(layout: Layout) -> bool {
+    layout.size() <= SLAB_MAX_OBJECT && layout.align() <= SLAB_ALIGN
+}
+
+// Allocates `layout` on `server_idx` from a leased slab, leasing a new one when all are full.
+// The lease runs without the lock, so frees and other allocations for that server go on
+// meanwhile; threads that lease at the same time each add a slab.
+pub fn allocate(layout: Layout, server_idx: usize) -> Option<usize> {
+    let size = slab_size(layout);
+    let align = layout.align().max(MIN_ALIGN);
+    if let Some(addr) = slabs_from(server_idx).lock().unwrap().allocate(size, align) {
+        return Some(addr);
+    }
+    let start = lease_blocking(server_idx);
+    if start == 0 {
+        return None;
+    }
+    dprintln!("leased slab {:x} on server {}", start, server_idx);
+    let mut slabs = slabs_from(server_idx).lock().unwrap();
+    slabs.slabs.insert(start, Slab::new(start, SLAB_SIZE));
+    slabs.allocate(size, align)
+}
+
+fn lease_blocking(server_idx: usize) -> usize {
+    let lessee = unsafe { SERVER_INDEX };
+    if sim::is_active() {
+        return sim::on_server(server_idx, || local_rlease_slab(lessee, SLAB_SIZE));
+    }
+    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
+    std::thread::spawn(move || {
+        Runtime::new()
+            .unwrap()
+            .block_on(client.rlease_slab(context::current(), lessee, SLAB_SIZE))
+    })
+    .join()
+    .unwrap()
+    .unwrap()
+}
+
+// Frees ranges of slabs this server leased from `server_idx`. Returns false for addresses that
+// are not in one of them.
+pub fn release(server_idx: usize, ranges: &[(usize, usize)]) -> bool {
+    let mut slabs = slabs_from(server_idx).lock().unwrap();
+    let mut all = true;
+    for &(addr, size) in ranges {
+        all &= slabs.free(addr, round_up(size.max(1), MIN_ALIGN));
+    }
+    let idle = slabs.take_idle();
+    drop(slabs);
+    if !idle.is_empty() {
+        return_slabs(server_idx, idle);
+    }
+    all
+}
+
+fn return_slabs(server_idx: usize, starts: Vec<usize>) {
+    dprintln!("returning {} slabs to server {}", starts.len(), server_idx);
+    if sim::is_active() {
+        sim::on_server(server_idx, || local_rreturn_slabs(starts));
+        return;
+    }
+    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
+    let returned = starts.len();
+    let give_back = async move {
+        if let Err(e) = client.rreturn_slabs(context::current(), starts).await {
+            reclaim::report_failure(server_idx, returned, &e.to_string());
+        }
+    };
+    // release also runs on plain threads, e.g. from a Drop outside any runtime.
+    match Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn(give_back);
+        }
+        Err(_) => {
+            std::thread::spawn(move || Runtime::new().unwrap().block_on(give_back));
+        }
+    }
+}
+
+// Owner side of the DAllocator service.
+pub(crate) fn local_rlease_slab(lessee: usize, size: usize) -> usize {
+    let (addr, allocated_size) = local_rallocate(size, SLAB_ALIGN);
+    if allocated_size < size {
+        return 0;
+    }
+    for shard in lease_shards(addr, size) {
+        LEASES[shard].lock().unwrap().insert(addr, (size, lessee));
+    }
+    LEASE_COUNT.fetch_add(1, Ordering::Release);
+    addr
+}
+
+// A lessee may return a slab twice, e.g. when it retries after a lost reply. That must not take
+// the owner down, so unknown starts are logged and skipped.
+pub(crate) fn local_rreturn_slabs(starts: Vec<usize>) {
+    for start in starts {
+        let Some((size, _)) = LEASES[shard_of(start)].lock().unwrap().remove(&start) else {
+            eprintln!("drust: ignoring the return of slab {:#x}, which is not leased", start);
+            continue;
+        };
+        for shard in lease_shards(start, size).skip(1) {
+            LEASES[shard].lock().unwrap().remove(&start);
+        }
+        LEASE_COUNT.fetch_sub(1, Ordering::Release);
+        unsafe {
+            local_allocator().deallocate(
+                NonNull::new_unchecked(start as *mut u8),
+                Layout::from_size_align_unchecked(size, SLAB_ALIGN),
+            )
+        };
+    }
+}
+
+// The lessee that sub-allocates the local address `addr`, if it lies in a leased slab.
+pub(crate) fn lessee_of(addr: usize) -> Option<usize> {
+    if LEASE_COUNT.load(Ordering::Acquire) == 0 {
+        return None;
+    }
+    LEASES[shard_of(addr)]
+        .lock()
+        .unwrap()
+        .range(..=addr)
+        .next_back()
+        .filter(|(start, (size, _))| addr < **start + size)
+        .map(|(_, (_, lessee))| *lessee)
+}
+
+// Hands a range of leased memory freed on this server back to its lessee.
+pub(crate) fn forward_free(lessee: usize, addr: usize, size: usize) {
+    let owner = unsafe { SERVER_INDEX };
+    reclaim::enqueue(lessee, Reclaim::SlabFree { owner, addr, size });
+}
+
+// Bytes leased from `server_idx`, and the bytes and objects currently allocated in them.
+pub(crate) fn leased_from(server_idx: usize) -> (usize, usize, usize) {
+    let slabs = slabs_from(server_idx).lock().unwrap();
+    slabs.slabs.values().fold((0, 0, 0), |(size, live, objects), slab| {
+        (size + slab.size, live + slab.live, objects + slab.objects)
+    })
+}
+
+// Bytes of this server's heap leased by `lessee`.
+pub(crate) fn leased_to(lessee: usize) -> usize {
+    // Each lease is counted in the shard of its first granule only.
+    LEASES
+        .iter()
+        .enumerate()
+        .map(|(shard, leases)| {
+            leases
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(start, _)| shard_of(**start) == shard)
+                .filter(|(_, (_, leased_by))| *leased_by == lessee)
+                .map(|(_, (size, _))| size)
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+// Forgets all slabs and leases, for a simulation that maps a fresh heap.
+pub(crate) fn reset() {
+    for slabs in SLABS.iter().flatten() {
+        slabs.lock().unwrap().slabs.clear();
+    }
+    for leases in &LEASES {
+        leases.lock().unwrap().clear();
+    }
+    LEASE_COUNT.store(0, Ordering::Release);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drust_std::{
+        alloc::{local_free, Placement},
+        primitives::dbox::DBox,
+        sim,
+    };
+
+    // Leases a slab of `owner` for the active server.
+    fn lease(owner: usize) -> usize {
+        let lessee = unsafe { SERVER_INDEX };
+        let start = sim::on_server(owner, || local_rlease_slab(lessee, SLAB_SIZE));
+        assert_ne!(start, 0, "server {} has no room for a slab", owner);
+        start
+    }
+
+    #[test]
+    fn owner_tracks_leases_until_returned() {
+        sim::test_cluster(2).run_test(async {
+            let before = sim::on_server(1, || local_allocator().allocated());
+            let start = lease(1);
+            assert_eq!(start % SLAB_ALIGN, 0);
+            sim::on_server(1, || {
+                assert_eq!(lessee_of(start), Some(0));
+                assert_eq!(lessee_of(start + SLAB_SIZE - 1), Some(0));
+                assert_eq!(lessee_of(start + SLAB_SIZE), None);
+                assert_eq!(leased_to(0), SLAB_SIZE);
+
+                local_rreturn_slabs(vec![start]);
+                assert_eq!(lessee_of(start), None);
+                assert_eq!(leased_to(0), 0);
+                assert_eq!(local_allocator().allocated(), before);
+            });
+        });
+    }
+
+    #[test]
+    fn lessee_frees_locally_and_hands_back_idle_slabs() {
+        sim::test_cluster(2).run_test(async {
+            let mut slabs = RemoteSlabs::new();
+            let first = lease(1);
+            slabs.slabs.insert(first, Slab::new(first, SLAB_SIZE));
+            let size = slab_size(Layout::from_size_align(100, 8).unwrap());
+            let a = slabs.allocate(size, MIN_ALIGN).unwrap();
+            let b = slabs.allocate(size, MIN_ALIGN).unwrap();
+            assert_eq!((a, b), (first, first + size));
+            assert_eq!(slabs.allocate(SLAB_SIZE, MIN_ALIGN), None);
+
+            // What the first slab has no room for goes to the next one.
+            let second = lease(1);
+            slabs.slabs.insert(second, Slab::new(second, SLAB_SIZE));
+            let c = slabs.allocate(SLAB_SIZE - size, MIN_ALIGN).unwrap();
+            assert_eq!(c, second);
+
+            assert!(slabs.free(a, size));
+            assert!(slabs.free(b, size));
+            // The freed ranges merge into one again.
+            assert_eq!(slabs.slabs[&first].free, BTreeMap::from([(first, SLAB_SIZE)]));
+            // One empty slab stays for the next allocations.
+            assert!(slabs.take_idle().is_empty());
+            assert!(slabs.free(c, SLAB_SIZE - size));
+            let idle = slabs.take_idle();
+            assert_eq!(idle.len(), 1);
+            assert!(!slabs.free(idle[0], size));
+
+            let kept: Vec<usize> = slabs.slabs.keys().copied().collect();
+            sim::on_server(1, || {
+                local_rreturn_slabs(idle);
+                local_rreturn_slabs(kept);
+                assert_eq!(leased_to(0), 0);
+            });
+        });
+    }
+
+    #[test]
+    fn owner_forwards_frees_to_the_lessee() {
+        sim::test_cluster(2).run_test(async {
+            let start = lease(1);
+            slabs_from(1).lock().unwrap().slabs.insert(start, Slab::new(start, SLAB_SIZE));
+            let layout = Layout::new::<usize>();
+            let addr = allocate(layout, 1).unwrap();
+            assert_eq!(leased_from(1), (SLAB_SIZE, MIN_ALIGN, 1));
+
+            // As when a value the lessee placed on server 1 is dropped there.
+            sim::on_server(1, || local_free(addr, layout.size(), layout.align()));
+            reclaim::flush_all().await;
+            assert_eq!(leased_from(1), (SLAB_SIZE, 0, 0));
+
+            slabs_from(1).lock().unwrap().slabs.clear();
+            sim::on_server(1, || local_rreturn_slabs(vec![start]));
+        });
+    }
+
+    #[test]
+    fn stale_returns_are_ignored() {
+        sim::test_cluster(2).run_test(async {
+            let start = lease(1);
+            sim::on_server(1, || {
+                local_rreturn_slabs(vec![start]);
+                let after = local_allocator().allocated();
+                // As when a lessee retries a return whose reply was lost.
+                local_rreturn_slabs(vec![start, start + SLAB_SIZE]);
+                assert_eq!(local_allocator().allocated(), after);
+                assert_eq!(leased_to(0), 0);
+            });
+        });
+    }
+
+    #[test]
+    fn placed_values_are_carved_out_of_leased_slabs() {
+        sim::test_cluster(2).run_test(async {
+            let value = DBox::new_on(7usize, Placement::On(1));
+            let other = DBox::new_on(8usize, Placement::On(1));
+            assert_eq!(leased_from(1), (SLAB_SIZE, 2 * MIN_ALIGN, 2));
+            assert_eq!(sim::on_server(1, || leased_to(0)), SLAB_SIZE);
+
+            // Dropping them frees on server 1, which hands the memory back to server 0.
+            drop(value);
+            drop(other);
+            reclaim::flush_all().await;
+            assert_eq!(leased_from(1), (SLAB_SIZE, 0, 0));
+        });
+    }
+}
@@ -0,0 +1,121 @@
+use std::{fmt::Write, sync::Arc};
+
+use futures::future;
+use serde::{Deserialize, Serialize};
+use tarpc::context;
+
+use super::{direct_for, local_allocator, slab, DALLOCTOR};
+use crate::{conf::*, drust_std::sim};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PeerStats {
+    pub server_idx: usize,
+    // Bytes of this server's heap leased as slabs by the peer.
+    pub leased_to: usize,
+    // Bytes of the peer's heap this server leased, and what is allocated in them.
+    pub leased_from: usize,
+    pub slab_bytes: usize,
+    pub slab_objects: usize,
+    // Objects the peer allocated on this server's heap outside slabs, and their bytes.
+    pub direct_bytes: usize,
+    pub direct_objects: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HeapStats {
+    pub server_idx: usize,
+    pub heap_size: usize,
+    pub allocated_bytes: usize,
+    pub allocated_chunks: usize,
+    pub free_bytes: usize,
+    // The never used end of the heap, one free block. Freed holes below it are not counted.
+    pub untouched_tail: usize,
+    pub peers: Vec<PeerStats>,
+}
+
+// Reads the totals kept by this server's allocator, so it is cheap and safe while allocations
+// run. Allocator headers and padding count as free.
+pub fn stats() -> HeapStats {
+    let (server_idx, heap_size) = unsafe { (SERVER_INDEX, WORKER_UNIT_SIZE) };
+    let allocator = local_allocator();
+    let (allocated_bytes, allocated_chunks) = allocator.allocated();
+    let peers = (0..unsafe { NUM_SERVERS })
+        .filter(|i| *i != server_idx)
+        .map(|i| {
+            let (leased_from, slab_bytes, slab_objects) = slab::leased_from(i);
+            let (direct_bytes, direct_objects) = direct_for(i);
+            PeerStats {
+                server_idx: i,
+                leased_to: slab::leased_to(i),
+                leased_from,
+                slab_bytes,
+                slab_objects,
+                direct_bytes,
+                direct_objects,
+            }
+        })
+        .collect();
+    HeapStats {
+        server_idx,
+        heap_size,
+        allocated_bytes,
+        allocated_chunks,
+        free_bytes: heap_size.saturating_sub(allocated_bytes),
+        untouched_tail: allocator.untouched(),
+        peers,
+    }
+}
+
+pub async fn server_stats(server_idx: usize) -> anyhow::Result<HeapStats> {
+    if sim::is_active() {
+        return Ok(sim::on_server(server_idx, stats));
+    }
+    if server_idx == unsafe { SERVER_INDEX } {
+        return Ok(stats());
+    }
+    let client = Arc::clone(&unsafe { DALLOCTOR.as_ref().unwrap() }[server_idx]);
+    Ok(client.heap_stats(context::current()).await?)
+}
+
+// Heap stats of every server, in server order.
+pub async fn cluster_stats() -> anyhow::Result<Vec<HeapStats>> {
+    future::try_join_all((0..unsafe { NUM_SERVERS }).map(server_stats)).await
+}
+
+fn gb(bytes: usize) -> f64 {
+    bytes as f64 / (1u64 << 30) as f64
+}
+
+pub fn format_report(stats: &[HeapStats]) -> String {
+    let mut report = String::new();
+    writeln!(
+        report,
+        "server  used GB  free GB  untouched GB  chunks  leased out GB  leased in GB"
+    )
+    .unwrap();
+    for s in stats {
+        let leased_out: usize = s.peers.iter().map(|p| p.leased_to).sum();
+        let leased_in: usize = s.peers.iter().map(|p| p.leased_from).sum();
+        writeln!(
+            report,
+            "{:>6}  {:>7.2}  {:>7.2}  {:>12.2}  {:>6}  {:>13.2}  {:>12.2}",
+            s.server_idx,
+            gb(s.allocated_bytes),
+            gb(s.free_bytes),
+            gb(s.untouched_tail),
+            s.allocated_chunks,
+            gb(leased_out),
+            gb(leased_in),
+        )
+        .unwrap();
+    }
+    let used: usize = stats.iter().map(|s| s.allocated_bytes).sum();
+    let total: usize = stats.iter().map(|s| s.heap_size).sum();
+    write!(report, "total   {:>7.2} of {:.2} GB", gb(used), gb(total)).unwrap();
+    report
+}
+
+// Collects the heap stats of all servers into a printable table.
+pub async fn cluster_report() -> anyhow::Result<String> {
+    Ok(format_report(&cluster_stats().await?))
+}
@@ -0,0 +1,55 @@
+use std::{future::Future, pin::Pin};
+
+pub type AppFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+// A workload that drust_main can launch by name with `--application`.
+pub trait DrustApp {
+    fn name(&self) -> &'static str;
+
+    // Runs on every server once its app clients are connected, before the coordinator starts.
+    fn init(&self) -> AppFuture {
+        Box::pin(async {})
+    }
+
+    // Runs on the coordinator, server 0. The cluster shuts down when it returns.
+    fn run(&self) -> AppFuture;
+
+    // Runs on every server after the coordinator finished.
+    fn shutdown(&self) -> AppFuture {
+        Box::pin(async {})
+    }
+}
+
+static mut APPS: Vec<&'static dyn DrustApp> = Vec::new();
+
+pub fn register_app(app: &'static dyn DrustApp) {
+    let name = app.name();
+    if find_app(name).is_some() {
+        panic!("application {} is registered twice", name);
+    }
+    unsafe {
+        APPS.push(app);
+    }
+}
+
+pub fn app_names() -> Vec<&'static str> {
+    unsafe { APPS.iter().map(|app| app.name()).collect() }
+}
+
+pub fn find_app(name: &str) -> Option<&'static dyn DrustApp> {
+    unsafe { APPS.iter().find(|app| app.name() == name).copied() }
+}
+
+// Defines `main` for an application binary that depends on the drust library:
+//     drust::drust_main!(MyApp);
+#[macro_export]
+macro_rules! drust_main {
+    ($($app:expr),* $(,)?) => {
+        fn main() {
+            $(
+                $crate::drust_std::application::register_app(&$app);
+            )*
+            $crate::drust_std::launch();
+        }
+    };
+}
@@ -0,0 +1,215 @@
+use std::{marker::PhantomData, mem, slice};
+
+use futures::future;
+use rayon::prelude::*;
+
+use super::dvec::{DVec, DVecRef};
+use crate::{
+    conf::*,
+    drust_std::{
+        alloc::local_allocator,
+        collective,
+        primitives::{current_place, read_placed, Destination, DRust},
+        thread::{capacity, dspawn_to_relaxed, JoinError},
+    },
+};
+
+// Data-parallel iteration over a DVec. The index range is split into one part per server, sized
+// by the compute slots each server reported. Every part runs on its server: the owner of the
+// buffer works on it in place, the others read just their part with one remote read. Within a
+// part the work is spread over the local threads with rayon.
+//
+// Elements are Copy and the closures are Copy + 'static, as they are shipped to the other servers
+// bit by bit: a closure must not capture references or pointers into local memory.
+
+// The stages after map and filter, composed into one function that drops filtered elements.
+#[derive(Clone, Copy)]
+pub struct DParIter<'a, T, U, G> {
+    addr: usize,
+    len: usize,
+    stage: G,
+    _source: PhantomData<&'a [T]>,
+    _output: PhantomData<fn() -> U>,
+}
+
+impl<T: DRust + Copy + Send + Sync + 'static> DVec<T> {
+    pub fn dpar_iter(&self) -> DParIter<'_, T, T, fn(T) -> Option<T>> {
+        DParIter::new(self.as_ptr() as usize, self.len())
+    }
+}
+
+impl<'a, T: DRust + Copy + Send + Sync + 'static> DVecRef<'a, T> {
+    pub fn dpar_iter(&self) -> DParIter<'a, T, T, fn(T) -> Option<T>> {
+        DParIter::new(self.orig_raw.0, self.orig_raw.1)
+    }
+}
+
+impl<'a, T: Copy> DParIter<'a, T, T, fn(T) -> Option<T>> {
+    fn new(addr: usize, len: usize) -> Self {
+        DParIter {
+            addr,
+            len,
+            stage: Some,
+            _source: PhantomData,
+            _output: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, U, G> DParIter<'a, T, U, G>
+where
+    T: DRust + Copy + Send + Sync + 'static,
+    U: Send + 'static,
+    G: Fn(T) -> Option<U> + Copy + Send + Sync + 'static,
+{
+    pub fn map<V, F>(
+        self,
+        f: F,
+    ) -> DParIter<'a, T, V, impl Fn(T) -> Option<V> + Copy + Send + Sync + 'static>
+    where
+        V: Send + 'static,
+        F: Fn(U) -> V + Copy + Send + Sync + 'static,
+    {
+        let stage = self.stage;
+        DParIter {
+            addr: self.addr,
+            len: self.len,
+            stage: move |item| stage(item).map(f),
+            _source: PhantomData,
+            _output: PhantomData,
+        }
+    }
+
+    pub fn filter<P>(
+        self,
+        predicate: P,
+    ) -> DParIter<'a, T, U, impl Fn(T) -> Option<U> + Copy + Send + Sync + 'static>
+    where
+        P: Fn(&U) -> bool + Copy + Send + Sync + 'static,
+    {
+        let stage = self.stage;
+        DParIter {
+            addr: self.addr,
+            len: self.len,
+            stage: move |item| stage(item).filter(predicate),
+            _source: PhantomData,
+            _output: PhantomData,
+        }
+    }
+
+    pub async fn for_each<F>(self, f: F) -> Result<(), JoinError>
+    where
+        F: Fn(U) + Copy + Send + Sync + 'static,
+    {
+        let stage = self.stage;
+        self.run(move |items: &[T]| {
+            items.par_iter().for_each(|item| {
+                if let Some(value) = stage(*item) {
+                    f(value);
+                }
+            })
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Folds every part from `identity()` with `op`, then the results of the parts in index order.
+    // `op` should be associative, as with rayon's reduce.
+    pub async fn reduce<ID, OP>(self, identity: ID, op: OP) -> Result<U, JoinError>
+    where
+        U: DRust,
+        ID: Fn() -> U + Copy + Send + Sync + 'static,
+        OP: Fn(U, U) -> U + Copy + Send + Sync + 'static,
+    {
+        let stage = self.stage;
+        let parts = self
+            .run(move |items: &[T]| {
+                items
+                    .par_iter()
+                    .filter_map(|item| stage(*item))
+                    .reduce(identity, op)
+            })
+            .await?;
+        Ok(parts.into_iter().fold(identity(), op))
+    }
+
+    // Gathers the results, in index order, into a new DVec on this server.
+    pub async fn collect(self) -> Result<DVec<U>, JoinError>
+    where
+        U: DRust + Copy + Sync,
+    {
+        let stage = self.stage;
+        let parts = self
+            .run(move |items: &[T]| {
+                DVec::from_vec(items.par_iter().filter_map(|item| stage(*item)).collect())
+            })
+            .await?;
+        Ok(collective::gather(parts))
+    }
+
+    // Runs `work` on every non-empty part, each on its server, and returns the outputs in index
+    // order.
+    async fn run<R, W>(self, work: W) -> Result<Vec<R>, JoinError>
+    where
+        R: DRust + Send + 'static,
+        W: Fn(&[T]) -> R + Copy + Send + Sync + 'static,
+    {
+        let addr = self.addr;
+        let handles = partition(self.len)
+            .into_iter()
+            .filter(|(_, start, end)| start < end)
+            .map(|(server_idx, start, end)| {
+                let part_addr = addr + start * mem::size_of::<T>();
+                dspawn_to_relaxed(
+                    async move { with_slice(part_addr, end - start, work) },
+                    server_idx,
+                )
+            });
+        future::join_all(handles).await.into_iter().collect()
+    }
+}
+
+// Splits 0..len into one range per server, in server order, in proportion to the compute slots of
+// each server.
+fn partition(len: usize) -> Vec<(usize, usize, usize)> {
+    let slots: Vec<usize> = (0..unsafe { NUM_SERVERS }).map(capacity::slots).collect();
+    let total: usize = slots.iter().sum();
+    let mut ranges = Vec::with_capacity(slots.len());
+    let mut seen = 0;
+    for (server_idx, &n) in slots.iter().enumerate() {
+        let start = len * seen / total;
+        seen += n;
+        ranges.push((server_idx, start, len * seen / total));
+    }
+    ranges
+}
+
+// Calls `f` on the `len` elements at `addr`, in place if they live here and on a copy otherwise.
+fn with_slice<T: Copy, R>(addr: usize, len: usize, f: impl FnOnce(&[T]) -> R) -> R {
+    if current_place(addr) == Destination::Local {
+        return f(unsafe { slice::from_raw_parts(addr as *const T, len) });
+    }
+    let mut copy = Vec::with_capacity_in(len, local_allocator());
+    unsafe {
+        read_placed(copy.as_mut_ptr(), addr as *const T, len);
+        copy.set_len(len);
+    }
+    f(&copy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drust_std::sim;
+
+    #[test]
+    fn partition_covers_the_range_in_server_order() {
+        sim::test_cluster(4).run_test(async {
+            // Every simulated server reports the same slots.
+            capacity::discover(&[]).await.unwrap();
+            assert_eq!(partition(10), vec![(0, 0, 2), (1, 2, 5), (2, 5, 7), (3, 7, 10)]);
+            // With fewer elements than servers some ranges are empty.
+            assert_eq!(partition(3), vec![(0, 0, 0), (1, 0, 1), (2, 1, 2), (3, 2, 3)]);
+        });
+    }
+}
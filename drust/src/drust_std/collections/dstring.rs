@@ -1,7 +1,7 @@
-use crate::drust_std::alloc::LOCAL_ALLOCATOR;
+use crate::drust_std::alloc::local_allocator;
 
 use super::{*, dvec::*};
 
-pub(crate) type DString = DVec<u8>;
-pub(crate) type DStringRef<'a> = DVecRef<'a, u8>;
-pub(crate) type DStringMut<'a> = DVecMutRef<'a, u8>;
\ No newline at end of file
+pub type DString = DVec<u8>;
+pub type DStringRef<'a> = DVecRef<'a, u8>;
+pub type DStringMut<'a> = DVecMutRef<'a, u8>;
\ No newline at end of file
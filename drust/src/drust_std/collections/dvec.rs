@@ -8,13 +8,13 @@ use serde::{Deserialize, Serialize};
 use std::{alloc::Layout, mem, ptr::NonNull, sync::Arc, thread};
 
 use crate::drust_std::comm::*;
-use crate::drust_std::{alloc::*, primitives::*};
+use crate::drust_std::{alloc::{oom::{oom, try_local_allocate}, *}, primitives::*};
 use crate::{conf::*, dassert, dprintln};
 
 // #[derive(Clone)]
 pub struct DVec<T: DRust> {
-    pub internal_vec: Option<Vec<T, &'static good_memory_allocator::SpinLockedAllocator>>,
-    pub copy: Vec<T, &'static good_memory_allocator::SpinLockedAllocator>,
+    pub internal_vec: Option<Vec<T, &'static HeapAllocator>>,
+    pub copy: Vec<T, &'static HeapAllocator>,
     pub copy_exists: bool,
 }
 
@@ -22,8 +22,8 @@ impl<T:DRust> Default for DVec<T> {
     fn default() -> Self {
         unsafe{
             DVec {
-                internal_vec: Some(Vec::new_in(&LOCAL_ALLOCATOR)),
-                copy: Vec::new_in(&LOCAL_ALLOCATOR),
+                internal_vec: Some(Vec::new_in(local_allocator())),
+                copy: Vec::new_in(local_allocator()),
                 copy_exists: false,
             }
         }
@@ -45,7 +45,7 @@ impl<T: DRust + Clone> Clone for DVec<T> {
         unsafe {
             DVec {
                 internal_vec: Some(new_dvec),
-                copy: Vec::new_in(&LOCAL_ALLOCATOR),
+                copy: Vec::new_in(local_allocator()),
                 copy_exists: false,
             }
         }
@@ -59,6 +59,9 @@ impl<T: DRust> DRust for DVec<T> {
     fn typeid(&self) -> u32 {
         (T::static_typeid() << 8) | 1
     }
+    fn drop_local_copies(&mut self) {
+        self.drop_copy();
+    }
     fn migrate(&mut self, dest: Destination) -> bool {
         false
         // match dest {
@@ -140,7 +143,10 @@ impl<T: DRust> Drop for DVec<T> {
                 match current_place(raw_addr) {
                     Destination::Local => {
                         // dprintln!("Dropping DVec at {:x} as local", raw_addr);
-                        drop(ivec);
+                        let (raw_internal_vec, length, capacity) = ivec.into_raw_parts();
+                        drop(unsafe {
+                            Vec::from_raw_parts_in(raw_internal_vec, length, capacity, LocalFree)
+                        });
                     }
                     Destination::Remote(server_idx) => {
                         if ivec.capacity() == 0 {
@@ -200,17 +206,17 @@ impl<'a, T: DRust> DVec<T> {
     pub fn new() -> Self {
         unsafe{
             DVec {
-                internal_vec: Some(Vec::new_in(&LOCAL_ALLOCATOR)),
-                copy: Vec::new_in(&LOCAL_ALLOCATOR),
+                internal_vec: Some(Vec::new_in(local_allocator())),
+                copy: Vec::new_in(local_allocator()),
                 copy_exists: false,
             }
         }
     }
 
-    fn drop_copy(&mut self) {
+    pub(crate) fn drop_copy(&mut self) {
         if self.copy_exists {
             let orig_addr = self.internal_vec.as_ref().unwrap().as_ptr() as usize;
-            let empty_copy = unsafe{Vec::new_in(&LOCAL_ALLOCATOR)};  
+            let empty_copy = unsafe{Vec::new_in(local_allocator())};  
             let ref_map = unsafe { Arc::clone(REF_MAP.as_ref().unwrap()) };
             match ref_map.entry(orig_addr) {
                 Entry::Occupied(mut entry) => {
@@ -257,15 +263,39 @@ impl<'a, T: DRust> DVec<T> {
     }
     
     pub fn with_capacity(capacity: usize) -> Self {
-        let mut vec: Vec<T, &'static good_memory_allocator::SpinLockedAllocator> =
-            unsafe { Vec::with_capacity_in(capacity, &LOCAL_ALLOCATOR) };
-        DVec {
+        Self::with_capacity_from(capacity, try_local_allocate).unwrap_or_else(|e| oom(e))
+    }
+
+    // Like with_capacity, but reports running out of memory instead of panicking. With the spill
+    // policy the buffer may be placed on another server.
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, AllocError> {
+        Self::with_capacity_from(capacity, try_allocate)
+    }
+
+    // Allocates the buffer on the server picked by `placement`. Pushing into a remote vector
+    // moves it back here first, so fill it with from_vec_on instead.
+    pub fn with_capacity_on(capacity: usize, placement: impl Into<Placement>) -> Self {
+        let placement = placement.into();
+        Self::with_capacity_from(capacity, |layout| allocate_on(layout, placement))
+            .unwrap_or_else(|e| oom(e))
+    }
+
+    fn with_capacity_from(
+        capacity: usize,
+        allocate: impl FnOnce(Layout) -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<Self, AllocError> {
+        let layout = Layout::array::<T>(capacity).expect("capacity overflow");
+        let vec = if layout.size() == 0 {
+            Vec::with_capacity_in(capacity, local_allocator())
+        } else {
+            let buffer = allocate(layout)?;
+            unsafe { Vec::from_raw_parts_in(buffer.as_mut_ptr() as *mut T, 0, capacity, local_allocator()) }
+        };
+        Ok(DVec {
             internal_vec: Some(vec),
-            copy: unsafe {
-                Vec::new_in(&LOCAL_ALLOCATOR)
-            },
+            copy: Vec::new_in(local_allocator()),
             copy_exists: false,
-        }
+        })
     }
 
     pub fn push(&mut self, item: T) {
@@ -283,12 +313,12 @@ impl<'a, T: DRust> DVec<T> {
     }
 
     pub unsafe fn from_raw(
-        raw: Vec<T, &'static good_memory_allocator::SpinLockedAllocator>,
+        raw: Vec<T, &'static HeapAllocator>,
     ) -> Self {
         DVec {
             internal_vec: Some(raw),
             copy: unsafe {
-                Vec::new_in(&LOCAL_ALLOCATOR)
+                Vec::new_in(local_allocator())
             },
             copy_exists: false,
         }
@@ -300,8 +330,8 @@ impl<'a, T: DRust> DVec<T> {
     }
 
     pub fn from_vec(mut vec: Vec<T>) -> Self {
-        let mut rvec: Vec<T, &'static good_memory_allocator::SpinLockedAllocator> =
-            unsafe { Vec::with_capacity_in(vec.capacity(), &LOCAL_ALLOCATOR) };
+        let mut rvec: Vec<T, &'static HeapAllocator> =
+            unsafe { Vec::with_capacity_in(vec.capacity(), local_allocator()) };
         unsafe {
             // copy_mem(
             //     vec.as_ptr() as usize,
@@ -320,12 +350,29 @@ impl<'a, T: DRust> DVec<T> {
         DVec {
             internal_vec: Some(rvec),
             copy: unsafe {
-                Vec::new_in(&LOCAL_ALLOCATOR)
+                Vec::new_in(local_allocator())
             },
             copy_exists: false,
         }
     }
 
+    // Copies the elements of `vec` to the server picked by `placement`, in as few writes as the
+    // staging buffer allows.
+    pub fn from_vec_on(mut vec: Vec<T>, placement: impl Into<Placement>) -> Self {
+        let len = vec.len();
+        let mut dvec = Self::with_capacity_on(len, placement);
+        unsafe {
+            copy_placed(
+                dvec.as_ptr() as *mut u8,
+                vec.as_ptr() as *const u8,
+                len * mem::size_of::<T>(),
+            );
+            dvec.set_len(len);
+            vec.set_len(0);
+        }
+        dvec
+    }
+
     pub fn migrate_to_local(&mut self) {
         if self.copy_exists {
             self.drop_copy();
@@ -342,8 +389,8 @@ impl<'a, T: DRust> DVec<T> {
                 dprintln!(
                     "--------------------------Real Migrating to local--------------------------"
                 );
-                let mut local_vec: Vec<T, &'static good_memory_allocator::SpinLockedAllocator> =
-                    unsafe { Vec::with_capacity_in(capacity, &LOCAL_ALLOCATOR) };
+                let mut local_vec: Vec<T, &'static HeapAllocator> =
+                    unsafe { Vec::with_capacity_in(capacity, local_allocator()) };
                 unsafe {
                     drust_read_large_sync(
                         (local_vec.as_ptr() as *mut T as usize) - LOCAL_HEAP_START,
@@ -396,11 +443,11 @@ impl<'a, T: DRust> DVec<T> {
                         *ptr as *mut T,
                         self.internal_vec.as_ref().unwrap().len(),
                         self.internal_vec.as_ref().unwrap().capacity(),
-                        &LOCAL_ALLOCATOR,
+                        local_allocator(),
                     )
                 };
                 unsafe {
-                    let dst_ptr = &self.copy as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as *mut Vec<T, &'static good_memory_allocator::SpinLockedAllocator>;
+                    let dst_ptr = &self.copy as *const Vec<T, &'static HeapAllocator> as *mut Vec<T, &'static HeapAllocator>;
                     std::ptr::write_volatile(dst_ptr, copy_vec);
                     std::ptr::write_volatile(&self.copy_exists as *const bool as *mut bool, true);
                     dprintln!("Copy addr: {:x}", self.copy.as_ptr() as usize);
@@ -413,8 +460,8 @@ impl<'a, T: DRust> DVec<T> {
             }
             Entry::Vacant(entry) => {
                 dprintln!("Creating a new entry in ref map");
-                let mut v: Vec<T, &good_memory_allocator::SpinLockedAllocator> =
-                    unsafe { Vec::with_capacity_in(self.internal_vec.as_ref().unwrap().capacity(), &LOCAL_ALLOCATOR) };
+                let mut v: Vec<T, &HeapAllocator> =
+                    unsafe { Vec::with_capacity_in(self.internal_vec.as_ref().unwrap().capacity(), local_allocator()) };
                 let ptr = v.as_ptr();
                 dprintln!("Copy addr: {:x}", ptr as usize);
                 dprintln!(
@@ -433,7 +480,7 @@ impl<'a, T: DRust> DVec<T> {
                 }
                 dprintln!("first 4 bytes: {:x}", unsafe { *(ptr as *const usize) });
                 unsafe {
-                    let dst_ptr = (&self.copy) as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as usize as *mut Vec<T, &'static good_memory_allocator::SpinLockedAllocator>;
+                    let dst_ptr = (&self.copy) as *const Vec<T, &'static HeapAllocator> as usize as *mut Vec<T, &'static HeapAllocator>;
                     std::ptr::write_volatile(dst_ptr, v);
                     std::ptr::write_volatile(&self.copy_exists as *const bool as usize as *mut bool, true);
                 }
@@ -449,22 +496,22 @@ impl<'a, T: DRust> DVec<T> {
 
     }
 
-    pub fn as_local(mut self) -> Vec<T, &'static good_memory_allocator::SpinLockedAllocator> {
+    pub fn as_local(mut self) -> Vec<T, &'static HeapAllocator> {
         self.migrate_to_local();
         mem::replace(&mut self.internal_vec, None).unwrap()
     }
 
-    pub fn from_local(vec: Vec<T, &'static good_memory_allocator::SpinLockedAllocator>) -> Self {
+    pub fn from_local(vec: Vec<T, &'static HeapAllocator>) -> Self {
         DVec {
             internal_vec: Some(vec),
-            copy: unsafe{Vec::new_in(&LOCAL_ALLOCATOR)},
+            copy: unsafe{Vec::new_in(local_allocator())},
             copy_exists: false,
         }
     }
 
     pub fn as_local_ref(
         &'a self,
-    ) -> &'a Vec<T, &'static good_memory_allocator::SpinLockedAllocator> {
+    ) -> &'a Vec<T, &'static HeapAllocator> {
         if self.internal_vec.as_ref().unwrap().capacity() == 0 {
             return self.internal_vec.as_ref().unwrap();
         }
@@ -480,7 +527,7 @@ impl<'a, T: DRust> DVec<T> {
 
     pub unsafe fn as_local_mut_ref(
         &'a mut self,
-    ) -> &'a mut Vec<T, &'static good_memory_allocator::SpinLockedAllocator> {
+    ) -> &'a mut Vec<T, &'static HeapAllocator> {
         self.migrate_to_local();
         self.internal_vec.as_mut().unwrap()
     }
@@ -492,7 +539,7 @@ impl<'a, T: DRust> DVec<T> {
         DVecRef {
             orig_vec: self.internal_vec.as_ref().unwrap(),
             orig_raw: (raw_addr, len, cap),
-            copy: unsafe{Vec::new_in(&LOCAL_ALLOCATOR)},
+            copy: unsafe{Vec::new_in(local_allocator())},
             copy_exists: false,
         }
     }
@@ -510,9 +557,9 @@ impl<'a, T: DRust> DVec<T> {
 }
 
 pub struct DVecRef<'a, T: DRust> {
-    pub orig_vec: &'a Vec<T, &'static good_memory_allocator::SpinLockedAllocator>,
+    pub orig_vec: &'a Vec<T, &'static HeapAllocator>,
     pub orig_raw: (usize, usize, usize), // (ptr, length, capacity)
-    pub copy: Vec<T, &'static good_memory_allocator::SpinLockedAllocator>,
+    pub copy: Vec<T, &'static HeapAllocator>,
     pub copy_exists: bool
 }
 
@@ -520,9 +567,9 @@ impl<'a, T:DRust> Default for DVecRef<'a, T> {
     fn default() -> Self {
         unsafe{
             DVecRef {
-                orig_vec: &*(ptr::null() as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator>),
+                orig_vec: &*(ptr::null() as *const Vec<T, &'static HeapAllocator>),
                 orig_raw: (0, 0, 0),
-                copy: Vec::new_in(&LOCAL_ALLOCATOR),
+                copy: Vec::new_in(local_allocator()),
                 copy_exists: false,
             }
         }
@@ -537,7 +584,7 @@ where
         DVecRef {
             orig_vec: self.orig_vec,
             orig_raw: self.orig_raw,
-            copy: unsafe{Vec::new_in(&LOCAL_ALLOCATOR)},
+            copy: unsafe{Vec::new_in(local_allocator())},
             copy_exists: false,
         }
     }
@@ -553,6 +600,9 @@ impl<'a, T: DRust + Sized> DRust for DVecRef<'a, T> {
     fn migrate(&mut self, dest: Destination) -> bool {
         false
     }
+    fn drop_local_copies(&mut self) {
+        self.drop_copy();
+    }
 }
 
 impl<'a, T: DRust + Sized> Drop for DVecRef<'a, T> {
@@ -570,14 +620,14 @@ impl<'a, T: DRust + Sized> DVecRef<'a, T> {
         if self.copy_exists {
             if self.copy.as_ptr() as usize == self.orig_raw.0 {
 
-                let empty_copy = unsafe{Vec::new_in(&LOCAL_ALLOCATOR)};
+                let empty_copy = unsafe{Vec::new_in(local_allocator())};
                 let mut v = mem::replace(&mut self.copy, empty_copy);
                 let _ = v.into_raw_parts();
                 return;
             }
             dprintln!("Dropping copy! Vec of Type: {:x}", T::static_typeid());
             let orig_addr = self.orig_raw.0;
-            let empty_copy = unsafe{Vec::new_in(&LOCAL_ALLOCATOR)};
+            let empty_copy = unsafe{Vec::new_in(local_allocator())};
             let ref_map = unsafe { Arc::clone(REF_MAP.as_ref().unwrap()) };
             match ref_map.entry(orig_addr) {
                 Entry::Occupied(mut entry) => {
@@ -630,9 +680,9 @@ impl<'a, T: DRust + Sized> DVecRef<'a, T> {
                         self.orig_raw.0 as *mut T,
                         self.orig_raw.1,
                         self.orig_raw.2,
-                        &LOCAL_ALLOCATOR,
+                        local_allocator(),
                     );
-                    let dst_ptr = (&self.copy) as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as usize as *mut Vec<T, &'static good_memory_allocator::SpinLockedAllocator>;
+                    let dst_ptr = (&self.copy) as *const Vec<T, &'static HeapAllocator> as usize as *mut Vec<T, &'static HeapAllocator>;
                     std::ptr::write_volatile(dst_ptr, v);
                     std::ptr::write_volatile(&self.copy_exists as *const bool as usize as *mut bool, true);
                 }
@@ -649,11 +699,11 @@ impl<'a, T: DRust + Sized> DVecRef<'a, T> {
                                 *ptr as *mut T,
                                 self.orig_raw.1,
                                 self.orig_raw.2,
-                                &LOCAL_ALLOCATOR,
+                                local_allocator(),
                             )
                         };
                         unsafe {
-                            let dst_ptr = &self.copy as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as *mut Vec<T, &'static good_memory_allocator::SpinLockedAllocator>;
+                            let dst_ptr = &self.copy as *const Vec<T, &'static HeapAllocator> as *mut Vec<T, &'static HeapAllocator>;
                             std::ptr::write_volatile(dst_ptr, v);
                             std::ptr::write_volatile(&self.copy_exists as *const bool as *mut bool, true);
                             dprintln!("Copy addr: {:x}", self.copy.as_ptr() as usize);
@@ -665,8 +715,8 @@ impl<'a, T: DRust + Sized> DVecRef<'a, T> {
                     }
                     Entry::Vacant(entry) => {
                         dprintln!("Creating a new entry in ref map");
-                        let mut v: Vec<T, &good_memory_allocator::SpinLockedAllocator> =
-                            unsafe { Vec::with_capacity_in(self.orig_raw.2, &LOCAL_ALLOCATOR) };
+                        let mut v: Vec<T, &HeapAllocator> =
+                            unsafe { Vec::with_capacity_in(self.orig_raw.2, local_allocator()) };
                         let ptr = v.as_ptr();
                         dprintln!("Copy addr: {:x}", ptr as usize);
                         dprintln!("orig_raw.0: {:x}", self.orig_raw.0);
@@ -687,7 +737,7 @@ impl<'a, T: DRust + Sized> DVecRef<'a, T> {
                         }
                         dprintln!("first 4 bytes: {:x}", unsafe { *(ptr as *const usize) });
                         unsafe {
-                            let dst_ptr = (&self.copy) as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as usize as *mut Vec<T, &'static good_memory_allocator::SpinLockedAllocator>;
+                            let dst_ptr = (&self.copy) as *const Vec<T, &'static HeapAllocator> as usize as *mut Vec<T, &'static HeapAllocator>;
                             dprintln!("dst_ptr: {:x}", dst_ptr as usize);
                             std::ptr::write_volatile(dst_ptr, v);
                             dprintln!("copy_exists: {:x}", &self.copy_exists as *const bool as usize);
@@ -705,7 +755,7 @@ impl<'a, T: DRust + Sized> DVecRef<'a, T> {
         
     }
 
-    pub fn as_regular(&self) -> &Vec<T, &'static good_memory_allocator::SpinLockedAllocator> {
+    pub fn as_regular(&self) -> &Vec<T, &'static HeapAllocator> {
         self.local_copy();
         &self.copy
     }
@@ -729,9 +779,9 @@ where
 }
 
 pub struct DVecMutRef<'a, T: DRust> {
-    pub orig_vec: &'a Vec<T, &'static good_memory_allocator::SpinLockedAllocator>,
+    pub orig_vec: &'a Vec<T, &'static HeapAllocator>,
     pub orig_raw: (usize, usize, usize), // (ptr, length, capacity)
-    pub copy: Option<Vec<T, &'static good_memory_allocator::SpinLockedAllocator>>,
+    pub copy: Option<Vec<T, &'static HeapAllocator>>,
 }
 
 impl<'a, T: DRust + Sized> Drop for DVecMutRef<'a, T> {
@@ -791,13 +841,13 @@ impl<'a, T: DRust + Sized> DVecMutRef<'a, T> {
                         self.orig_raw.0 as *mut T,
                         self.orig_raw.1,
                         self.orig_raw.2,
-                        &LOCAL_ALLOCATOR,
+                        local_allocator(),
                     ))
                 };
             }
             Destination::Remote(_server_idx) => {
-                let mut v: Vec<T, &good_memory_allocator::SpinLockedAllocator> =
-                    unsafe { Vec::with_capacity_in(self.orig_raw.2, &LOCAL_ALLOCATOR) };
+                let mut v: Vec<T, &HeapAllocator> =
+                    unsafe { Vec::with_capacity_in(self.orig_raw.2, local_allocator()) };
                 let ptr = v.as_ptr();
                 unsafe {
                     drust_read_large_sync(
@@ -815,7 +865,7 @@ impl<'a, T: DRust + Sized> DVecMutRef<'a, T> {
 
     pub fn as_regular(
         &mut self,
-    ) -> &mut Vec<T, &'static good_memory_allocator::SpinLockedAllocator> {
+    ) -> &mut Vec<T, &'static HeapAllocator> {
         self.local_copy();
         let v = self.copy.as_mut().unwrap();
         v
@@ -823,45 +873,45 @@ impl<'a, T: DRust + Sized> DVecMutRef<'a, T> {
 }
 
 
-impl<T:DRust> Borrow<Vec<T, &'static good_memory_allocator::SpinLockedAllocator>> for DVec<T> {
-    fn borrow(&self) -> &Vec<T, &'static good_memory_allocator::SpinLockedAllocator> {
+impl<T:DRust> Borrow<Vec<T, &'static HeapAllocator>> for DVec<T> {
+    fn borrow(&self) -> &Vec<T, &'static HeapAllocator> {
         unsafe{
-            // &*(self.as_local_ref() as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as usize as *const Vec<T>)
+            // &*(self.as_local_ref() as *const Vec<T, &'static HeapAllocator> as usize as *const Vec<T>)
             self.as_local_ref()
         }
     }
 }
 
-impl<T:DRust> AsRef<Vec<T, &'static good_memory_allocator::SpinLockedAllocator>> for DVec<T> {
-    fn as_ref(&self) -> &Vec<T,  &'static good_memory_allocator::SpinLockedAllocator> {
+impl<T:DRust> AsRef<Vec<T, &'static HeapAllocator>> for DVec<T> {
+    fn as_ref(&self) -> &Vec<T,  &'static HeapAllocator> {
         unsafe{
-            // &*(self.as_local_ref() as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as usize as *const Vec<T>)
+            // &*(self.as_local_ref() as *const Vec<T, &'static HeapAllocator> as usize as *const Vec<T>)
             self.as_local_ref()
-            // &*(self.internal_vec.as_ref().unwrap() as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as usize as *const Vec<T>)
+            // &*(self.internal_vec.as_ref().unwrap() as *const Vec<T, &'static HeapAllocator> as usize as *const Vec<T>)
         }
     }
 }
 
-impl<T:DRust> AsMut<Vec<T, &'static good_memory_allocator::SpinLockedAllocator>> for DVec<T> {
-    fn as_mut(&mut self) -> &mut Vec<T,  &'static good_memory_allocator::SpinLockedAllocator> {
+impl<T:DRust> AsMut<Vec<T, &'static HeapAllocator>> for DVec<T> {
+    fn as_mut(&mut self) -> &mut Vec<T,  &'static HeapAllocator> {
         unsafe{
             self.as_local_mut_ref()
         }
     }
 }
 
-impl<T:DRust> AsRef<Vec<T, &'static good_memory_allocator::SpinLockedAllocator>> for DVecRef<'_, T> {
-    fn as_ref(&self) -> &Vec<T,  &'static good_memory_allocator::SpinLockedAllocator> {
+impl<T:DRust> AsRef<Vec<T, &'static HeapAllocator>> for DVecRef<'_, T> {
+    fn as_ref(&self) -> &Vec<T,  &'static HeapAllocator> {
         unsafe{
-            // &*(self.as_local_ref() as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as usize as *const Vec<T>)
+            // &*(self.as_local_ref() as *const Vec<T, &'static HeapAllocator> as usize as *const Vec<T>)
             self.as_regular()
-            // &*(self.internal_vec.as_ref().unwrap() as *const Vec<T, &'static good_memory_allocator::SpinLockedAllocator> as usize as *const Vec<T>)
+            // &*(self.internal_vec.as_ref().unwrap() as *const Vec<T, &'static HeapAllocator> as usize as *const Vec<T>)
         }
     }
 }
 
-impl<T:DRust> AsMut<Vec<T, &'static good_memory_allocator::SpinLockedAllocator>> for DVecMutRef<'_, T> {
-    fn as_mut(&mut self) -> &mut Vec<T,  &'static good_memory_allocator::SpinLockedAllocator> {
+impl<T:DRust> AsMut<Vec<T, &'static HeapAllocator>> for DVecMutRef<'_, T> {
+    fn as_mut(&mut self) -> &mut Vec<T,  &'static HeapAllocator> {
         unsafe{
             self.as_regular()
         }
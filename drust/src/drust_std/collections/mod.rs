@@ -1,2 +1,5 @@
+#[allow(unused, clippy::all)]
 pub mod dvec;
-pub mod dstring;
\ No newline at end of file
+#[allow(unused, clippy::all)]
+pub mod dstring;
+pub mod dpar;
\ No newline at end of file
@@ -0,0 +1,321 @@
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{future::Future, mem, pin::Pin};
+
+use futures::future;
+
+use crate::{
+    conf::*,
+    drust_std::{
+        alloc::local_allocator,
+        collections::dvec::DVec,
+        primitives::{dbox::DBox, read_placed, DRust},
+        thread::{dspawn_to_relaxed, JoinError},
+    },
+};
+
+// Collective operations over all servers, driven from the calling server. Data moves by remote
+// reads of tasks spawned next to where it is needed, never through the caller.
+//
+// Values are copied bit by bit, so element types are Copy, and the ops are Copy + 'static closures
+// that capture no references, as for dpar_iter.
+
+type Spread = Pin<Box<dyn Future<Output = Result<Vec<usize>, JoinError>> + Send>>;
+
+// What a task of spread sends back: the addresses of the copies made from its server on, or, when
+// that failed, the error encoded with bincode. A task that fails has freed its copies already.
+#[derive(DRust)]
+enum Spreaded {
+    Copied(DVec<usize>),
+    Failed(DVec<u8>),
+}
+
+#[cfg(test)]
+static FAIL_COPY_ON: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+// Copies `len` values at `src`, local or remote, into a new buffer on this server.
+fn copy_here<T: DRust>(src: usize, len: usize) -> DVec<T> {
+    let mut copy = Vec::with_capacity_in(len, local_allocator());
+    unsafe {
+        read_placed(copy.as_mut_ptr(), src as *const T, len);
+        copy.set_len(len);
+    }
+    DVec::from_local(copy)
+}
+
+// Wraps the buffer of `len` values at `addr`, which another server allocated for us.
+unsafe fn adopt<T: DRust>(addr: usize, len: usize) -> DVec<T> {
+    DVec::from_local(Vec::from_raw_parts_in(addr as *mut T, len, len, local_allocator()))
+}
+
+// Frees the `len` values at each of `addrs`, wherever they are.
+fn discard<T: DRust>(addrs: &[usize], len: usize) {
+    for &addr in addrs {
+        drop(unsafe { adopt::<T>(addr, len) });
+    }
+}
+
+// Servers in broadcast order: rank 0 is the server that started it.
+fn server_of(root: usize, rank: usize) -> usize {
+    (root + rank) % unsafe { NUM_SERVERS }
+}
+
+// Gives the servers of ranks first + 1 .. first + span a copy of the `len` values at `here`, the
+// replica of rank `first`, and returns the addresses of their copies in rank order. The upper half
+// of the ranks is handed to a server that copies from `here` and spreads further, so the servers
+// holding a copy double every round and a broadcast takes log2(NUM_SERVERS) rounds. If any copy
+// fails, the others are freed and the first error comes back.
+fn spread<T: DRust + Copy + Send + Sync + 'static>(
+    root: usize,
+    here: usize,
+    len: usize,
+    first: usize,
+    span: usize,
+) -> Spread {
+    Box::pin(async move {
+        let mut handles = Vec::new();
+        let mut span = span;
+        while span > 1 {
+            let child_span = span / 2;
+            let child = first + span - child_span;
+            handles.push(dspawn_to_relaxed(
+                async move {
+                    #[cfg(test)]
+                    assert_ne!(FAIL_COPY_ON.load(Ordering::SeqCst), unsafe { SERVER_INDEX });
+                    let copy = copy_here::<T>(here, len);
+                    let addr = copy.as_local().into_raw_parts().0 as usize;
+                    match spread::<T>(root, addr, len, child, child_span).await {
+                        Ok(rest) => Spreaded::Copied(DVec::from_vec([vec![addr], rest].concat())),
+                        Err(e) => {
+                            discard::<T>(&[addr], len);
+                            Spreaded::Failed(DVec::from_vec(bincode::serialize(&e).unwrap()))
+                        }
+                    }
+                },
+                server_of(root, child),
+            ));
+            span -= child_span;
+        }
+        // Children were handed the ranks from the top down. All of them are awaited, so that no
+        // copy is left behind when one fails.
+        let mut addrs = Vec::new();
+        let mut failed = None;
+        for handle in handles.into_iter().rev() {
+            match handle.await {
+                Ok(Spreaded::Copied(copies)) => addrs.extend_from_slice(&copies.as_local()),
+                Ok(Spreaded::Failed(error)) => {
+                    let error = bincode::deserialize(&error.as_local()).unwrap();
+                    failed = failed.or(Some(error));
+                }
+                Err(e) => failed = failed.or(Some(e)),
+            }
+        }
+        match failed {
+            None => Ok(addrs),
+            Some(e) => {
+                discard::<T>(&addrs, len);
+                Err(e)
+            }
+        }
+    })
+}
+
+// Replicas of the `len` values at `root`, a fresh buffer on this server that becomes its replica,
+// one per server by server index. On failure `root` is freed as well.
+async fn replicate<T: DRust + Copy + Send + Sync + 'static>(
+    root: usize,
+    len: usize,
+) -> Result<Vec<usize>, JoinError> {
+    let server_idx = unsafe { SERVER_INDEX };
+    let mut addrs = vec![root];
+    match spread::<T>(server_idx, root, len, 0, unsafe { NUM_SERVERS }).await {
+        Ok(rest) => addrs.extend(rest),
+        Err(e) => {
+            discard::<T>(&[root], len);
+            return Err(e);
+        }
+    }
+    addrs.rotate_right(server_idx);
+    Ok(addrs)
+}
+
+// A copy of `value` on every server, by server index.
+pub async fn broadcast<T: DRust + Copy + Send + Sync + 'static>(
+    value: &T,
+) -> Result<Vec<DBox<T>>, JoinError> {
+    let root = unsafe { DBox::new(*value).into_raw_addr() };
+    let addrs = replicate::<T>(root, 1).await?;
+    Ok(addrs
+        .into_iter()
+        .map(|addr| unsafe { DBox::from_raw(addr as *mut T) })
+        .collect())
+}
+
+// A copy of `vec` on every server, by server index.
+pub async fn broadcast_vec<T: DRust + Copy + Send + Sync + 'static>(
+    vec: &DVec<T>,
+) -> Result<Vec<DVec<T>>, JoinError> {
+    let len = vec.len();
+    if len == 0 {
+        return Ok((0..unsafe { NUM_SERVERS }).map(|_| DVec::from_vec(Vec::new())).collect());
+    }
+    let (root, _, _) = copy_here::<T>(vec.as_ptr() as usize, len).as_local().into_raw_parts();
+    let addrs = replicate::<T>(root as usize, len).await?;
+    Ok(addrs.into_iter().map(|addr| unsafe { adopt(addr, len) }).collect())
+}
+
+// Splits `vec` into NUM_SERVERS parts of about equal length and moves part i to server i. Every
+// server reads its own part, all at once.
+pub async fn scatter<T: DRust + Copy + Send + Sync + 'static>(
+    vec: DVec<T>,
+) -> Result<Vec<DVec<T>>, JoinError> {
+    let num_servers = unsafe { NUM_SERVERS };
+    let (addr, len) = (vec.as_ptr() as usize, vec.len());
+    let handles = (0..num_servers).map(|server_idx| {
+        let start = len * server_idx / num_servers;
+        let end = len * (server_idx + 1) / num_servers;
+        let src = addr + start * mem::size_of::<T>();
+        dspawn_to_relaxed(async move { copy_here::<T>(src, end - start) }, server_idx)
+    });
+    let parts = future::join_all(handles).await.into_iter().collect();
+    drop(vec);
+    parts
+}
+
+// Concatenates `parts`, wherever they are, in order into a new DVec on this server.
+pub fn gather<T: DRust + Copy>(parts: Vec<DVec<T>>) -> DVec<T> {
+    let total = parts.iter().map(|part| part.len()).sum();
+    let mut out: Vec<T, _> = Vec::with_capacity_in(total, local_allocator());
+    for part in parts.iter() {
+        unsafe {
+            read_placed(out.as_mut_ptr().add(out.len()), part.as_ptr(), part.len());
+            out.set_len(out.len() + part.len());
+        }
+    }
+    DVec::from_local(out)
+}
+
+// Replaces every element of every part with `op` over that element in all parts, e.g. summing
+// per-server histograms so that each server holds the total. The parts must have the same length
+// and are usually one per server. `op` must be associative and commutative, as the parts are
+// combined in a different order for each chunk.
+//
+// Works as a ring: part j only reads from part j - 1, on the server holding part j. In the first
+// len(parts) - 1 steps every chunk gathers the values of all parts on one of them; in the next
+// len(parts) - 1 steps the finished chunks travel around the ring. Each part moves about twice its
+// own size in total, however many parts there are.
+pub async fn all_reduce<T, OP>(parts: &mut [DVec<T>], op: OP) -> Result<(), JoinError>
+where
+    T: DRust + Copy + Send + Sync + 'static,
+    OP: Fn(T, T) -> T + Copy + Send + Sync + 'static,
+{
+    let k = parts.len();
+    let len = parts.first().map_or(0, |part| part.len());
+    assert!(
+        parts.iter().all(|part| part.len() == len),
+        "all_reduce needs parts of the same length"
+    );
+    if k < 2 || len == 0 {
+        return Ok(());
+    }
+    for part in parts.iter_mut() {
+        part.drop_copy();
+    }
+    let ring: Vec<(usize, usize)> = parts
+        .iter()
+        .map(|part| (part.server_idx(), part.as_ptr() as usize))
+        .collect();
+    let chunk = |c: usize| (len * c / k, len * (c + 1) / k);
+    for step in 0..2 * (k - 1) {
+        let handles = (0..k).map(|j| {
+            let (server_idx, own) = ring[j];
+            let (_, prev) = ring[(j + k - 1) % k];
+            let reducing = step < k - 1;
+            // Part j works on chunk j - 1 - step: first adding in the neighbour's values, then
+            // taking the neighbour's finished chunk.
+            let c = (j + 3 * k - 1 - step) % k;
+            let (start, end) = chunk(c);
+            let offset = start * mem::size_of::<T>();
+            dspawn_to_relaxed(
+                async move {
+                    let own = (own + offset) as *mut T;
+                    if reducing {
+                        let theirs = copy_here::<T>(prev + offset, end - start);
+                        for (i, value) in theirs.as_ref().iter().enumerate() {
+                            unsafe { *own.add(i) = op(*own.add(i), *value) };
+                        }
+                    } else {
+                        unsafe { read_placed(own, (prev + offset) as *const T, end - start) };
+                    }
+                },
+                server_idx,
+            )
+        });
+        for result in future::join_all(handles).await {
+            result?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drust_std::sim;
+
+    fn allocated(num_servers: usize) -> Vec<(usize, usize)> {
+        (0..num_servers).map(|i| sim::on_server(i, || local_allocator().allocated())).collect()
+    }
+
+    fn home(addr: usize) -> usize {
+        unsafe { (addr - GLOBAL_HEAP_START) / WORKER_UNIT_SIZE }
+    }
+
+    #[test]
+    fn broadcast_gives_every_server_a_copy() {
+        sim::test_cluster(5).run_test(async {
+            let copies = broadcast(&1234usize).await.unwrap();
+            assert_eq!(copies.len(), 5);
+            for (server_idx, copy) in copies.iter().enumerate() {
+                assert_eq!(home(copy.get_addr()), server_idx);
+                assert_eq!(**copy, 1234);
+            }
+        });
+    }
+
+    #[test]
+    fn failed_broadcast_frees_the_copies_it_made() {
+        sim::test_cluster(5).run_test(async {
+            let vec = DVec::from_vec((0..100usize).collect());
+            let before = allocated(5);
+            // Server 3 copies for server 4, which fails after servers 1 to 3 have their copies.
+            FAIL_COPY_ON.store(4, Ordering::SeqCst);
+            let result = broadcast_vec(&vec).await;
+            FAIL_COPY_ON.store(usize::MAX, Ordering::SeqCst);
+            match result {
+                Err(JoinError::Panic(panic)) => assert_eq!(panic.server_idx, 4),
+                Err(e) => panic!("expected the panic on server 4, got {}", e),
+                Ok(_) => panic!("the broadcast succeeded"),
+            }
+            assert_eq!(allocated(5), before);
+        });
+    }
+
+    #[test]
+    fn all_reduce_leaves_the_total_in_every_part() {
+        // Different seeds interleave the ring steps differently.
+        for seed in 0..4 {
+            sim::test_cluster(3).seed(seed).yield_permille(300).run_test(async {
+                let mut parts: Vec<DVec<usize>> = (0..3)
+                    .map(|i| DVec::from_vec_on((0..10).map(|x| x * (i + 1)).collect(), i))
+                    .collect();
+                all_reduce(&mut parts, |a, b| a + b).await.unwrap();
+                for (server_idx, part) in parts.iter().enumerate() {
+                    assert_eq!(part.server_idx(), server_idx);
+                }
+                let expected: Vec<usize> = (0..3).flat_map(|_| (0..10).map(|x| x * 6)).collect();
+                assert_eq!(gather(parts).as_ref()[..], expected[..]);
+            });
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    io, ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{map_heap, Transport};
+use crate::conf::*;
+
+// Every address lives in this process, so remote accesses are plain memcpy within the mapped
+// heap. Only meaningful with a single server; used for builds without the rdma feature.
+pub struct LocalTransport;
+
+impl LocalTransport {
+    fn local_addr(local_offset: usize) -> usize {
+        unsafe { LOCAL_HEAP_START + local_offset }
+    }
+
+    fn remote_addr(remote_offset: usize) -> usize {
+        unsafe { GLOBAL_HEAP_START + remote_offset }
+    }
+}
+
+impl Transport for LocalTransport {
+    fn start_server(&self, _heap_start: usize, _heap_size: usize, server_idx: usize) {
+        panic!(
+            "local transport cannot start server {} of {}, use the rdma or tcp transport for multiple servers",
+            server_idx,
+            unsafe { NUM_SERVERS }
+        );
+    }
+
+    fn server_ready(&self) {}
+
+    fn disconnect(&self) -> i32 {
+        0
+    }
+
+    fn register_mem(&self, heap_start: usize, heap_size: usize) -> usize {
+        map_heap(heap_start, heap_size)
+    }
+
+    fn write(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        byte_size: usize,
+    ) -> io::Result<usize> {
+        self.write_sync(local_src_offset, remote_dst_offset, byte_size, 0)
+    }
+
+    fn read(
+        &self,
+        local_dst_offset: usize,
+        remote_src_offset: usize,
+        byte_size: usize,
+    ) -> io::Result<usize> {
+        self.read_sync(local_dst_offset, remote_src_offset, byte_size, 0)
+    }
+
+    fn write_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        byte_size: usize,
+        _thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        let src = Self::local_addr(local_src_offset);
+        let dst = Self::remote_addr(remote_dst_offset);
+        unsafe { ptr::copy(src as *const u8, dst as *mut u8, byte_size) };
+        Ok(src)
+    }
+
+    fn read_sync(
+        &self,
+        local_dst_offset: usize,
+        remote_src_offset: usize,
+        byte_size: usize,
+        _thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        let dst = Self::local_addr(local_dst_offset);
+        let src = Self::remote_addr(remote_src_offset);
+        unsafe { ptr::copy(src as *const u8, dst as *mut u8, byte_size) };
+        Ok(dst)
+    }
+
+    fn atomic_cmp_exchg(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        old_value: usize,
+        new_value: usize,
+    ) -> io::Result<usize> {
+        self.atomic_cmp_exchg_sync(local_src_offset, remote_dst_offset, old_value, new_value, 0)
+    }
+
+    fn atomic_cmp_exchg_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        old_value: usize,
+        new_value: usize,
+        _thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        let local = Self::local_addr(local_src_offset);
+        let remote = unsafe { &*(Self::remote_addr(remote_dst_offset) as *const AtomicUsize) };
+        let old = match remote.compare_exchange(old_value, new_value, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(v) => v,
+            Err(v) => v,
+        };
+        unsafe { ptr::write_volatile(local as *mut usize, old) };
+        Ok(local)
+    }
+
+    fn atomic_fetch_add(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        add_value: usize,
+    ) -> io::Result<usize> {
+        self.atomic_fetch_add_sync(local_src_offset, remote_dst_offset, add_value, 0)
+    }
+
+    fn atomic_fetch_add_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        add_value: usize,
+        _thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        let local = Self::local_addr(local_src_offset);
+        let remote = unsafe { &*(Self::remote_addr(remote_dst_offset) as *const AtomicUsize) };
+        let old = remote.fetch_add(add_value, Ordering::SeqCst);
+        unsafe { ptr::write_volatile(local as *mut usize, old) };
+        Ok(local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{Allocator, Layout};
+
+    use super::*;
+    use crate::drust_std::{alloc::local_allocator, comm::transport, sim};
+
+    fn allocate_word() -> usize {
+        local_allocator().allocate(Layout::new::<usize>()).unwrap().as_mut_ptr() as usize
+    }
+
+    #[test]
+    fn reaches_the_heap_of_another_server() {
+        sim::test_cluster(2).run_test(async {
+            let (local, remote) = (allocate_word(), sim::on_server(1, allocate_word));
+            let (local_offset, remote_offset) =
+                unsafe { (local - LOCAL_HEAP_START, remote - GLOBAL_HEAP_START) };
+            let (local_word, remote_word) = (local as *mut usize, remote as *mut usize);
+            let word = std::mem::size_of::<usize>();
+            let transport = transport();
+            unsafe {
+                *local_word = 42;
+                transport.write(local_offset, remote_offset, word).unwrap();
+                assert_eq!(*remote_word, 42);
+
+                *remote_word = 7;
+                transport.read(local_offset, remote_offset, word).unwrap();
+                assert_eq!(*local_word, 7);
+
+                // Atomics leave the old remote value in the local word.
+                transport.atomic_fetch_add(local_offset, remote_offset, 3).unwrap();
+                assert_eq!((*local_word, *remote_word), (7, 10));
+                transport.atomic_cmp_exchg(local_offset, remote_offset, 9, 1).unwrap();
+                assert_eq!((*local_word, *remote_word), (10, 10));
+                transport.atomic_cmp_exchg(local_offset, remote_offset, 10, 1).unwrap();
+                assert_eq!((*local_word, *remote_word), (10, 1));
+            }
+        });
+    }
+}
@@ -1,57 +1,257 @@
-extern "C" {
-    pub fn drust_start_server(heap_start: usize, heap_size: usize, server_id: usize) -> i32;
-    pub fn drust_server_ready();
-    pub fn drust_disconnect() -> i32;
-    pub fn copy_mem(src_addr: usize, dst_addr: usize, byte_size: usize) -> i32;
-    pub fn register_mem(heap_start: usize, heap_size: usize) -> usize;
-    pub fn drust_write(
+pub mod local;
+#[cfg(feature = "rdma")]
+pub mod rdma;
+pub mod tcp;
+
+use std::{io, net::SocketAddr};
+
+use clap::ValueEnum;
+
+// All offsets follow the comm-lib convention: local offsets are relative to LOCAL_HEAP_START,
+// remote offsets are relative to GLOBAL_HEAP_START. Atomics leave the old remote value in the
+// local buffer at `local_src_offset`. Reads, writes and atomics return the local address.
+pub trait Transport: Send + Sync {
+    fn start_server(&self, heap_start: usize, heap_size: usize, server_idx: usize);
+    fn server_ready(&self);
+    fn disconnect(&self) -> i32;
+    fn register_mem(&self, heap_start: usize, heap_size: usize) -> usize;
+    fn write(
+        &self,
         local_src_offset: usize,
         remote_dst_offset: usize,
         byte_size: usize,
-    ) -> usize;
-    pub fn drust_read(local_dst_offset: usize, remote_src_offset: usize, byte_size: usize)
-        -> usize;
-    pub fn drust_write_sync(
+    ) -> io::Result<usize>;
+    fn read(
+        &self,
+        local_dst_offset: usize,
+        remote_src_offset: usize,
+        byte_size: usize,
+    ) -> io::Result<usize>;
+    fn write_sync(
+        &self,
         local_src_offset: usize,
         remote_dst_offset: usize,
         byte_size: usize,
         thread_flag_id: usize,
-    ) -> usize;
-    pub fn drust_read_sync(
+    ) -> io::Result<usize>;
+    fn read_sync(
+        &self,
         local_dst_offset: usize,
         remote_src_offset: usize,
         byte_size: usize,
         thread_flag_id: usize,
-    ) -> usize;
-    pub fn drust_atomic_cmp_exchg(
+    ) -> io::Result<usize>;
+    fn atomic_cmp_exchg(
+        &self,
         local_src_offset: usize,
         remote_dst_offset: usize,
         old_value: usize,
         new_value: usize,
-    ) -> usize;
-    pub fn drust_atomic_cmp_exchg_sync(
+    ) -> io::Result<usize>;
+    fn atomic_cmp_exchg_sync(
+        &self,
         local_src_offset: usize,
         remote_dst_offset: usize,
         old_value: usize,
         new_value: usize,
         thread_flag_id: usize,
-    ) -> usize;
-    pub fn drust_atomic_fetch_add(
+    ) -> io::Result<usize>;
+    fn atomic_fetch_add(
+        &self,
         local_src_offset: usize,
         remote_dst_offset: usize,
         add_value: usize,
-    ) -> usize;
-    pub fn drust_atomic_fetch_add_sync(
+    ) -> io::Result<usize>;
+    fn atomic_fetch_add_sync(
+        &self,
         local_src_offset: usize,
         remote_dst_offset: usize,
         add_value: usize,
         thread_flag_id: usize,
-    ) -> usize;
-    // pub fn drust_local_atomic_cmp_exchg_sync(
-    //   local_src_offset: usize,
-    //   remote_dst_offset: usize,
-    //   old_value: usize,
-    //   new_value: usize,
-    //   thread_flag_id: usize
-    // ) -> usize;
+    ) -> io::Result<usize>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TransportKind {
+    /// InfiniBand verbs through comm-lib
+    #[cfg(feature = "rdma")]
+    Rdma,
+    /// Plain TCP memory servers, for clusters without RDMA NICs
+    Tcp,
+    /// In-process memcpy and atomics, single server only
+    Local,
+}
+
+impl Default for TransportKind {
+    fn default() -> Self {
+        #[cfg(feature = "rdma")]
+        return TransportKind::Rdma;
+        #[cfg(not(feature = "rdma"))]
+        return TransportKind::Local;
+    }
+}
+
+impl TransportKind {
+    // Consecutive ports a server's mem_ip takes, starting at its own port: the tcp memory server
+    // listens on one, the rdma transport on one per peer.
+    #[cfg_attr(not(feature = "rdma"), allow(unused_variables))]
+    pub fn mem_ports(self, num_servers: usize) -> usize {
+        match self {
+            #[cfg(feature = "rdma")]
+            TransportKind::Rdma => num_servers.max(2) - 1,
+            TransportKind::Tcp => 1,
+            TransportKind::Local => 0,
+        }
+    }
+}
+
+pub static mut TRANSPORT: Option<Box<dyn Transport>> = None;
+
+pub fn init_transport(kind: TransportKind, mem_addrs: Vec<SocketAddr>) {
+    let transport: Box<dyn Transport> = match kind {
+        #[cfg(feature = "rdma")]
+        TransportKind::Rdma => Box::new(rdma::RdmaTransport::new(mem_addrs)),
+        TransportKind::Tcp => Box::new(tcp::TcpTransport::new(mem_addrs)),
+        TransportKind::Local => Box::new(local::LocalTransport),
+    };
+    unsafe {
+        TRANSPORT = Some(transport);
+    }
+}
+
+pub fn transport() -> &'static dyn Transport {
+    unsafe { TRANSPORT.as_ref().expect("transport is not initialized").as_ref() }
+}
+
+pub(crate) fn map_heap(heap_start: usize, heap_size: usize) -> usize {
+    let addr = unsafe {
+        libc::mmap(
+            heap_start as *mut libc::c_void,
+            heap_size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_FIXED | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+            -1,
+            0,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        panic!("failed to map heap at {:x} with size {:x}", heap_start, heap_size);
+    }
+    addr as usize
+}
+
+pub unsafe fn drust_start_server(heap_start: usize, heap_size: usize, server_id: usize) -> i32 {
+    transport().start_server(heap_start, heap_size, server_id);
+    0
+}
+
+pub unsafe fn drust_server_ready() {
+    transport().server_ready();
+}
+
+pub unsafe fn drust_disconnect() -> i32 {
+    transport().disconnect()
+}
+
+pub unsafe fn copy_mem(src_addr: usize, dst_addr: usize, byte_size: usize) -> i32 {
+    std::ptr::copy_nonoverlapping(src_addr as *const u8, dst_addr as *mut u8, byte_size);
+    0
+}
+
+pub unsafe fn register_mem(heap_start: usize, heap_size: usize) -> usize {
+    transport().register_mem(heap_start, heap_size)
+}
+
+// The callers have no way to go on without the remote memory, as with the verbs library, so a
+// failed access ends the process with the reason.
+fn failed(op: &str, e: io::Error) -> ! {
+    panic!("remote {} failed: {}", op, e)
+}
+
+pub unsafe fn drust_write(
+    local_src_offset: usize,
+    remote_dst_offset: usize,
+    byte_size: usize,
+) -> usize {
+    transport()
+        .write(local_src_offset, remote_dst_offset, byte_size)
+        .unwrap_or_else(|e| failed("write", e))
+}
+
+pub unsafe fn drust_read(local_dst_offset: usize, remote_src_offset: usize, byte_size: usize) -> usize {
+    transport()
+        .read(local_dst_offset, remote_src_offset, byte_size)
+        .unwrap_or_else(|e| failed("read", e))
+}
+
+pub unsafe fn drust_write_sync(
+    local_src_offset: usize,
+    remote_dst_offset: usize,
+    byte_size: usize,
+    thread_flag_id: usize,
+) -> usize {
+    transport()
+        .write_sync(local_src_offset, remote_dst_offset, byte_size, thread_flag_id)
+        .unwrap_or_else(|e| failed("write", e))
+}
+
+pub unsafe fn drust_read_sync(
+    local_dst_offset: usize,
+    remote_src_offset: usize,
+    byte_size: usize,
+    thread_flag_id: usize,
+) -> usize {
+    transport()
+        .read_sync(local_dst_offset, remote_src_offset, byte_size, thread_flag_id)
+        .unwrap_or_else(|e| failed("read", e))
+}
+
+pub unsafe fn drust_atomic_cmp_exchg(
+    local_src_offset: usize,
+    remote_dst_offset: usize,
+    old_value: usize,
+    new_value: usize,
+) -> usize {
+    transport()
+        .atomic_cmp_exchg(local_src_offset, remote_dst_offset, old_value, new_value)
+        .unwrap_or_else(|e| failed("compare-exchange", e))
+}
+
+pub unsafe fn drust_atomic_cmp_exchg_sync(
+    local_src_offset: usize,
+    remote_dst_offset: usize,
+    old_value: usize,
+    new_value: usize,
+    thread_flag_id: usize,
+) -> usize {
+    transport()
+        .atomic_cmp_exchg_sync(
+            local_src_offset,
+            remote_dst_offset,
+            old_value,
+            new_value,
+            thread_flag_id,
+        )
+        .unwrap_or_else(|e| failed("compare-exchange", e))
+}
+
+pub unsafe fn drust_atomic_fetch_add(
+    local_src_offset: usize,
+    remote_dst_offset: usize,
+    add_value: usize,
+) -> usize {
+    transport()
+        .atomic_fetch_add(local_src_offset, remote_dst_offset, add_value)
+        .unwrap_or_else(|e| failed("fetch-add", e))
+}
+
+pub unsafe fn drust_atomic_fetch_add_sync(
+    local_src_offset: usize,
+    remote_dst_offset: usize,
+    add_value: usize,
+    thread_flag_id: usize,
+) -> usize {
+    transport()
+        .atomic_fetch_add_sync(local_src_offset, remote_dst_offset, add_value, thread_flag_id)
+        .unwrap_or_else(|e| failed("fetch-add", e))
 }
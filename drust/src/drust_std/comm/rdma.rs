@@ -0,0 +1,201 @@
+use std::{ffi::CString, io, net::SocketAddr};
+
+use super::Transport;
+
+extern "C" {
+    pub fn drust_set_num_servers(num_servers: usize);
+    pub fn drust_set_server_addr(server_id: usize, ip: *const libc::c_char, base_port: u16);
+    pub fn drust_start_server(heap_start: usize, heap_size: usize, server_id: usize) -> i32;
+    pub fn drust_server_ready();
+    pub fn drust_disconnect() -> i32;
+    pub fn copy_mem(src_addr: usize, dst_addr: usize, byte_size: usize) -> i32;
+    pub fn register_mem(heap_start: usize, heap_size: usize) -> usize;
+    pub fn drust_write(
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        byte_size: usize,
+    ) -> usize;
+    pub fn drust_read(local_dst_offset: usize, remote_src_offset: usize, byte_size: usize)
+        -> usize;
+    pub fn drust_write_sync(
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        byte_size: usize,
+        thread_flag_id: usize,
+    ) -> usize;
+    pub fn drust_read_sync(
+        local_dst_offset: usize,
+        remote_src_offset: usize,
+        byte_size: usize,
+        thread_flag_id: usize,
+    ) -> usize;
+    pub fn drust_atomic_cmp_exchg(
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        old_value: usize,
+        new_value: usize,
+    ) -> usize;
+    pub fn drust_atomic_cmp_exchg_sync(
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        old_value: usize,
+        new_value: usize,
+        thread_flag_id: usize,
+    ) -> usize;
+    pub fn drust_atomic_fetch_add(
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        add_value: usize,
+    ) -> usize;
+    pub fn drust_atomic_fetch_add_sync(
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        add_value: usize,
+        thread_flag_id: usize,
+    ) -> usize;
+    // pub fn drust_local_atomic_cmp_exchg_sync(
+    //   local_src_offset: usize,
+    //   remote_dst_offset: usize,
+    //   old_value: usize,
+    //   new_value: usize,
+    //   thread_flag_id: usize
+    // ) -> usize;
+}
+
+// One-sided verbs through comm-lib (libmyrdma). Peers are wired up by the C library itself, each
+// server listening on its mem_ip at one port per peer.
+pub struct RdmaTransport {
+    mem_addrs: Vec<SocketAddr>,
+}
+
+impl RdmaTransport {
+    pub fn new(mem_addrs: Vec<SocketAddr>) -> Self {
+        RdmaTransport { mem_addrs }
+    }
+}
+
+impl Transport for RdmaTransport {
+    fn start_server(&self, heap_start: usize, heap_size: usize, server_idx: usize) {
+        unsafe {
+            drust_set_num_servers(crate::conf::NUM_SERVERS);
+            for (i, addr) in self.mem_addrs.iter().enumerate() {
+                let ip = CString::new(addr.ip().to_string()).unwrap();
+                drust_set_server_addr(i, ip.as_ptr(), addr.port());
+            }
+            drust_start_server(heap_start, heap_size, server_idx);
+        }
+    }
+
+    fn server_ready(&self) {
+        unsafe {
+            drust_server_ready();
+        }
+    }
+
+    fn disconnect(&self) -> i32 {
+        unsafe { drust_disconnect() }
+    }
+
+    fn register_mem(&self, heap_start: usize, heap_size: usize) -> usize {
+        unsafe { register_mem(heap_start, heap_size) }
+    }
+
+    fn write(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        byte_size: usize,
+    ) -> io::Result<usize> {
+        Ok(unsafe { drust_write(local_src_offset, remote_dst_offset, byte_size) })
+    }
+
+    fn read(
+        &self,
+        local_dst_offset: usize,
+        remote_src_offset: usize,
+        byte_size: usize,
+    ) -> io::Result<usize> {
+        Ok(unsafe { drust_read(local_dst_offset, remote_src_offset, byte_size) })
+    }
+
+    fn write_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        byte_size: usize,
+        thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        Ok(unsafe {
+            drust_write_sync(local_src_offset, remote_dst_offset, byte_size, thread_flag_id)
+        })
+    }
+
+    fn read_sync(
+        &self,
+        local_dst_offset: usize,
+        remote_src_offset: usize,
+        byte_size: usize,
+        thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        Ok(unsafe {
+            drust_read_sync(local_dst_offset, remote_src_offset, byte_size, thread_flag_id)
+        })
+    }
+
+    fn atomic_cmp_exchg(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        old_value: usize,
+        new_value: usize,
+    ) -> io::Result<usize> {
+        Ok(unsafe {
+            drust_atomic_cmp_exchg(local_src_offset, remote_dst_offset, old_value, new_value)
+        })
+    }
+
+    fn atomic_cmp_exchg_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        old_value: usize,
+        new_value: usize,
+        thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        Ok(unsafe {
+            drust_atomic_cmp_exchg_sync(
+                local_src_offset,
+                remote_dst_offset,
+                old_value,
+                new_value,
+                thread_flag_id,
+            )
+        })
+    }
+
+    fn atomic_fetch_add(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        add_value: usize,
+    ) -> io::Result<usize> {
+        Ok(unsafe { drust_atomic_fetch_add(local_src_offset, remote_dst_offset, add_value) })
+    }
+
+    fn atomic_fetch_add_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        add_value: usize,
+        thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        Ok(unsafe {
+            drust_atomic_fetch_add_sync(
+                local_src_offset,
+                remote_dst_offset,
+                add_value,
+                thread_flag_id,
+            )
+        })
+    }
+}
@@ -0,0 +1,460 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{map_heap, Transport};
+use crate::{
+    conf::*,
+    drust_std::connect::{
+        bootstrap::{Phase, DEFAULT_PHASE_TIMEOUT_SECS},
+        config::CONFIG,
+    },
+};
+
+const OP_READ: u8 = 0;
+const OP_WRITE: u8 = 1;
+const OP_CMP_EXCHG: u8 = 2;
+const OP_FETCH_ADD: u8 = 3;
+const HEADER_SIZE: usize = 1 + 8 * 4;
+// Every response starts with a status byte. A refused request gets STATUS_REFUSED, the length of
+// the reason as a u32 and the reason, and then the server closes the connection.
+const STATUS_OK: u8 = 0;
+const STATUS_REFUSED: u8 = 1;
+const CONNS_PER_PEER: usize = 16;
+const CONNECT_RETRY_MS: u64 = 200;
+
+// Software replacement for the verbs path: every server runs a small memory server that serves
+// one-sided reads, writes and atomics against its own slice of the global heap.
+pub struct TcpTransport {
+    mem_addrs: Vec<SocketAddr>,
+    peers: Vec<OnceLock<Vec<Mutex<TcpStream>>>>,
+    // Set once start_server is done, with the reason if it failed.
+    ready: Mutex<Option<Result<(), String>>>,
+    ready_cond: Condvar,
+}
+
+impl TcpTransport {
+    pub fn new(mem_addrs: Vec<SocketAddr>) -> Self {
+        let mut peers = Vec::with_capacity(mem_addrs.len());
+        for _ in 0..mem_addrs.len() {
+            peers.push(OnceLock::new());
+        }
+        TcpTransport {
+            mem_addrs,
+            peers,
+            ready: Mutex::new(None),
+            ready_cond: Condvar::new(),
+        }
+    }
+
+    fn peer(&self, remote_offset: usize, thread_flag_id: usize) -> io::Result<&Mutex<TcpStream>> {
+        let server_idx = remote_offset / unsafe { WORKER_UNIT_SIZE };
+        let conns = self.peers.get(server_idx).and_then(|peer| peer.get()).ok_or_else(|| {
+            let message = format!("no tcp connection to server {}", server_idx);
+            io::Error::new(io::ErrorKind::NotConnected, message)
+        })?;
+        Ok(&conns[thread_flag_id % conns.len()])
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn request(
+        &self,
+        op: u8,
+        remote_offset: usize,
+        byte_size: usize,
+        arg0: usize,
+        arg1: usize,
+        payload: Option<&[u8]>,
+        response: &mut [u8],
+        thread_flag_id: usize,
+    ) -> io::Result<()> {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0] = op;
+        header[1..9].copy_from_slice(&((unsafe { GLOBAL_HEAP_START } + remote_offset) as u64).to_le_bytes());
+        header[9..17].copy_from_slice(&(byte_size as u64).to_le_bytes());
+        header[17..25].copy_from_slice(&(arg0 as u64).to_le_bytes());
+        header[25..33].copy_from_slice(&(arg1 as u64).to_le_bytes());
+        let mut stream = self.peer(remote_offset, thread_flag_id)?.lock().unwrap();
+        stream.write_all(&header)?;
+        if let Some(payload) = payload {
+            stream.write_all(payload)?;
+        }
+        let mut status = [0u8; 1];
+        stream.read_exact(&mut status)?;
+        if status[0] != STATUS_OK {
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len)?;
+            let mut reason = vec![0u8; u32::from_le_bytes(len) as usize];
+            stream.read_exact(&mut reason)?;
+            let message = format!("memory server refused: {}", String::from_utf8_lossy(&reason));
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        stream.read_exact(response)
+    }
+
+    // Retries until the peer's memory server is up, for as long as the transport may take to
+    // come up during bootstrap.
+    fn connect(addr: SocketAddr, deadline: Instant) -> io::Result<TcpStream> {
+        loop {
+            match TcpStream::connect(addr) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    return Ok(stream);
+                }
+                Err(e) if Instant::now() >= deadline => {
+                    let message = format!("cannot reach memory server {}: {}", addr, e);
+                    return Err(io::Error::new(e.kind(), message));
+                }
+                Err(_) => thread::sleep(Duration::from_millis(CONNECT_RETRY_MS)),
+            }
+        }
+    }
+
+    fn start(&self, heap_start: usize, heap_size: usize, server_idx: usize) -> io::Result<()> {
+        map_heap(heap_start, heap_size);
+        let listener = TcpListener::bind(self.mem_addrs[server_idx])?;
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                thread::spawn(move || serve_connection(stream, heap_start, heap_size));
+            }
+        });
+        let timeout = match unsafe { CONFIG.as_ref() } {
+            Some(config) => config.phase_timeout(Phase::RdmaUp),
+            None => Duration::from_secs(DEFAULT_PHASE_TIMEOUT_SECS),
+        };
+        let deadline = Instant::now() + timeout;
+        for i in 0..self.mem_addrs.len() {
+            if i == server_idx {
+                continue;
+            }
+            let mut conns = Vec::with_capacity(CONNS_PER_PEER);
+            for _ in 0..CONNS_PER_PEER {
+                conns.push(Mutex::new(Self::connect(self.mem_addrs[i], deadline)?));
+            }
+            let _ = self.peers[i].set(conns);
+            println!("tcp transport connected to server {}", i);
+        }
+        Ok(())
+    }
+}
+
+// Checks a request against the local heap, so a bad address or op from a peer is refused instead
+// of touching memory outside it.
+fn check_request(
+    op: u8,
+    addr: usize,
+    byte_size: usize,
+    heap_start: usize,
+    heap_size: usize,
+) -> Result<(), String> {
+    if !matches!(op, OP_READ | OP_WRITE | OP_CMP_EXCHG | OP_FETCH_ADD) {
+        return Err(format!("unknown op {}", op));
+    }
+    let in_heap = match addr.checked_add(byte_size) {
+        Some(end) => addr >= heap_start && end <= heap_start + heap_size,
+        None => false,
+    };
+    if !in_heap {
+        return Err(format!("access {:x}+{:x} is outside the heap", addr, byte_size));
+    }
+    let atomic = matches!(op, OP_CMP_EXCHG | OP_FETCH_ADD);
+    if atomic && (byte_size != 8 || addr % 8 != 0) {
+        return Err(format!("atomic access {:x}+{:x} is not one aligned word", addr, byte_size));
+    }
+    Ok(())
+}
+
+fn refuse(stream: &mut TcpStream, reason: &str) {
+    eprintln!("tcp transport: refused request from {:?}: {}", stream.peer_addr(), reason);
+    let mut frame = vec![STATUS_REFUSED];
+    frame.extend_from_slice(&(reason.len() as u32).to_le_bytes());
+    frame.extend_from_slice(reason.as_bytes());
+    let _ = stream.write_all(&frame);
+}
+
+fn reply_word(stream: &mut TcpStream, word: usize) -> io::Result<()> {
+    stream.write_all(&[STATUS_OK])?;
+    stream.write_all(&(word as u64).to_le_bytes())
+}
+
+fn serve_connection(mut stream: TcpStream, heap_start: usize, heap_size: usize) {
+    if stream.set_nodelay(true).is_err() {
+        return;
+    }
+    let mut header = [0u8; HEADER_SIZE];
+    loop {
+        if stream.read_exact(&mut header).is_err() {
+            return;
+        }
+        let op = header[0];
+        let addr = u64::from_le_bytes(header[1..9].try_into().unwrap()) as usize;
+        let byte_size = u64::from_le_bytes(header[9..17].try_into().unwrap()) as usize;
+        let arg0 = u64::from_le_bytes(header[17..25].try_into().unwrap()) as usize;
+        let arg1 = u64::from_le_bytes(header[25..33].try_into().unwrap()) as usize;
+        if let Err(reason) = check_request(op, addr, byte_size, heap_start, heap_size) {
+            // A refused write's payload is still on the way, so the connection cannot go on.
+            refuse(&mut stream, &reason);
+            return;
+        }
+        let result = match op {
+            OP_READ => {
+                let data = unsafe { std::slice::from_raw_parts(addr as *const u8, byte_size) };
+                stream.write_all(&[STATUS_OK]).and_then(|_| stream.write_all(data))
+            }
+            OP_WRITE => {
+                let data = unsafe { std::slice::from_raw_parts_mut(addr as *mut u8, byte_size) };
+                match stream.read_exact(data) {
+                    Ok(_) => stream.write_all(&[STATUS_OK]),
+                    Err(e) => Err(e),
+                }
+            }
+            OP_CMP_EXCHG => {
+                let atomic = unsafe { &*(addr as *const AtomicUsize) };
+                let old = match atomic.compare_exchange(arg0, arg1, Ordering::SeqCst, Ordering::SeqCst) {
+                    Ok(v) => v,
+                    Err(v) => v,
+                };
+                reply_word(&mut stream, old)
+            }
+            _ => {
+                let atomic = unsafe { &*(addr as *const AtomicUsize) };
+                reply_word(&mut stream, atomic.fetch_add(arg0, Ordering::SeqCst))
+            }
+        };
+        if result.is_err() {
+            return;
+        }
+    }
+}
+
+impl Transport for TcpTransport {
+    fn start_server(&self, heap_start: usize, heap_size: usize, server_idx: usize) {
+        let result = self.start(heap_start, heap_size, server_idx);
+        *self.ready.lock().unwrap() = Some(result.map_err(|e| e.to_string()));
+        self.ready_cond.notify_all();
+    }
+
+    fn server_ready(&self) {
+        let mut ready = self.ready.lock().unwrap();
+        while ready.is_none() {
+            ready = self.ready_cond.wait(ready).unwrap();
+        }
+        if let Some(Err(e)) = ready.as_ref() {
+            panic!("tcp transport failed to start: {}", e);
+        }
+    }
+
+    fn disconnect(&self) -> i32 {
+        for peer in self.peers.iter() {
+            if let Some(conns) = peer.get() {
+                for conn in conns {
+                    let _ = conn.lock().unwrap().shutdown(std::net::Shutdown::Both);
+                }
+            }
+        }
+        0
+    }
+
+    fn register_mem(&self, heap_start: usize, heap_size: usize) -> usize {
+        map_heap(heap_start, heap_size)
+    }
+
+    fn write(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        byte_size: usize,
+    ) -> io::Result<usize> {
+        let tid = thread::current().id().as_u64().get() as usize;
+        self.write_sync(local_src_offset, remote_dst_offset, byte_size, tid)
+    }
+
+    fn read(
+        &self,
+        local_dst_offset: usize,
+        remote_src_offset: usize,
+        byte_size: usize,
+    ) -> io::Result<usize> {
+        let tid = thread::current().id().as_u64().get() as usize;
+        self.read_sync(local_dst_offset, remote_src_offset, byte_size, tid)
+    }
+
+    fn write_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        byte_size: usize,
+        thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        let local_addr = unsafe { LOCAL_HEAP_START } + local_src_offset;
+        let payload = unsafe { std::slice::from_raw_parts(local_addr as *const u8, byte_size) };
+        self.request(
+            OP_WRITE,
+            remote_dst_offset,
+            byte_size,
+            0,
+            0,
+            Some(payload),
+            &mut [],
+            thread_flag_id,
+        )?;
+        Ok(local_addr)
+    }
+
+    fn read_sync(
+        &self,
+        local_dst_offset: usize,
+        remote_src_offset: usize,
+        byte_size: usize,
+        thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        let local_addr = unsafe { LOCAL_HEAP_START } + local_dst_offset;
+        let buffer = unsafe { std::slice::from_raw_parts_mut(local_addr as *mut u8, byte_size) };
+        self.request(OP_READ, remote_src_offset, byte_size, 0, 0, None, buffer, thread_flag_id)?;
+        Ok(local_addr)
+    }
+
+    fn atomic_cmp_exchg(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        old_value: usize,
+        new_value: usize,
+    ) -> io::Result<usize> {
+        let tid = thread::current().id().as_u64().get() as usize;
+        self.atomic_cmp_exchg_sync(local_src_offset, remote_dst_offset, old_value, new_value, tid)
+    }
+
+    fn atomic_cmp_exchg_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        old_value: usize,
+        new_value: usize,
+        thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        let local_addr = unsafe { LOCAL_HEAP_START } + local_src_offset;
+        let buffer = unsafe { std::slice::from_raw_parts_mut(local_addr as *mut u8, 8) };
+        self.request(
+            OP_CMP_EXCHG,
+            remote_dst_offset,
+            8,
+            old_value,
+            new_value,
+            None,
+            buffer,
+            thread_flag_id,
+        )?;
+        Ok(local_addr)
+    }
+
+    fn atomic_fetch_add(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        add_value: usize,
+    ) -> io::Result<usize> {
+        let tid = thread::current().id().as_u64().get() as usize;
+        self.atomic_fetch_add_sync(local_src_offset, remote_dst_offset, add_value, tid)
+    }
+
+    fn atomic_fetch_add_sync(
+        &self,
+        local_src_offset: usize,
+        remote_dst_offset: usize,
+        add_value: usize,
+        thread_flag_id: usize,
+    ) -> io::Result<usize> {
+        let local_addr = unsafe { LOCAL_HEAP_START } + local_src_offset;
+        let buffer = unsafe { std::slice::from_raw_parts_mut(local_addr as *mut u8, 8) };
+        self.request(
+            OP_FETCH_ADD,
+            remote_dst_offset,
+            8,
+            add_value,
+            0,
+            None,
+            buffer,
+            thread_flag_id,
+        )?;
+        Ok(local_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An op, where it points relative to the heap start and the error it is refused with.
+    type Case = (u8, fn(usize) -> usize, &'static str);
+
+    // A memory server over two fresh words, a connection to it and the address of the words.
+    fn serve() -> (TcpStream, usize) {
+        let words: &'static mut [u64; 2] = Box::leak(Box::new([0u64; 2]));
+        let (heap_start, heap_size) = (words.as_mut_ptr() as usize, 16);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve_connection(stream, heap_start, heap_size);
+        });
+        (TcpStream::connect(addr).unwrap(), heap_start)
+    }
+
+    fn send(stream: &mut TcpStream, op: u8, addr: usize, byte_size: usize, arg0: usize) -> u8 {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0] = op;
+        header[1..9].copy_from_slice(&(addr as u64).to_le_bytes());
+        header[9..17].copy_from_slice(&(byte_size as u64).to_le_bytes());
+        header[17..25].copy_from_slice(&(arg0 as u64).to_le_bytes());
+        stream.write_all(&header).unwrap();
+        let mut status = [0u8; 1];
+        stream.read_exact(&mut status).unwrap();
+        status[0]
+    }
+
+    fn read_word(stream: &mut TcpStream) -> u64 {
+        let mut word = [0u8; 8];
+        stream.read_exact(&mut word).unwrap();
+        u64::from_le_bytes(word)
+    }
+
+    #[test]
+    fn serves_atomics_in_the_heap() {
+        let (mut stream, heap_start) = serve();
+        assert_eq!(send(&mut stream, OP_FETCH_ADD, heap_start + 8, 8, 3), STATUS_OK);
+        assert_eq!(read_word(&mut stream), 0);
+        assert_eq!(send(&mut stream, OP_READ, heap_start + 8, 8, 0), STATUS_OK);
+        assert_eq!(read_word(&mut stream), 3);
+    }
+
+    #[test]
+    fn refuses_requests_outside_the_heap() {
+        let cases: [Case; 5] = [
+            (OP_READ, |start| start + 12, "outside the heap"),
+            (OP_WRITE, |start| start - 8, "outside the heap"),
+            (OP_READ, |_| usize::MAX - 3, "outside the heap"),
+            (OP_FETCH_ADD, |start| start + 4, "not one aligned word"),
+            (9, |start| start, "unknown op 9"),
+        ];
+        for (op, addr, expected) in cases {
+            let (mut stream, heap_start) = serve();
+            assert_eq!(send(&mut stream, op, addr(heap_start), 8, 0), STATUS_REFUSED);
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len).unwrap();
+            let mut reason = vec![0u8; u32::from_le_bytes(len) as usize];
+            stream.read_exact(&mut reason).unwrap();
+            let reason = String::from_utf8(reason).unwrap();
+            assert!(reason.contains(expected), "{}", reason);
+            // The server hangs up after a refusal.
+            assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+        }
+    }
+}
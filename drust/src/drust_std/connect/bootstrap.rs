@@ -0,0 +1,159 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+use futures::future;
+use serde::{Deserialize, Serialize};
+use tarpc::{client, context};
+use tokio::time::sleep;
+
+use super::{
+    codec::{self, DTransport},
+    config::CONFIG,
+    dsafepoint::{set_ready, DSafePointClient},
+};
+use crate::{dprintln, drust_std::sim};
+
+pub const DEFAULT_PHASE_TIMEOUT_SECS: u64 = 120;
+const RETRY_START_MS: u64 = 50;
+const RETRY_MAX_MS: u64 = 1000;
+
+// Startup phases, in order. A server marks a phase in its READY_MAP once it reached the phase
+// locally, and barrier() waits until every server has marked it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    RdmaUp,
+    AllocServerUp,
+    AllocClientsConnected,
+    AppServerUp,
+    AppClientsConnected,
+}
+
+impl Phase {
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::RdmaUp => "rdma up",
+            Phase::AllocServerUp => "alloc server up",
+            Phase::AllocClientsConnected => "alloc clients connected",
+            Phase::AppServerUp => "app server up",
+            Phase::AppClientsConnected => "app clients connected",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub fn phase_timeout(phase: Phase) -> Duration {
+    match unsafe { CONFIG.as_ref() } {
+        Some(config) => config.phase_timeout(phase),
+        None => Duration::from_secs(DEFAULT_PHASE_TIMEOUT_SECS),
+    }
+}
+
+pub fn set_phase(phase: Phase) {
+    dprintln!("reached phase {}", phase);
+    set_ready(phase.index());
+}
+
+pub fn bootstrap_failed(e: anyhow::Error) -> ! {
+    eprintln!("drust: bootstrap failed: {:#}", e);
+    std::process::exit(1);
+}
+
+// Connects to a tarpc server, retrying with exponential backoff until `deadline` while the
+// remote side is still starting up. A failed handshake means the peer runs an incompatible
+// build, so it is reported right away.
+pub async fn connect_transport<Item, SinkItem>(
+    addr: SocketAddr,
+    service: &str,
+    server_idx: usize,
+    deadline: Instant,
+) -> anyhow::Result<DTransport<Item, SinkItem>>
+where
+    Item: for<'de> Deserialize<'de>,
+    SinkItem: Serialize,
+{
+    let mut backoff = Duration::from_millis(RETRY_START_MS);
+    loop {
+        match codec::connect(addr).await {
+            Ok(transport) => return Ok(transport),
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                bail!("server {} {} at {}: {}", server_idx, service, addr, e);
+            }
+            Err(e) => {
+                if Instant::now() + backoff > deadline {
+                    bail!("server {} {} at {} is unreachable: {}", server_idx, service, addr, e);
+                }
+                dprintln!("retrying {} of server {} in {:?}: {}", service, server_idx, backoff, e);
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_millis(RETRY_MAX_MS));
+            }
+        }
+    }
+}
+
+async fn wait_server(
+    server_idx: usize,
+    addr: SocketAddr,
+    ready_idx: usize,
+    deadline: Instant,
+) -> anyhow::Result<()> {
+    let transport = connect_transport(addr, "safepoint server", server_idx, deadline).await?;
+    let client = DSafePointClient::new(client::Config::default(), transport).spawn();
+    let mut ctx = context::current();
+    ctx.deadline = SystemTime::now() + deadline.saturating_duration_since(Instant::now());
+    client
+        .wait_ready(ctx, ready_idx)
+        .await
+        .map_err(|e| anyhow!("server {} did not come up: {}", server_idx, e))
+}
+
+// Waits until every server has marked `ready_idx`, and names all servers that did not in time.
+pub(crate) async fn wait_all(
+    safepoint_addrs: &[SocketAddr],
+    ready_idx: usize,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    if sim::is_active() {
+        sim::wait_all_ready(ready_idx).await;
+        return Ok(());
+    }
+    let deadline = Instant::now() + timeout;
+    let waits = safepoint_addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| wait_server(i, *addr, ready_idx, deadline));
+    let errors: Vec<String> = future::join_all(waits)
+        .await
+        .into_iter()
+        .filter_map(|r| r.err().map(|e| format!("{:#}", e)))
+        .collect();
+    if !errors.is_empty() {
+        bail!("{}", errors.join("; "));
+    }
+    Ok(())
+}
+
+pub async fn barrier(safepoint_addrs: &[SocketAddr], phase: Phase) -> anyhow::Result<()> {
+    let timeout = phase_timeout(phase);
+    wait_all(safepoint_addrs, phase.index(), timeout)
+        .await
+        .map_err(|e| anyhow!("phase {} not reached within {:?}: {:#}", phase, timeout, e))?;
+    dprintln!("barrier {} done", phase);
+    Ok(())
+}
+
+pub async fn sync_phase(safepoint_addrs: &[SocketAddr], phase: Phase) {
+    if let Err(e) = barrier(safepoint_addrs, phase).await {
+        bootstrap_failed(e);
+    }
+}
@@ -0,0 +1,269 @@
+use std::{error::Error, fmt, io, marker::PhantomData, net::SocketAddr, pin::Pin, time::Duration};
+
+use bincode::Options;
+use bytes::{Bytes, BytesMut};
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tarpc::{
+    serde_transport::{self, Transport},
+    tokio_serde::{Deserializer, Serializer},
+    tokio_util::codec::{Framed, LengthDelimitedCodec},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use super::fingerprint::{self, Fingerprint, FINGERPRINT_SIZE};
+
+// Bumped whenever the framing or a tarpc service signature changes, so mixed builds fail at
+// connect time instead of with decode errors in the middle of a run.
+pub const WIRE_VERSION: u16 = 13;
+const MAGIC: [u8; 4] = *b"DRST";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// Handshakes that may be in flight at once on one listener.
+const MAX_PENDING_HANDSHAKES: usize = 64;
+// Largest control-plane message. Shipped futures and task outputs are far smaller; bulk data
+// moves through the transport. A length prefix above this closes the connection before anything
+// is allocated for it.
+pub const MAX_FRAME_LENGTH: usize = 64 << 20;
+
+// Encoding of the tarpc control plane. Every server of a cluster must use the same one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    // Readable, but every byte of a Vec<u8> becomes a decimal number. Useful for debugging.
+    Json,
+    #[default]
+    Bincode,
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireFormat::Json => write!(f, "json"),
+            WireFormat::Bincode => write!(f, "bincode"),
+        }
+    }
+}
+
+pub static mut WIRE_FORMAT: WireFormat = WireFormat::Bincode;
+
+pub type DTransport<Item, SinkItem> = Transport<TcpStream, Item, SinkItem, WireCodec<Item, SinkItem>>;
+
+// tokio_serde codec that encodes with the format picked in drust.json.
+pub struct WireCodec<Item, SinkItem> {
+    format: WireFormat,
+    ghost: PhantomData<fn(SinkItem) -> Item>,
+}
+
+impl<Item, SinkItem> WireCodec<Item, SinkItem> {
+    pub fn new(format: WireFormat) -> Self {
+        WireCodec {
+            format,
+            ghost: PhantomData,
+        }
+    }
+}
+
+// Also bounds what a length inside a message can make bincode allocate.
+fn bincode_options() -> impl Options {
+    bincode::options().with_limit(MAX_FRAME_LENGTH as u64)
+}
+
+fn invalid_data(e: impl Into<Box<dyn Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+impl<Item, SinkItem: Serialize> Serializer<SinkItem> for WireCodec<Item, SinkItem> {
+    type Error = io::Error;
+
+    fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> io::Result<Bytes> {
+        match self.format {
+            WireFormat::Json => serde_json::to_vec(item).map(Bytes::from).map_err(invalid_data),
+            WireFormat::Bincode => {
+                bincode_options().serialize(item).map(Bytes::from).map_err(invalid_data)
+            }
+        }
+    }
+}
+
+impl<Item: for<'de> Deserialize<'de>, SinkItem> Deserializer<Item> for WireCodec<Item, SinkItem> {
+    type Error = io::Error;
+
+    fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> io::Result<Item> {
+        match self.format {
+            WireFormat::Json => serde_json::from_slice(src).map_err(invalid_data),
+            WireFormat::Bincode => bincode_options().deserialize(src).map_err(invalid_data),
+        }
+    }
+}
+
+fn header(format: WireFormat) -> [u8; 8] {
+    let version = WIRE_VERSION.to_le_bytes();
+    let format = match format {
+        WireFormat::Json => 0,
+        WireFormat::Bincode => 1,
+    };
+    [MAGIC[0], MAGIC[1], MAGIC[2], MAGIC[3], version[0], version[1], format, 0]
+}
+
+fn describe(header: &[u8; 8]) -> String {
+    if header[..4] != MAGIC {
+        return "no drust handshake".to_string();
+    }
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    match header[6] {
+        0 => format!("wire version {} (json)", version),
+        1 => format!("wire version {} (bincode)", version),
+        other => format!("wire version {} (format {})", version, other),
+    }
+}
+
+// Both sides send their header and build fingerprint before anything else, then compare them
+// with the peer's. A mismatch is reported as InvalidData and is not worth retrying.
+async fn handshake(stream: &mut TcpStream, format: WireFormat) -> io::Result<()> {
+    let ours = header(format);
+    let our_build = fingerprint::local();
+    stream.write_all(&ours).await?;
+    stream.write_all(&our_build.to_bytes()).await?;
+    let mut theirs = [0u8; 8];
+    stream.read_exact(&mut theirs).await?;
+    if theirs != ours {
+        return Err(invalid_data(format!(
+            "peer speaks {}, this binary speaks {}",
+            describe(&theirs),
+            describe(&ours)
+        )));
+    }
+    let mut their_build = [0u8; FINGERPRINT_SIZE];
+    stream.read_exact(&mut their_build).await?;
+    if let Some(mismatch) = our_build.mismatch(&Fingerprint::from_bytes(&their_build)) {
+        return Err(invalid_data(format!("peer runs a different build: {}", mismatch)));
+    }
+    Ok(())
+}
+
+fn frames() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .max_frame_length(MAX_FRAME_LENGTH)
+        .new_codec()
+}
+
+async fn framed<Item, SinkItem>(mut stream: TcpStream) -> io::Result<DTransport<Item, SinkItem>>
+where
+    Item: for<'de> Deserialize<'de>,
+    SinkItem: Serialize,
+{
+    let format = unsafe { WIRE_FORMAT };
+    stream.set_nodelay(true)?;
+    timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream, format))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "handshake timed out"))??;
+    Ok(serde_transport::new(Framed::new(stream, frames()), WireCodec::new(format)))
+}
+
+pub async fn connect<Item, SinkItem>(addr: SocketAddr) -> io::Result<DTransport<Item, SinkItem>>
+where
+    Item: for<'de> Deserialize<'de>,
+    SinkItem: Serialize,
+{
+    framed(TcpStream::connect(addr).await?).await
+}
+
+// Accepts connections on `addr`. Peers that fail the handshake show up as errors in the stream.
+// Handshakes run concurrently, so a slow peer does not hold up the ones behind it.
+pub async fn listen<Item, SinkItem>(
+    addr: SocketAddr,
+) -> io::Result<impl Stream<Item = io::Result<DTransport<Item, SinkItem>>>>
+where
+    Item: for<'de> Deserialize<'de>,
+    SinkItem: Serialize,
+{
+    let listener = TcpListener::bind(addr).await?;
+    let accepted = stream::unfold(listener, |listener| async move {
+        let accepted = listener.accept().await;
+        Some((accepted, listener))
+    });
+    Ok(Box::pin(
+        accepted
+            .map(|accepted| async move {
+                let (stream, peer) = accepted?;
+                framed(stream).await.map_err(|e| {
+                    eprintln!("drust: rejected connection from {}: {}", peer, e);
+                    e
+                })
+            })
+            .buffer_unordered(MAX_PENDING_HANDSHAKES),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tarpc::tokio_util::codec::Decoder;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Message {
+        server_idx: usize,
+        name: String,
+        bytes: Vec<u8>,
+    }
+
+    fn round_trip(format: WireFormat) {
+        let message = Message {
+            server_idx: 3,
+            name: "gemm".to_string(),
+            bytes: (0..=255).collect(),
+        };
+        let mut codec = WireCodec::<Message, Message>::new(format);
+        let encoded = Pin::new(&mut codec).serialize(&message).unwrap();
+        let decoded = Pin::new(&mut codec).deserialize(&BytesMut::from(&encoded[..])).unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip(WireFormat::Json);
+    }
+
+    #[test]
+    fn bincode_round_trip() {
+        round_trip(WireFormat::Bincode);
+    }
+
+    #[test]
+    fn bincode_rejects_a_length_past_the_limit() {
+        let mut codec = WireCodec::<Vec<u8>, Vec<u8>>::new(WireFormat::Bincode);
+        let huge = bincode::options().serialize(&(MAX_FRAME_LENGTH as u64 + 1)).unwrap();
+        let e = Pin::new(&mut codec).deserialize(&BytesMut::from(&huge[..])).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn frames_past_the_limit_are_refused() {
+        let mut src = BytesMut::from(&(MAX_FRAME_LENGTH as u32 + 1).to_be_bytes()[..]);
+        assert!(frames().decode(&mut src).is_err());
+    }
+
+    #[test]
+    fn mismatched_formats_fail_the_handshake() {
+        Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let client = tokio::spawn(async move {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                handshake(&mut stream, WireFormat::Json).await
+            });
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let e = handshake(&mut stream, WireFormat::Bincode).await.unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            let expected = format!("peer speaks wire version {} (json)", WIRE_VERSION);
+            assert!(e.to_string().contains(&expected), "{}", e);
+            assert!(client.await.unwrap().is_err());
+        });
+    }
+}
@@ -0,0 +1,376 @@
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    bootstrap::{Phase, DEFAULT_PHASE_TIMEOUT_SECS},
+    codec::WireFormat,
+};
+use crate::{
+    conf::*,
+    drust_std::{
+        alloc::oom::{OomPolicy, DEFAULT_OOM_WAIT_MS},
+        comm::TransportKind,
+    },
+};
+
+// Layout of drust.json. Every server needs an app, alloc and safepoint address (plus a mem
+// address for the tcp transport). Each one is either given in full, e.g. "alloc_ip", or derived
+// from the server's "host" and the cluster-wide "ports".
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerEntry {
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    ip: Option<String>,
+    #[serde(default)]
+    alloc_ip: Option<String>,
+    #[serde(default)]
+    safepoint_ip: Option<String>,
+    #[serde(default)]
+    mem_ip: Option<String>,
+    // Overrides the cluster-wide compute_slots for this server.
+    #[serde(default)]
+    compute_slots: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct PortEntry {
+    app: u16,
+    alloc: u16,
+    safepoint: u16,
+    mem: u16,
+}
+
+impl Default for PortEntry {
+    fn default() -> Self {
+        PortEntry {
+            app: 36758,
+            alloc: 36759,
+            safepoint: 36760,
+            mem: 36761,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    servers: Vec<ServerEntry>,
+    #[serde(default)]
+    ports: PortEntry,
+    #[serde(default)]
+    unit_heap_size_gb: Option<usize>,
+    #[serde(default)]
+    heap_start: Option<usize>,
+    #[serde(default)]
+    worker_threads: Option<usize>,
+    #[serde(default)]
+    dataset_dir: Option<String>,
+    #[serde(default)]
+    log_dir: Option<String>,
+    #[serde(default)]
+    log_level: Option<LogLevel>,
+    #[serde(default)]
+    bootstrap_timeout_secs: Option<u64>,
+    #[serde(default)]
+    phase_timeouts_secs: HashMap<Phase, u64>,
+    #[serde(default)]
+    wire_format: WireFormat,
+    #[serde(default)]
+    oom_policy: OomPolicy,
+    #[serde(default)]
+    oom_wait_ms: Option<u64>,
+    // Compute slots of every server, instead of the one per tokio worker it reports.
+    #[serde(default)]
+    compute_slots: Option<usize>,
+}
+
+pub struct ServerAddrs {
+    pub app: SocketAddr,
+    pub alloc: SocketAddr,
+    pub safepoint: SocketAddr,
+    pub mem: Option<SocketAddr>,
+}
+
+// Validated configuration, shared by every part of the runtime once prepare has run.
+pub struct ClusterConfig {
+    pub servers: Vec<ServerAddrs>,
+    pub unit_heap_size_gb: usize,
+    pub heap_start: usize,
+    pub worker_threads: Option<usize>,
+    pub dataset_dir: String,
+    pub log_dir: String,
+    pub log_level: LogLevel,
+    pub bootstrap_timeout: Duration,
+    pub phase_timeouts: HashMap<Phase, Duration>,
+    pub wire_format: WireFormat,
+    pub oom_policy: OomPolicy,
+    pub oom_wait: Duration,
+    // Configured compute slots of each server, if any.
+    pub compute_slots: Vec<Option<usize>>,
+}
+
+impl ClusterConfig {
+    pub fn phase_timeout(&self, phase: Phase) -> Duration {
+        self.phase_timeouts.get(&phase).copied().unwrap_or(self.bootstrap_timeout)
+    }
+}
+
+pub static mut CONFIG: Option<ClusterConfig> = None;
+// Read by dprintln! on every call, so kept apart from CONFIG.
+pub static mut LOG_LEVEL: LogLevel = LogLevel::Info;
+
+pub fn config() -> &'static ClusterConfig {
+    unsafe { CONFIG.as_ref().expect("cluster config is not loaded") }
+}
+
+fn home_dir(sub_dir: &str) -> String {
+    format!("{}/DRust_home/{}", dirs::home_dir().unwrap().display(), sub_dir)
+}
+
+pub fn dataset_dir() -> String {
+    match unsafe { CONFIG.as_ref() } {
+        Some(config) => config.dataset_dir.clone(),
+        None => home_dir("dataset"),
+    }
+}
+
+pub fn log_dir() -> String {
+    match unsafe { CONFIG.as_ref() } {
+        Some(config) => config.log_dir.clone(),
+        None => home_dir("logs"),
+    }
+}
+
+impl ConfigFile {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("cannot read {}", path))?;
+        serde_json::from_str(&text).with_context(|| format!("invalid {}", path))
+    }
+
+    fn server_addr(
+        &self,
+        idx: usize,
+        field: &str,
+        addr: &Option<String>,
+        port: u16,
+    ) -> anyhow::Result<Option<SocketAddr>> {
+        let addr = match (addr, &self.servers[idx].host) {
+            (Some(addr), _) => addr.clone(),
+            (None, Some(host)) => format!("{}:{}", host, port),
+            (None, None) => return Ok(None),
+        };
+        addr.parse()
+            .map(Some)
+            .map_err(|_| anyhow!("server {} {}: invalid socket address \"{}\"", idx, field, addr))
+    }
+
+    fn required_addr(
+        &self,
+        idx: usize,
+        field: &str,
+        addr: &Option<String>,
+        port: u16,
+    ) -> anyhow::Result<SocketAddr> {
+        self.server_addr(idx, field, addr, port)?
+            .ok_or_else(|| anyhow!("server {} missing {}", idx, field))
+    }
+
+    pub fn validate(self, transport: TransportKind) -> anyhow::Result<ClusterConfig> {
+        if self.servers.is_empty() {
+            bail!("servers is empty");
+        }
+        if self.servers.len() > MAX_SERVERS {
+            bail!("servers lists {} servers, at most {} are supported", self.servers.len(), MAX_SERVERS);
+        }
+        let mem_ports = transport.mem_ports(self.servers.len());
+        let mut servers = Vec::with_capacity(self.servers.len());
+        for (i, server) in self.servers.iter().enumerate() {
+            let mem = self.server_addr(i, "mem_ip", &server.mem_ip, self.ports.mem)?;
+            match mem {
+                None if mem_ports > 0 => {
+                    let name = format!("{:?}", transport).to_lowercase();
+                    bail!("server {} missing mem_ip, required by the {} transport", i, name);
+                }
+                Some(mem) if mem.port() as usize + mem_ports > u16::MAX as usize + 1 => {
+                    let port = mem.port();
+                    bail!("server {} mem_ip port {} leaves no room for {} ports", i, port, mem_ports);
+                }
+                _ => {}
+            }
+            servers.push(ServerAddrs {
+                app: self.required_addr(i, "ip", &server.ip, self.ports.app)?,
+                alloc: self.required_addr(i, "alloc_ip", &server.alloc_ip, self.ports.alloc)?,
+                safepoint: self.required_addr(i, "safepoint_ip", &server.safepoint_ip, self.ports.safepoint)?,
+                mem,
+            });
+        }
+
+        let mut used: HashMap<SocketAddr, String> = HashMap::new();
+        for (i, server) in servers.iter().enumerate() {
+            let fields = [
+                ("ip", Some(server.app)),
+                ("alloc_ip", Some(server.alloc)),
+                ("safepoint_ip", Some(server.safepoint)),
+                ("mem_ip", server.mem),
+            ];
+            for (field, addr) in fields {
+                let Some(addr) = addr else { continue };
+                let owner = format!("server {} {}", i, field);
+                if let Some(prev) = used.insert(addr, owner.clone()) {
+                    bail!("duplicate port {} on {} ({} and {})", addr.port(), addr.ip(), prev, owner);
+                }
+            }
+            // The rest of the ports the transport takes from mem_ip on.
+            let Some(mem) = server.mem else { continue };
+            for k in 1..mem_ports {
+                let addr = SocketAddr::new(mem.ip(), mem.port() + k as u16);
+                let owner = format!("server {} mem_ip+{}", i, k);
+                if let Some(prev) = used.insert(addr, owner.clone()) {
+                    bail!("duplicate port {} on {} ({} and {})", addr.port(), addr.ip(), prev, owner);
+                }
+            }
+        }
+
+        let unit_heap_size_gb = self.unit_heap_size_gb.unwrap_or(DEFAULT_UNIT_HEAP_SIZE_GB);
+        if unit_heap_size_gb == 0 {
+            bail!("unit_heap_size_gb must be at least 1");
+        }
+        let heap_start = self.heap_start.unwrap_or(DEFAULT_GLOBAL_HEAP_START);
+        if heap_start % 4096 != 0 {
+            bail!("heap_start {:#x} is not page aligned", heap_start);
+        }
+        if self.worker_threads == Some(0) {
+            bail!("worker_threads must be at least 1");
+        }
+        let bootstrap_timeout_secs = self.bootstrap_timeout_secs.unwrap_or(DEFAULT_PHASE_TIMEOUT_SECS);
+        if bootstrap_timeout_secs == 0 {
+            bail!("bootstrap_timeout_secs must be at least 1");
+        }
+        let oom_wait_ms = self.oom_wait_ms.unwrap_or(DEFAULT_OOM_WAIT_MS);
+        if oom_wait_ms == 0 {
+            bail!("oom_wait_ms must be at least 1");
+        }
+        let compute_slots: Vec<Option<usize>> = self
+            .servers
+            .iter()
+            .map(|server| server.compute_slots.or(self.compute_slots))
+            .collect();
+        if let Some(i) = compute_slots.iter().position(|slots| *slots == Some(0)) {
+            bail!("server {} compute_slots must be at least 1", i);
+        }
+        if let Some((phase, _)) = self.phase_timeouts_secs.iter().find(|(_, secs)| **secs == 0) {
+            let key = serde_json::to_string(phase).unwrap();
+            bail!("phase_timeouts_secs.{} must be at least 1", key.trim_matches('"'));
+        }
+        Ok(ClusterConfig {
+            servers,
+            unit_heap_size_gb,
+            heap_start,
+            worker_threads: self.worker_threads,
+            dataset_dir: self.dataset_dir.unwrap_or_else(|| home_dir("dataset")),
+            log_dir: self.log_dir.unwrap_or_else(|| home_dir("logs")),
+            log_level: self.log_level.unwrap_or(LogLevel::Info),
+            bootstrap_timeout: Duration::from_secs(bootstrap_timeout_secs),
+            phase_timeouts: self
+                .phase_timeouts_secs
+                .into_iter()
+                .map(|(phase, secs)| (phase, Duration::from_secs(secs)))
+                .collect(),
+            wire_format: self.wire_format,
+            oom_policy: self.oom_policy,
+            oom_wait: Duration::from_millis(oom_wait_ms),
+            compute_slots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(json: &str, transport: TransportKind) -> anyhow::Result<ClusterConfig> {
+        serde_json::from_str::<ConfigFile>(json).unwrap().validate(transport)
+    }
+
+    #[test]
+    fn accepts_hosts_with_the_default_ports() {
+        let config = validate(
+            r#"{"servers": [{"host": "10.0.0.1"}, {"host": "10.0.0.2", "compute_slots": 3}]}"#,
+            TransportKind::Tcp,
+        )
+        .unwrap();
+        assert_eq!(config.servers.len(), 2);
+        assert_eq!(config.servers[1].alloc, "10.0.0.2:36759".parse().unwrap());
+        assert_eq!(config.servers[1].mem, Some("10.0.0.2:36761".parse().unwrap()));
+        assert_eq!(config.compute_slots, vec![None, Some(3)]);
+        assert_eq!(config.heap_start, DEFAULT_GLOBAL_HEAP_START);
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let one = r#""servers": [{"host": "10.0.0.1"}]"#;
+        let hosts: Vec<String> = (1..=17).map(|i| format!(r#"{{"host": "10.0.0.{}"}}"#, i)).collect();
+        let cases = [
+            (r#""servers": []"#.to_string(), "servers is empty"),
+            (format!(r#""servers": [{}]"#, hosts.join(",")), "at most 16 are supported"),
+            (r#""servers": [{"ip": "10.0.0.1:1"}]"#.to_string(), "server 0 missing alloc_ip"),
+            (
+                r#""servers": [{"host": "10.0.0.1", "ip": "nowhere"}]"#.to_string(),
+                "server 0 ip: invalid socket address",
+            ),
+            (
+                r#""servers": [{"host": "10.0.0.1", "alloc_ip": "10.0.0.1:36758"}]"#.to_string(),
+                "duplicate port 36758",
+            ),
+            (format!(r#"{}, "unit_heap_size_gb": 0"#, one), "unit_heap_size_gb must be at least 1"),
+            (format!(r#"{}, "heap_start": 4097"#, one), "is not page aligned"),
+            (format!(r#"{}, "worker_threads": 0"#, one), "worker_threads must be at least 1"),
+            (format!(r#"{}, "compute_slots": 0"#, one), "server 0 compute_slots must be at least 1"),
+            (
+                r#""servers": [{"host": "10.0.0.1"}, {"host": "10.0.0.2", "compute_slots": 0}]"#
+                    .to_string(),
+                "server 1 compute_slots must be at least 1",
+            ),
+            (
+                format!(r#"{}, "phase_timeouts_secs": {{"alloc_server_up": 0}}"#, one),
+                "phase_timeouts_secs.alloc_server_up must be at least 1",
+            ),
+            (
+                format!(r#"{}, "bootstrap_timeout_secs": 0"#, one),
+                "bootstrap_timeout_secs must be at least 1",
+            ),
+            (format!(r#"{}, "oom_wait_ms": 0"#, one), "oom_wait_ms must be at least 1"),
+        ];
+        for (fields, expected) in cases {
+            let json = format!("{{{}}}", fields);
+            match validate(&json, TransportKind::Local) {
+                Ok(_) => panic!("{} was accepted, expected \"{}\"", json, expected),
+                Err(e) => assert!(e.to_string().contains(expected), "{}: got \"{}\"", json, e),
+            }
+        }
+    }
+
+    #[test]
+    fn tcp_needs_a_mem_address() {
+        let json = r#"{"servers": [
+            {"ip": "1.1.1.1:1", "alloc_ip": "1.1.1.1:2", "safepoint_ip": "1.1.1.1:3"}
+        ]}"#;
+        assert!(validate(json, TransportKind::Local).is_ok());
+        let e = validate(json, TransportKind::Tcp).err().unwrap();
+        assert_eq!(e.to_string(), "server 0 missing mem_ip, required by the tcp transport");
+    }
+}
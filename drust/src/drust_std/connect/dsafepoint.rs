@@ -7,14 +7,23 @@ use std::{
     net::SocketAddr,
     ops::{Add, Rem},
     ptr::NonNull,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 // use rand::distributions::Distribution;
-use crate::dprintln;
+use super::{
+    bootstrap::{connect_transport, wait_all, DEFAULT_PHASE_TIMEOUT_SECS},
+    codec,
+};
+use crate::{
+    dprintln,
+    drust_std::{
+        sim,
+        thread::capacity::{self, Capacity},
+    },
+};
 use tarpc::{
     client, context,
     server::{self, incoming::Incoming, Channel},
-    tokio_serde::formats::Json,
 };
 use tokio::{runtime::Runtime, time::sleep};
 
@@ -26,6 +35,8 @@ pub trait DSafePoint {
     async fn unset_ready(idx: usize);
     async fn reset();
     async fn shutdown();
+    // CPUs, worker threads, compute slots and queue depth of this server.
+    async fn capacity() -> Capacity;
 }
 
 #[derive(Clone)]
@@ -76,15 +87,14 @@ impl DSafePoint for DSafePointServer {
             }
         }
     }
+    async fn capacity(self, _: context::Context) -> Capacity {
+        capacity::local()
+    }
 }
 
+// READY_MAP must be set up with init_ready_map before the server starts.
 pub async fn start_safepoint_server(server_addr: SocketAddr) -> anyhow::Result<()> {
-    unsafe {
-        READY_MAP = Some(Arc::new(DashMap::new()));
-    }
-
-    let mut listener = tarpc::serde_transport::tcp::listen(&server_addr, Json::default).await?;
-    listener.config_mut().max_frame_length(usize::MAX);
+    let listener = codec::listen(server_addr).await?;
     listener
         // Ignore accept errors.
         .filter_map(|r| future::ready(r.ok()))
@@ -104,29 +114,20 @@ pub async fn start_safepoint_server(server_addr: SocketAddr) -> anyhow::Result<(
     anyhow::Ok(())
 }
 
-pub async fn rsync(sync_server_addrs: &[SocketAddr; NUM_SERVERS], target_idx: usize) {
-    let mut sync_clients = Vec::new();
-    for i in 0..NUM_SERVERS {
-        let mut transport =
-            tarpc::serde_transport::tcp::connect(&sync_server_addrs[i], Json::default);
-        transport.config_mut().max_frame_length(usize::MAX);
-        let fut_transport = transport.await.expect("failed to connect");
-        let client =
-            Arc::new(DSafePointClient::new(client::Config::default(), fut_transport).spawn());
-        sync_clients.push(client);
-    }
-    for i in 0..NUM_SERVERS {
-        let mut ctx = context::current();
-        ctx.deadline = SystemTime::now() + Duration::from_secs(RPC_WAIT);
-        sync_clients[i]
-            .wait_ready(ctx, target_idx)
-            .await
-            .expect("failed to wait ready");
+// Waits until every server marked `target_idx`. Startup itself goes through bootstrap::barrier.
+pub async fn rsync(sync_server_addrs: &[SocketAddr], target_idx: usize) {
+    if let Err(e) = wait_all(sync_server_addrs, target_idx, Duration::from_secs(RPC_WAIT)).await {
+        panic!("rsync for {} failed: {:#}", target_idx, e);
     }
-    sleep(Duration::from_secs(1)).await;
     dprintln!("rsync done for {}", target_idx);
 }
 
+pub fn init_ready_map() {
+    unsafe {
+        READY_MAP = Some(Arc::new(DashMap::new()));
+    }
+}
+
 pub fn set_ready(idx: usize) {
     unsafe {
         if let Some(map) = &READY_MAP {
@@ -135,18 +136,20 @@ pub fn set_ready(idx: usize) {
     }
 }
 
-pub async fn rshutdown(sync_server_addrs: &[SocketAddr; NUM_SERVERS]) {
+pub async fn rshutdown(sync_server_addrs: &[SocketAddr]) {
+    if sim::is_active() {
+        sim::shutdown_all();
+        return;
+    }
+    let deadline = Instant::now() + Duration::from_secs(DEFAULT_PHASE_TIMEOUT_SECS);
     let mut sync_clients = Vec::new();
-    for i in 0..NUM_SERVERS {
-        let mut transport =
-            tarpc::serde_transport::tcp::connect(&sync_server_addrs[i], Json::default);
-        transport.config_mut().max_frame_length(usize::MAX);
-        let fut_transport = transport.await.expect("failed to connect");
-        let client =
-            Arc::new(DSafePointClient::new(client::Config::default(), fut_transport).spawn());
-        sync_clients.push(client);
+    for (i, addr) in sync_server_addrs.iter().enumerate() {
+        let transport = connect_transport(*addr, "safepoint server", i, deadline)
+            .await
+            .unwrap_or_else(|e| panic!("failed to shut down: {:#}", e));
+        sync_clients.push(DSafePointClient::new(client::Config::default(), transport).spawn());
     }
-    for i in 0..NUM_SERVERS {
+    for i in 0..sync_server_addrs.len() {
         if i == unsafe { SERVER_INDEX } {
             continue;
         }
@@ -0,0 +1,159 @@
+use std::{
+    any::Any,
+    env,
+    fs::File,
+    hash::Hasher,
+    io::Read,
+    mem::transmute,
+    sync::OnceLock,
+};
+
+use fnv::FnvHasher;
+
+use crate::conf::*;
+
+// Servers pass heap addresses, future vtables and function pointers to each other as plain
+// integers. That is only sound between copies of the same executable, loaded at the same address
+// (ASLR off), with the same heap layout, so peers compare these before any RPC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub executable: u64,
+    pub code_anchor: u64,
+    pub vtable_anchor: u64,
+    pub heap_start: u64,
+    pub unit_size: u64,
+    pub num_servers: u64,
+}
+
+pub const FINGERPRINT_SIZE: usize = 48;
+
+static EXECUTABLE_HASH: OnceLock<u64> = OnceLock::new();
+
+// Hashes the running executable. Done once at startup, before the first handshake needs it.
+pub fn init() {
+    EXECUTABLE_HASH.get_or_init(|| match hash_executable() {
+        Ok(hash) => hash,
+        Err(e) => panic!("cannot read the drust executable to fingerprint it: {}", e),
+    });
+}
+
+fn hash_executable() -> std::io::Result<u64> {
+    let mut file = File::open(env::current_exe()?)?;
+    let mut hasher = FnvHasher::default();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..n]);
+    }
+}
+
+pub fn local() -> Fingerprint {
+    init();
+    let anchor: &(dyn Any + Send + Sync) = &();
+    let (_, vtable) = unsafe { transmute::<_, (usize, usize)>(anchor) };
+    Fingerprint {
+        executable: *EXECUTABLE_HASH.get().unwrap(),
+        code_anchor: local as fn() -> Fingerprint as usize as u64,
+        vtable_anchor: vtable as u64,
+        heap_start: unsafe { GLOBAL_HEAP_START } as u64,
+        unit_size: unsafe { WORKER_UNIT_SIZE } as u64,
+        num_servers: unsafe { NUM_SERVERS } as u64,
+    }
+}
+
+impl Fingerprint {
+    fn fields(&self) -> [u64; 6] {
+        [
+            self.executable,
+            self.code_anchor,
+            self.vtable_anchor,
+            self.heap_start,
+            self.unit_size,
+            self.num_servers,
+        ]
+    }
+
+    pub fn to_bytes(&self) -> [u8; FINGERPRINT_SIZE] {
+        let mut bytes = [0u8; FINGERPRINT_SIZE];
+        for (chunk, field) in bytes.chunks_exact_mut(8).zip(self.fields()) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; FINGERPRINT_SIZE]) -> Self {
+        let field = |i: usize| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+        Fingerprint {
+            executable: field(0),
+            code_anchor: field(1),
+            vtable_anchor: field(2),
+            heap_start: field(3),
+            unit_size: field(4),
+            num_servers: field(5),
+        }
+    }
+
+    // Describes how `peer` differs from this fingerprint, or None if they match.
+    pub fn mismatch(&self, peer: &Fingerprint) -> Option<String> {
+        let mut differences = Vec::new();
+        if peer.executable != self.executable {
+            differences.push(format!(
+                "executable hash {:016x}, this binary {:016x}; deploy the same build everywhere",
+                peer.executable, self.executable
+            ));
+        } else if peer.code_anchor != self.code_anchor || peer.vtable_anchor != self.vtable_anchor {
+            differences.push(format!(
+                "code loaded at {:x}, this binary at {:x}; disable ASLR on every server",
+                peer.code_anchor, self.code_anchor
+            ));
+        }
+        if peer.heap_start != self.heap_start || peer.unit_size != self.unit_size {
+            differences.push(format!(
+                "heap at {:x} with {} GB per server, this server {:x} with {} GB",
+                peer.heap_start,
+                peer.unit_size >> 30,
+                self.heap_start,
+                self.unit_size >> 30
+            ));
+        }
+        if peer.num_servers != self.num_servers {
+            differences.push(format!(
+                "{} servers, this server {}",
+                peer.num_servers, self.num_servers
+            ));
+        }
+        (!differences.is_empty()).then(|| differences.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survives_the_wire() {
+        let ours = local();
+        assert_eq!(Fingerprint::from_bytes(&ours.to_bytes()), ours);
+        assert_eq!(ours.mismatch(&ours), None);
+    }
+
+    #[test]
+    fn names_what_differs() {
+        let ours = local();
+        let cases = [
+            (Fingerprint { executable: ours.executable ^ 1, ..ours }, "deploy the same build"),
+            (Fingerprint { code_anchor: ours.code_anchor + 4096, ..ours }, "disable ASLR"),
+            (Fingerprint { vtable_anchor: ours.vtable_anchor + 4096, ..ours }, "disable ASLR"),
+            (Fingerprint { heap_start: ours.heap_start + (1 << 30), ..ours }, "heap at"),
+            (Fingerprint { unit_size: ours.unit_size * 2, ..ours }, "GB per server"),
+            (Fingerprint { num_servers: ours.num_servers + 1, ..ours }, "servers, this server"),
+        ];
+        for (peer, expected) in cases {
+            let mismatch = ours.mismatch(&peer).expect("a different fingerprint was accepted");
+            assert!(mismatch.contains(expected), "{}", mismatch);
+        }
+    }
+}
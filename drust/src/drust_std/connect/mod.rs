@@ -1,35 +1,31 @@
+pub mod bootstrap;
+pub mod codec;
+pub mod config;
+#[allow(unused, clippy::all)]
 pub mod dsafepoint;
+pub mod fingerprint;
 
 use crate::drust_std::alloc::init::{init_connections, init_heap};
-use serde::{Deserialize, Serialize};
-use std::sync::atomic::AtomicBool;
-use std::{net::SocketAddr, sync::Arc, thread::sleep};
-use tarpc::tokio_serde::formats::Json;
+use std::net::SocketAddr;
 // use tarpc::server;
 use crate::drust_std::comm::*;
-use dsafepoint::*;
+use bootstrap::*;
+use config::*;
 use tokio::runtime::Runtime;
 
 use crate::conf::*;
 
-#[derive(Serialize, Deserialize)]
-struct Server {
-    ip: String,
-    alloc_ip: String,
-    safepoint_ip: String,
-}
-#[derive(Serialize, Deserialize)]
-struct Config {
-    servers: Vec<Server>,
-}
-
 #[macro_export]
 macro_rules! drun_server {
     ($addr:expr, $appserver:expr) => {
-        let mut listener = tarpc::serde_transport::tcp::listen(&($addr), Json::default)
-            .await
-            .unwrap();
-        listener.config_mut().max_frame_length(usize::MAX);
+        $crate::drun_server!($addr, $appserver, {});
+    };
+    // $on_listen runs once the listener is bound and clients can connect.
+    ($addr:expr, $appserver:expr, $on_listen:block) => {{
+        use $crate::futures::{future, StreamExt};
+        use $crate::tarpc::server::{self, incoming::Incoming, Channel};
+        let listener = $crate::drust_std::connect::codec::listen($addr).await.unwrap();
+        $on_listen
         listener
             // Ignore accept errors.
             .filter_map(|r| future::ready(r.ok()))
@@ -46,50 +42,96 @@ macro_rules! drun_server {
             .buffer_unordered(10000)
             .for_each(|_| async {})
             .await;
-    };
+    }};
 }
 
 #[macro_export]
 macro_rules! dconnect {
-    ($addr:expr, $vec:ident, $appClient:ident) => {
-        let mut client_refs: Vec<Arc<$appClient>> = Vec::with_capacity(NUM_SERVERS);
-        for i in 0..NUM_SERVERS {
-            let mut transport = tarpc::serde_transport::tcp::connect(&$addr[i], Json::default);
-            transport.config_mut().max_frame_length(usize::MAX);
-            let fut_transport = transport.await.expect("failed to connect");
-            let config = Config::default();
-            client_refs.push(Arc::new($appClient::new(config, fut_transport).spawn()));
+    ($addr:expr, $vec:ident, $appClient:ident) => {{
+        let num_servers = unsafe { $crate::conf::NUM_SERVERS };
+        let deadline = std::time::Instant::now()
+            + $crate::drust_std::connect::bootstrap::phase_timeout(
+                $crate::drust_std::connect::bootstrap::Phase::AppClientsConnected,
+            );
+        let mut client_refs: Vec<std::sync::Arc<$appClient>> = Vec::with_capacity(num_servers);
+        for i in 0..num_servers {
+            let fut_transport =
+                $crate::drust_std::connect::bootstrap::connect_transport($addr[i], "app server", i, deadline)
+                    .await
+                    .unwrap_or_else(|e| $crate::drust_std::connect::bootstrap::bootstrap_failed(e));
+            let config = $crate::tarpc::client::Config::default();
+            client_refs.push(std::sync::Arc::new($appClient::new(config, fut_transport).spawn()));
             println!("connected to server {}", i);
         }
         unsafe {
             $vec = Some(client_refs);
         }
-    };
+    }};
 }
 
-pub fn get_server_addrs() -> (
-    [SocketAddr; NUM_SERVERS],
-    [SocketAddr; NUM_SERVERS],
-    [SocketAddr; NUM_SERVERS],
+// Loads and validates the cluster config, then sizes the cluster from it. The command line can
+// run on a prefix of the listed servers and override the per-server heap size. Every node checks
+// the whole file, so a bad config stops all of them with the same message.
+pub fn init_cluster_conf(
+    path: &str,
+    num_servers: Option<usize>,
+    unit_heap_size_gb: Option<usize>,
+    transport: TransportKind,
 ) {
-    let config =
-        serde_json::from_str::<Config>(&std::fs::read_to_string("drust.json").unwrap()).unwrap();
-    let mut server_addrs = [SocketAddr::from(([0, 0, 0, 0], 0)); NUM_SERVERS];
-    let mut alloc_server_addrs = [SocketAddr::from(([0, 0, 0, 0], 0)); NUM_SERVERS];
-    let mut safepoint_addrs = [SocketAddr::from(([0, 0, 0, 0], 0)); NUM_SERVERS];
-    for i in 0..NUM_SERVERS {
-        server_addrs[i] = config.servers[i].ip.parse().unwrap();
-        alloc_server_addrs[i] = config.servers[i].alloc_ip.parse().unwrap();
-        safepoint_addrs[i] = config.servers[i].safepoint_ip.parse().unwrap();
+    let config = match ConfigFile::load(path).and_then(|file| file.validate(transport)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("drust: bad config: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let num_servers = num_servers.unwrap_or(config.servers.len());
+    if num_servers == 0 || num_servers > config.servers.len() {
+        eprintln!(
+            "drust: bad config: {} lists {} servers, cannot run on {}",
+            path,
+            config.servers.len(),
+            num_servers
+        );
+        std::process::exit(1);
+    }
+    init_cluster(
+        num_servers,
+        unit_heap_size_gb.unwrap_or(config.unit_heap_size_gb),
+        config.heap_start,
+    );
+    unsafe {
+        LOG_LEVEL = config.log_level;
+        codec::WIRE_FORMAT = config.wire_format;
+        crate::drust_std::alloc::oom::OOM_POLICY = config.oom_policy;
+        crate::drust_std::alloc::oom::OOM_WAIT = config.oom_wait;
+        CONFIG = Some(config);
     }
-    (server_addrs, alloc_server_addrs, safepoint_addrs)
+    fingerprint::init();
+}
+
+pub fn get_server_addrs() -> (Vec<SocketAddr>, Vec<SocketAddr>, Vec<SocketAddr>) {
+    let servers = &config().servers[..unsafe { NUM_SERVERS }];
+    (
+        servers.iter().map(|s| s.app).collect(),
+        servers.iter().map(|s| s.alloc).collect(),
+        servers.iter().map(|s| s.safepoint).collect(),
+    )
+}
+
+// Addresses of the memory servers used by the tcp transport, unused with rdma.
+pub fn get_mem_addrs() -> Vec<SocketAddr> {
+    config().servers[..unsafe { NUM_SERVERS }]
+        .iter()
+        .map(|s| s.mem.unwrap_or(SocketAddr::from(([0, 0, 0, 0], 0))))
+        .collect()
 }
 
 pub fn rconnect(alloc_addr: SocketAddr) {
     println!("[rust] start");
     let server_idx = unsafe { SERVER_INDEX };
 
-    if NUM_SERVERS > 1 {
+    if unsafe { NUM_SERVERS } > 1 {
         let s_idx = server_idx;
         std::thread::spawn(move || {
             println!("start drust rdma server from the spawned thread!");
@@ -97,7 +139,7 @@ pub fn rconnect(alloc_addr: SocketAddr) {
                 drust_start_server(LOCAL_HEAP_START, WORKER_UNIT_SIZE, s_idx);
             }
         });
-        // TODO: wait until the server is ready
+        // Blocks until the connections to all other servers are established.
         unsafe {
             drust_server_ready();
         }
@@ -107,16 +149,16 @@ pub fn rconnect(alloc_addr: SocketAddr) {
         }
     }
 
+    set_phase(Phase::RdmaUp);
+
     let s_addr = alloc_addr;
     std::thread::spawn(move || {
         println!("start drust distributed alloc server from the spawned thread!");
         init_heap(s_addr);
     });
-    sleep(std::time::Duration::from_secs(1));
-    set_ready(0);
 }
 
-pub fn rconnect_alloc(alloc_addrs: [SocketAddr; NUM_SERVERS]) {
+pub fn rconnect_alloc(alloc_addrs: Vec<SocketAddr>) {
     std::thread::spawn(move || {
         println!("start connecting distributed alloc from the spawned thread!");
         Runtime::new()
@@ -1,52 +1,57 @@
 pub mod alloc;
+pub mod application;
 pub mod collections;
+pub mod collective;
 pub mod comm;
 pub mod connect;
 pub mod primitives;
+pub mod sim;
 pub mod sync;
 pub mod thread;
+#[allow(unused, clippy::all)]
 pub mod utils;
 
-use std::sync::Arc;
 use std::{net::SocketAddr, time::Duration};
 
 use crate::{
-    app,
     conf::*,
     dconnect,
     drust_std::{
-        connect::dsafepoint::{
-            rshutdown, set_ready, start_safepoint_server, READY_MAP, SHUTDOWN_INDEX,
+        connect::{
+            bootstrap::{bootstrap_failed, set_phase, sync_phase, Phase},
+            dsafepoint::{
+                init_ready_map, rshutdown, start_safepoint_server, READY_MAP, SHUTDOWN_INDEX,
+            },
         },
         thread::dlink::{run_server, DRustWorldClient, DCLIENTS},
     },
 };
-use clap::Parser;
-use connect::dsafepoint::rsync;
-use tarpc::{
-    client::{self, Config},
-    context, server,
-    server::{incoming::Incoming, Channel},
-    tokio_serde::formats::Json,
-};
 use tokio::runtime::Runtime;
 use tokio::time::sleep;
 use utils::*;
 
-pub fn prepare() -> (
-    [SocketAddr; NUM_SERVERS],
-    [SocketAddr; NUM_SERVERS],
-    [SocketAddr; NUM_SERVERS],
-) {
-    let (app, server_idx) = get_args();
+pub fn prepare() -> (Vec<SocketAddr>, Vec<SocketAddr>, Vec<SocketAddr>) {
+    let args = get_args();
+    let (app, server_idx, transport) = (args.application, args.serverid as usize, args.transport);
+    connect::init_cluster_conf(&args.config, args.num_servers, args.heap_size_gb, transport);
+    if server_idx >= unsafe { NUM_SERVERS } {
+        eprintln!(
+            "drust: server index {} is out of range for {} servers",
+            server_idx,
+            unsafe { NUM_SERVERS }
+        );
+        std::process::exit(1);
+    }
     unsafe {
         SERVER_INDEX = server_idx;
         LOCAL_HEAP_START = GLOBAL_HEAP_START + server_idx * WORKER_UNIT_SIZE;
         APPLICATION_NAME = Some(app);
     }
     let (app_addrs, alloc_addrs, safepoint_addrs) = connect::get_server_addrs();
+    comm::init_transport(transport, connect::get_mem_addrs());
 
-    let mut sync_server_addr = safepoint_addrs[server_idx];
+    init_ready_map();
+    let sync_server_addr = safepoint_addrs[server_idx];
     std::thread::spawn(move || {
         println!("start sync server from the spawned thread!");
         Runtime::new()
@@ -55,45 +60,43 @@ pub fn prepare() -> (
             .unwrap();
     });
 
-    std::thread::sleep(Duration::from_secs(5));
-
     connect::rconnect(alloc_addrs[server_idx]);
-    Runtime::new().unwrap().block_on(rsync(&safepoint_addrs, 0));
+    Runtime::new().unwrap().block_on(async {
+        sync_phase(&safepoint_addrs, Phase::RdmaUp).await;
+        sync_phase(&safepoint_addrs, Phase::AllocServerUp).await;
+    });
     (app_addrs, alloc_addrs, safepoint_addrs)
 }
 
 pub async fn drust_main(
-    app_addrs: [SocketAddr; NUM_SERVERS],
-    safepoint_addrs: [SocketAddr; NUM_SERVERS],
+    app_addrs: Vec<SocketAddr>,
+    safepoint_addrs: Vec<SocketAddr>,
     server_idx: usize,
 ) {
     let app = unsafe { APPLICATION_NAME.as_ref().unwrap().clone() };
+    let app_addr = app_addrs[server_idx];
     std::thread::spawn(move || {
         Runtime::new()
             .unwrap()
-            .block_on(run_server(app_addrs[server_idx]));
+            .block_on(run_server(app_addr));
     });
-    sleep(Duration::from_secs(2)).await;
-    set_ready(2);
-    rsync(&safepoint_addrs, 1).await;
-    rsync(&safepoint_addrs, 2).await;
+    sync_phase(&safepoint_addrs, Phase::AllocClientsConnected).await;
+    sync_phase(&safepoint_addrs, Phase::AppServerUp).await;
     dconnect!(app_addrs, DCLIENTS, DRustWorldClient);
-    set_ready(3);
-    rsync(&safepoint_addrs, 3).await;
+    tokio::spawn(thread::steal::run_stealer());
+    if let Err(e) = thread::capacity::discover(&safepoint_addrs).await {
+        bootstrap_failed(e);
+    }
+    let app = application::find_app(&app).unwrap_or_else(|| {
+        panic!("unknown app {}, available: {}", app, application::app_names().join(", "))
+    });
+    app.init().await;
+    set_phase(Phase::AppClientsConnected);
+    sync_phase(&safepoint_addrs, Phase::AppClientsConnected).await;
     if server_idx == 0 {
-        if app == "gemm" {
-            app::gemm::run().await;
-        } else if app == "dataframe" {
-            app::dataframe::run().await;
-        } else if app == "kv" {
-            app::kv::run().await;
-        } else if app == "sn" {
-            app::socialnet::run().await;
-        } else {
-            panic!("unknown app");
-        }
-        
+        app.run().await;
         println!("drust_main done");
+        alloc::reclaim::flush_all().await;
         rshutdown(&safepoint_addrs).await;
     } else {
         loop {
@@ -104,14 +107,12 @@ pub async fn drust_main(
             }
         }
     }
+    app.shutdown().await;
+    alloc::reclaim::flush_all().await;
 }
 
 pub fn run(
-    addrs: (
-        [SocketAddr; NUM_SERVERS],
-        [SocketAddr; NUM_SERVERS],
-        [SocketAddr; NUM_SERVERS],
-    ),
+    addrs: (Vec<SocketAddr>, Vec<SocketAddr>, Vec<SocketAddr>),
 ) {
     let (app_addrs, alloc_addrs, safepoint_addrs) = addrs;
     let app = unsafe { APPLICATION_NAME.as_ref().unwrap().clone() };
@@ -119,15 +120,28 @@ pub fn run(
     let server_idx = unsafe { SERVER_INDEX };
     println!("drust {} started", app);
     let handle = std::thread::spawn(move || {
-        Runtime::new()
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        if let Some(worker_threads) = connect::config::config().worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+        builder
+            .enable_all()
+            .build()
             .unwrap()
             .block_on(drust_main(app_addrs, safepoint_addrs_copy, server_idx));
     });
     connect::rconnect_alloc(alloc_addrs);
-    std::thread::sleep(Duration::from_secs(2));
     handle.join().unwrap();
 }
 
 pub fn finalize() {
     println!("finalize");
 }
+
+// Runs this process as one server of the cluster: parses the command line, joins the other
+// servers, runs the selected application and shuts down. Register the applications first.
+pub fn launch() {
+    let addrs = prepare();
+    run(addrs);
+    finalize();
+}
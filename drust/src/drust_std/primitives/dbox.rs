@@ -3,7 +3,10 @@ use std::{
 };
 
 use crate::{
-    dassert, dprintln, drust_std::alloc::{ddeallocate, ddrop, LOCAL_ALLOCATOR, REF_MAP}
+    dassert, dprintln, drust_std::alloc::{
+        allocate_on, ddeallocate, ddrop, local_allocate, local_allocator, local_free, oom::oom,
+        try_allocate, AllocError, HeapAllocator, LocalFree, Placement, REF_MAP,
+    }
 };
 
 use super::*;
@@ -14,7 +17,7 @@ use tbox::*;
 
 pub struct DBox<T: DRust + Sized> {
     // pub data: Box<T, System>,
-    pub(crate) data: Option<Box<T, &'static good_memory_allocator::SpinLockedAllocator>>,
+    pub(crate) data: Option<Box<T, &'static HeapAllocator>>,
     pub(crate) copy: *mut T,
     pub(crate) copy_exists: bool,
 }
@@ -39,9 +42,7 @@ impl<T: DRust + Sized + Clone> Clone for DBox<T> {
             Destination::Remote(_server_idx) => {
                 // TODO! (add a better way to clone remote data, currently do not support remote clone complicated type)
                 let ptr = unsafe {
-                    LOCAL_ALLOCATOR
-                        .allocate(Layout::new::<T>())
-                        .unwrap()
+                    local_allocate(Layout::new::<T>())
                         .as_mut_ptr() as *mut T
                 };
                 // if SETTING == 2 && mem::size_of::<T>() > 512 * 1024 {
@@ -61,7 +62,7 @@ impl<T: DRust + Sized + Clone> Clone for DBox<T> {
                     );
                 }
                 // }
-                let x = unsafe { Box::from_raw_in(ptr, &LOCAL_ALLOCATOR) };
+                let x = unsafe { Box::from_raw_in(ptr, local_allocator()) };
                 DBox {
                     data: Some(x),
                     copy: ptr::null_mut(),
@@ -105,7 +106,7 @@ impl<T: DRust + Sized> Drop for DBox<T> {
         let des = current_place(raw_orig as usize);
         match des {
             Destination::Local => {
-                let local_data = unsafe { Box::from_raw_in(raw_orig, &LOCAL_ALLOCATOR) };
+                let local_data = unsafe { Box::from_raw_in(raw_orig, LocalFree) };
                 drop(local_data);
             }
             Destination::Remote(_server_idx) => {
@@ -140,14 +141,14 @@ impl<'a, T: DRust + Sized> DBox<T> {
     pub unsafe fn from_raw(raw: *mut T) -> Self {
         dassert!(raw as usize >= GLOBAL_HEAP_START, "Invalid address!");
         DBox {
-            data: Some(Box::from_raw_in(raw, &LOCAL_ALLOCATOR)),
+            data: Some(Box::from_raw_in(raw, local_allocator())),
             copy: ptr::null_mut(),
             copy_exists: false,
         }
     }
 
     pub unsafe fn from_raw_box(
-        raw: Box<T, &'static good_memory_allocator::SpinLockedAllocator>,
+        raw: Box<T, &'static HeapAllocator>,
     ) -> Self {
         DBox { 
             data: Some(raw),
@@ -158,12 +159,10 @@ impl<'a, T: DRust + Sized> DBox<T> {
 
     pub fn new(contents: T) -> Self {
         let ptr = unsafe {
-            LOCAL_ALLOCATOR
-                .allocate(Layout::new::<T>())
-                .unwrap()
+            local_allocate(Layout::new::<T>())
                 .as_mut_ptr() as *mut T
         };
-        let mut x = unsafe { Box::from_raw_in(ptr, &LOCAL_ALLOCATOR) };
+        let mut x = unsafe { Box::from_raw_in(ptr, local_allocator()) };
         unsafe {
             // copy_mem(raw_1 as usize, raw_2 as usize, mem::size_of::<T>());
             ptr::write_volatile(x.as_mut() as *mut T, contents);
@@ -175,14 +174,39 @@ impl<'a, T: DRust + Sized> DBox<T> {
         }
     }
 
+    // Like new, but reports running out of memory instead of panicking. With the spill policy
+    // the box may be placed on another server.
+    pub fn try_new(contents: T) -> Result<Self, AllocError> {
+        let ptr = try_allocate(Layout::new::<T>())?.as_mut_ptr() as *mut T;
+        Ok(unsafe { Self::placed(ptr, contents) })
+    }
+
+    // Allocates the box on the server picked by `placement`, e.g. DBox::new_on(v, 2) or
+    // DBox::new_on(v, Placement::RoundRobin). Remote contents are written in one write.
+    pub fn new_on(contents: T, placement: impl Into<Placement>) -> Self {
+        Self::try_new_on(contents, placement).unwrap_or_else(|e| oom(e))
+    }
+
+    pub fn try_new_on(contents: T, placement: impl Into<Placement>) -> Result<Self, AllocError> {
+        let ptr = allocate_on(Layout::new::<T>(), placement.into())?.as_mut_ptr() as *mut T;
+        Ok(unsafe { Self::placed(ptr, contents) })
+    }
+
+    unsafe fn placed(ptr: *mut T, contents: T) -> Self {
+        write_placed(ptr, contents);
+        DBox {
+            data: Some(Box::from_raw_in(ptr, local_allocator())),
+            copy: ptr::null_mut(),
+            copy_exists: false,
+        }
+    }
+
     pub fn box_new(contents: Box<T>) -> Self {
         let ptr = unsafe {
-            LOCAL_ALLOCATOR
-                .allocate(Layout::new::<T>())
-                .unwrap()
+            local_allocate(Layout::new::<T>())
                 .as_mut_ptr() as *mut T
         };
-        let x = unsafe { Box::from_raw_in(ptr, &LOCAL_ALLOCATOR) };
+        let x = unsafe { Box::from_raw_in(ptr, local_allocator()) };
         let remote = DBox { 
             data: Some(x),
             copy: ptr::null_mut(),
@@ -201,26 +225,6 @@ impl<'a, T: DRust + Sized> DBox<T> {
         remote
     }
 
-    // pub fn box_new_on(contents: Box<T>, server_id: usize) -> Self {
-    //     if server_id == unsafe{SERVER_INDEX} {
-    //         return DBox::box_new(contents);
-    //     }
-    //     let local_ptr = unsafe{LOCAL_ALLOCATOR.allocate(Layout::new::<T>()).unwrap().as_mut_ptr() as *mut T};
-    //     let remote_ptr = dallocate(Layout::new::<T>(), server_id).unwrap().as_mut_ptr() as *mut T;
-    //     let raw_1 = ptr::addr_of!(*contents);
-    //     unsafe{
-    //         copy_mem(raw_1 as usize, local_ptr as usize, mem::size_of::<T>());
-    //         drust_write_sync(local_ptr as usize - LOCAL_HEAP_START, remote_ptr as usize - GLOBAL_HEAP_START, mem::size_of::<T>(), thread::current().id().as_u64().get() as usize);
-    //         let (addr, alloc) = Box::into_raw_with_allocator(contents);
-    //         alloc.deallocate(NonNull::new_unchecked(addr as *mut u8), Layout::new::<T>());
-    //         LOCAL_ALLOCATOR.deallocate(NonNull::new_unchecked(local_ptr as *mut u8), Layout::new::<T>());
-    //     }
-    //     let x = unsafe{Box::from_raw_in(remote_ptr, get_remote_allocator(server_id))};
-    //     DBox {
-    //         data: Some(x)
-    //     }
-    // }
-
     pub fn get_place(&self) -> Destination {
         let raw_1 = ptr::addr_of!(**(self.data.as_ref().unwrap())) as usize;
         current_place(raw_1)
@@ -240,12 +244,10 @@ impl<'a, T: DRust + Sized> DBox<T> {
             Destination::Remote(_server_idx) => {
                 self.drop_copy();
                 let ptr = unsafe {
-                    LOCAL_ALLOCATOR
-                        .allocate(Layout::new::<T>())
-                        .unwrap()
+                    local_allocate(Layout::new::<T>())
                         .as_mut_ptr() as *mut T
                 };
-                let x = unsafe { Box::from_raw_in(ptr, &LOCAL_ALLOCATOR) };
+                let x = unsafe { Box::from_raw_in(ptr, local_allocator()) };
                 let mut local = TBox { data: Some(x) };
                 let raw_2 = ptr::addr_of!(**(local.data.as_ref().unwrap()));
                 unsafe {
@@ -278,12 +280,10 @@ impl<'a, T: DRust + Sized> DBox<T> {
             Destination::Remote(_server_idx) => {
                 self.drop_copy();
                 let ptr = unsafe {
-                    LOCAL_ALLOCATOR
-                        .allocate(Layout::new::<T>())
-                        .unwrap()
+                    local_allocate(Layout::new::<T>())
                         .as_mut_ptr() as *mut T
                 };
-                let x = unsafe { Box::from_raw_in(ptr, &LOCAL_ALLOCATOR) };
+                let x = unsafe { Box::from_raw_in(ptr, local_allocator()) };
                 unsafe {
                     drust_read_sync(
                         ptr as usize - LOCAL_HEAP_START,
@@ -315,13 +315,8 @@ impl<'a, T: DRust + Sized> DBox<T> {
                     let (ptr, count) = entry.get_mut();
                     *count -= 1;
                     assert!(*count == 0, "Owner should be the last reference!");
-                    unsafe {
-                        assert!(current_place(*ptr as usize) == Destination::Local, "Copy should be local!");
-                        LOCAL_ALLOCATOR.deallocate(
-                            NonNull::new_unchecked(*ptr as *mut u8),
-                            Layout::new::<T>(),
-                        );
-                    }
+                    assert!(current_place(*ptr as usize) == Destination::Local, "Copy should be local!");
+                    local_free(*ptr, mem::size_of::<T>(), mem::align_of::<T>());
                     entry.remove();
                 }
                 Entry::Vacant(_) => {
@@ -356,9 +351,7 @@ impl<'a, T: DRust + Sized> DBox<T> {
             }
             Entry::Vacant(entry) => {
                 let ptr = unsafe {
-                    LOCAL_ALLOCATOR
-                        .allocate(Layout::new::<T>())
-                        .unwrap()
+                    local_allocate(Layout::new::<T>())
                         .as_mut_ptr() as *mut T
                 };
                 entry.insert((ptr as usize, 1));
@@ -395,7 +388,7 @@ impl<'a, T: DRust + Sized> DBox<T> {
         if self.copy_exists {
             self.migrate_to_local();
         }
-        let data_addr: *const Option<Box<T, &good_memory_allocator::SpinLockedAllocator<20, 8>>> = ptr::addr_of!(self.data);
+        let data_addr: *const Option<Box<T, &HeapAllocator>> = ptr::addr_of!(self.data);
         let combination: usize = ((unsafe{SERVER_INDEX} << 58) | (data_addr as usize));
         DMut {
             orig: &mut (**self.data.as_mut().unwrap()),
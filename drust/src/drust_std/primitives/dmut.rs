@@ -7,7 +7,7 @@ use std::{
 
 use crate::{
     dprintln,
-    drust_std::alloc::{ddeallocate, ddrop, dupdate, LOCAL_ALLOCATOR},
+    drust_std::alloc::{ddeallocate, ddrop, dupdate, local_allocate, local_allocator},
 };
 
 use super::*;
@@ -105,9 +105,7 @@ impl<'a, T: DRust + Sized> DMut<'a, T> {
                 }
 
                 let ptr = unsafe {
-                    LOCAL_ALLOCATOR
-                        .allocate(Layout::new::<T>())
-                        .unwrap()
+                    local_allocate(Layout::new::<T>())
                         .as_mut_ptr() as *mut T
                 };
                 unsafe {
@@ -1,14 +1,13 @@
 use std::{
-    alloc::{Allocator, Layout},
+    alloc::Layout,
     ops::{Deref, DerefMut},
-    ptr::{self, NonNull},
     sync::Arc,
     thread,
 };
 
 use crate::{
     dprintln,
-    drust_std::alloc::{ddeallocate, ddrop, LOCAL_ALLOCATOR, REF_MAP},
+    drust_std::alloc::{ddeallocate, ddrop, local_allocate, local_free, REF_MAP},
 };
 
 use super::*;
@@ -52,12 +51,7 @@ impl<'a, T: DRust + Sized> Drop for DRef<'a, T> {
                 let (ptr, count) = entry.get_mut();
                 *count -= 1;
                 if *count == 0 {
-                    unsafe {
-                        LOCAL_ALLOCATOR.deallocate(
-                            NonNull::new_unchecked((*ptr) as *mut u8),
-                            Layout::new::<T>(),
-                        )
-                    };
+                    local_free(*ptr, mem::size_of::<T>(), mem::align_of::<T>());
                     entry.remove();
                 }
             }
@@ -94,9 +88,7 @@ impl<'a, T: DRust + Sized> DRef<'a, T> {
                     }
                     Entry::Vacant(entry) => {
                         let ptr = unsafe {
-                            LOCAL_ALLOCATOR
-                                .allocate(Layout::new::<T>())
-                                .unwrap()
+                            local_allocate(Layout::new::<T>())
                                 .as_mut_ptr() as *mut T
                         };
                         entry.insert((ptr as usize, 1));
@@ -1,22 +1,33 @@
 use crate::conf::*;
-use crate::drust_std::comm::*;
-use std::mem;
+use crate::drust_std::{alloc::{local_allocate, HeapAllocator}, comm::*, sim};
+use std::{alloc::Layout, intrinsics, mem, sync::Mutex};
 
+#[allow(unused, clippy::all)]
 pub mod dbox;
+#[allow(unused, clippy::all)]
 pub mod dmut;
+#[allow(unused, clippy::all)]
 pub mod dref;
+pub mod registry;
+#[allow(unused, clippy::all)]
 pub mod tbox;
 
-#[derive(PartialEq, Eq)]
+pub use drust_derive::DRust;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Destination {
     Local,
     Remote(usize),
 }
 
+// Implemented with #[derive(DRust)], or by hand with an id from spec.rs.
 pub trait DRust {
     fn static_typeid() -> u32 where Self: Sized;
     fn typeid(&self) -> u32;
     fn migrate(&mut self, _dst: Destination) -> bool;
+    // Releases cached local copies of remote data before the value is sent to another server as
+    // raw bytes, e.g. as the result of a spawned task.
+    fn drop_local_copies(&mut self) {}
 }
 
 // It means it has no local embedded data
@@ -36,16 +47,16 @@ macro_rules! exclude {
 }
 
 pub fn consume_original_data<T>(
-    data: &mut Option<Box<T, &'static good_memory_allocator::SpinLockedAllocator>>,
+    data: &mut Option<Box<T, &'static HeapAllocator>>,
 ) -> *mut T {
-    let original_data = mem::replace(data, None);
-    let raw_1 = Box::into_raw(original_data.unwrap());
-    raw_1
+    Box::into_raw(data.take().unwrap())
 }
 
 pub fn current_place(addr: usize) -> Destination {
-    if addr >= GLOBAL_HEAP_START && addr < GLOBAL_HEAP_START + WORKER_HEAP_SIZE {
-        let server_idx = (addr - GLOBAL_HEAP_START) / WORKER_UNIT_SIZE;
+    let (heap_start, heap_size, unit_size) =
+        unsafe { (GLOBAL_HEAP_START, WORKER_HEAP_SIZE, WORKER_UNIT_SIZE) };
+    if addr >= heap_start && addr < heap_start + heap_size {
+        let server_idx = (addr - heap_start) / unit_size;
         if server_idx == unsafe { SERVER_INDEX } {
             Destination::Local
         } else {
@@ -56,12 +67,77 @@ pub fn current_place(addr: usize) -> Destination {
     }
 }
 
+// Local buffers for copying values into memory on other servers, one per destination, so writes
+// to different servers do not wait for each other. They are set up with the heap: a fresh local
+// allocation does not work there, as after a spill the local heap is full.
+const STAGING_SIZE: usize = 1 << 20;
+const NO_STAGING: Mutex<usize> = Mutex::new(0);
+static STAGING: [Mutex<usize>; MAX_SERVERS] = [NO_STAGING; MAX_SERVERS];
+
+pub fn init_staging() {
+    let (server_idx, num_servers) = unsafe { (SERVER_INDEX, NUM_SERVERS) };
+    for (i, staging) in STAGING.iter().enumerate().take(num_servers) {
+        if i != server_idx {
+            let buffer = local_allocate(Layout::from_size_align(STAGING_SIZE, 64).unwrap());
+            *staging.lock().unwrap() = buffer.as_mut_ptr() as usize;
+        }
+    }
+}
+
+// Moves `value` into `dst`, which may be on another server.
+pub unsafe fn write_placed<T>(dst: *mut T, value: T) {
+    copy_placed(dst as *mut u8, &value as *const T as *const u8, mem::size_of::<T>());
+    mem::forget(value);
+}
+
+// Copies `size` bytes into `dst`, which may be on another server. Up to STAGING_SIZE bytes go out
+// in a single write.
+pub unsafe fn copy_placed(dst: *mut u8, src: *const u8, size: usize) {
+    if size == 0 {
+        return;
+    }
+    let server_idx = match current_place(dst as usize) {
+        Destination::Remote(server_idx) if !sim::is_active() => server_idx,
+        _ => {
+            intrinsics::volatile_copy_nonoverlapping_memory(dst, src, size);
+            return;
+        }
+    };
+    let staging = STAGING[server_idx].lock().unwrap();
+    assert!(*staging != 0, "staging buffer for server {} is not initialized", server_idx);
+    let tid = std::thread::current().id().as_u64().get() as usize;
+    let mut done = 0;
+    while done < size {
+        let n = (size - done).min(STAGING_SIZE);
+        intrinsics::volatile_copy_nonoverlapping_memory(*staging as *mut u8, src.add(done), n);
+        drust_write_sync(*staging - LOCAL_HEAP_START, dst as usize + done - GLOBAL_HEAP_START, n, tid);
+        done += n;
+    }
+}
+
+// Copies `len` values from `src`, which may be on another server, to `dst` on this server's heap.
+pub unsafe fn read_placed<T>(dst: *mut T, src: *const T, len: usize) {
+    if len == 0 {
+        return;
+    }
+    if sim::is_active() || current_place(src as usize) == Destination::Local {
+        intrinsics::volatile_copy_nonoverlapping_memory(dst, src, len);
+        return;
+    }
+    drust_read_large_sync(
+        dst as usize - LOCAL_HEAP_START,
+        src as usize - GLOBAL_HEAP_START,
+        len * mem::size_of::<T>(),
+        std::thread::current().id().as_u64().get() as usize,
+    );
+}
+
 // If the region_size is larger than 1GB, then split it into multiple 1GB regions and read them to local
 pub fn drust_read_large_sync(
     local_dst_offset: usize,
     remote_src_offset: usize,
     region_size: usize,
-    tid: usize,
+    _tid: usize,
 ) {
     let mut region_size = region_size;
     let mut local_dst_offset = local_dst_offset;
@@ -102,3 +178,48 @@ pub fn drust_write_large_sync(
         remote_dst_offset += size;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+
+    use super::*;
+    use crate::drust_std::alloc::LOCAL_ALLOCATOR;
+
+    // Away from the heaps of simulated clusters, which are mapped anew for every run.
+    const HEAP_START: usize = DEFAULT_GLOBAL_HEAP_START + (1 << 40);
+    static HEAP: Once = Once::new();
+
+    // Sets this process up as server 0 of two, as a real server does but with the local
+    // transport, so remote writes take the staging path.
+    fn as_server_0_of_2() {
+        init_cluster(2, 1, HEAP_START);
+        init_transport(TransportKind::Local, Vec::new());
+        unsafe {
+            SERVER_INDEX = 0;
+            LOCAL_HEAP_START = HEAP_START;
+        }
+        HEAP.call_once(|| unsafe {
+            map_heap(HEAP_START, WORKER_HEAP_SIZE);
+            LOCAL_ALLOCATOR.init(HEAP_START, WORKER_UNIT_SIZE);
+            init_staging();
+        });
+    }
+
+    #[test]
+    fn copy_placed_goes_through_staging_to_another_server() {
+        let _turn = sim::test_turn();
+        as_server_0_of_2();
+        // More than one staging buffer, so the copy takes several writes.
+        let size = 2 * STAGING_SIZE + 123;
+        let src: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let dst = unsafe { HEAP_START + WORKER_UNIT_SIZE + 4096 } as *mut u8;
+        assert!(current_place(dst as usize) == Destination::Remote(1));
+        unsafe {
+            copy_placed(dst, src.as_ptr(), size);
+            assert_eq!(std::slice::from_raw_parts(dst, size), &src[..]);
+            SERVER_INDEX = 0;
+            LOCAL_HEAP_START = 0;
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{alloc::Allocator, collections::HashMap, sync::OnceLock};
+
+use super::DRust;
+
+// Low byte of the type id of every #[derive(DRust)] type. The hand-assigned ids in spec.rs stay
+// below it.
+pub const DERIVED: u32 = 0xFF;
+
+// Type id of a derived type: a 24 bit FNV-1a hash of its path above the DERIVED byte. It only
+// depends on the source, so every server of a cluster computes the same id.
+pub const fn derived_typeid(path: &str) -> u32 {
+    let bytes = path.as_bytes();
+    let mut hash: u32 = 0x811c9dc5;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u32;
+        hash = hash.wrapping_mul(0x01000193);
+        i += 1;
+    }
+    (((hash >> 24) ^ hash) & 0xFF_FFFF) << 8 | DERIVED
+}
+
+// What a remote drop needs to know about a derived type, submitted by the derive at link time.
+pub struct TypeEntry {
+    pub typeid: u32,
+    pub path: &'static str,
+    as_dyn: fn(usize) -> *mut dyn DRust,
+    drop_vec: unsafe fn(usize, usize, usize, &dyn Allocator),
+}
+
+impl TypeEntry {
+    pub const fn new<T: DRust + 'static>(path: &'static str) -> Self {
+        TypeEntry {
+            typeid: derived_typeid(path),
+            path,
+            as_dyn: as_dyn::<T>,
+            drop_vec: drop_vec::<T>,
+        }
+    }
+
+    pub fn from_addr_in<A: Allocator>(&self, addr: usize, alloc: A) -> Box<dyn DRust, A> {
+        unsafe { Box::from_raw_in((self.as_dyn)(addr), alloc) }
+    }
+
+    pub unsafe fn drop_vec_in<A: Allocator>(&self, addr: usize, capacity: usize, len: usize, alloc: A) {
+        (self.drop_vec)(addr, capacity, len, &alloc)
+    }
+}
+
+fn as_dyn<T: DRust + 'static>(addr: usize) -> *mut dyn DRust {
+    addr as *mut T
+}
+
+unsafe fn drop_vec<T>(addr: usize, capacity: usize, len: usize, alloc: &dyn Allocator) {
+    drop(Vec::from_raw_parts_in(addr as *mut T, len, capacity, alloc));
+}
+
+inventory::collect!(TypeEntry);
+
+static TYPES: OnceLock<HashMap<u32, &'static TypeEntry>> = OnceLock::new();
+
+fn types() -> &'static HashMap<u32, &'static TypeEntry> {
+    TYPES.get_or_init(|| {
+        let mut types = HashMap::new();
+        for entry in inventory::iter::<TypeEntry> {
+            if let Some(other) = types.insert(entry.typeid, entry) {
+                panic!(
+                    "derive(DRust): {} and {} hash to the same type id {:x}, rename one of them",
+                    other.path, entry.path, entry.typeid
+                );
+            }
+        }
+        types
+    })
+}
+
+// Builds the registry, so type id collisions show up at startup rather than at the first drop.
+pub fn init() {
+    types();
+}
+
+pub fn lookup(typeid: u32) -> &'static TypeEntry {
+    types()
+        .get(&typeid)
+        .unwrap_or_else(|| panic!("unknown type id: {:x}, is the type registered?", typeid))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::drust_std::{
+        alloc::{reclaim, slab, Placement},
+        primitives::dbox::DBox,
+        sim,
+    };
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(DRust)]
+    struct Probe {
+        value: usize,
+    }
+
+    impl Drop for Probe {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn derived_ids_depend_only_on_the_path() {
+        let id = derived_typeid("app::Probe");
+        assert_eq!(id, derived_typeid("app::Probe"));
+        assert_ne!(id, derived_typeid("app::Probe2"));
+        assert_eq!(id & 0xFF, DERIVED);
+        assert_eq!(Probe::static_typeid(), derived_typeid(concat!(module_path!(), "::Probe")));
+    }
+
+    #[test]
+    fn lookup_finds_derived_types() {
+        init();
+        let entry = lookup(Probe::static_typeid());
+        assert_eq!(entry.typeid, Probe::static_typeid());
+        assert_eq!(entry.path, concat!(module_path!(), "::Probe"));
+    }
+
+    #[test]
+    fn remote_drop_finds_the_type_in_the_registry() {
+        sim::test_cluster(2).run_test(async {
+            let probe = DBox::new_on(Probe { value: 7 }, Placement::On(1));
+            let (_, _, before) = slab::leased_from(1);
+            let drops = DROPS.load(Ordering::SeqCst);
+            drop(probe);
+            assert_eq!(DROPS.load(Ordering::SeqCst), drops + 1);
+            // The probe lived in a slab server 0 leased, so its memory goes back to server 0.
+            reclaim::flush_all().await;
+            let (_, _, after) = slab::leased_from(1);
+            assert_eq!(after, before - 1);
+        });
+    }
+}
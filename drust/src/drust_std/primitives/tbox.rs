@@ -13,7 +13,7 @@ use dmut::*;
 use dref::*;
 
 pub struct TBox<T: DRust + Sized> {
-    pub(crate) data: Option<Box<T, &'static good_memory_allocator::SpinLockedAllocator>>,
+    pub(crate) data: Option<Box<T, &'static HeapAllocator>>,
 }
 
 impl<'a, T: DRust + Sized> TBox<T> {
@@ -21,7 +21,7 @@ impl<'a, T: DRust + Sized> TBox<T> {
         let original_data = mem::replace(&mut self.data, None);
         let raw_1 = Box::into_raw(original_data.unwrap());
         DBox {
-            data: Some(unsafe { Box::from_raw_in(raw_1, &LOCAL_ALLOCATOR) }),
+            data: Some(unsafe { Box::from_raw_in(raw_1, local_allocator()) }),
             copy: ptr::null_mut(),
             copy_exists: false,
         }
@@ -39,12 +39,10 @@ impl<'a, T: DRust + Sized> TBox<T> {
                     }
                     Destination::Remote(_server_idx) => {
                         let ptr = unsafe {
-                            LOCAL_ALLOCATOR
-                                .allocate(Layout::new::<T>())
-                                .unwrap()
+                            local_allocate(Layout::new::<T>())
                                 .as_mut_ptr() as *mut T
                         };
-                        let x = unsafe { Box::from_raw_in(ptr, &LOCAL_ALLOCATOR) };
+                        let x = unsafe { Box::from_raw_in(ptr, local_allocator()) };
                         unsafe {
                             drust_read_sync(
                                 ptr as usize - LOCAL_HEAP_START,
@@ -83,12 +81,7 @@ impl<'a, T: DRust + Sized> TBox<T> {
                             );
                         }
                         // wait_sync();
-                        unsafe {
-                            LOCAL_ALLOCATOR.deallocate(
-                                NonNull::new_unchecked(raw_1 as *mut u8),
-                                Layout::new::<T>(),
-                            )
-                        };
+                        local_free(raw_1 as usize, mem::size_of::<T>(), mem::align_of::<T>());
                         let original_data = mem::replace(&mut self.data, Some(x));
                         let _ = Box::into_raw(original_data.unwrap());
                     }
@@ -0,0 +1,335 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use dashmap::DashMap;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    conf::*,
+    drust_std::{
+        alloc::{reclaim, slab, HeapAllocator, REF_MAP, REMOTE_ALLOCATORS, SIM_ALLOCATORS},
+        comm::{self, TransportKind},
+        connect::dsafepoint::{READY_MAP, SHUTDOWN_INDEX},
+        sync::dmutex::LOCAL_MUTEX_CACHE,
+    },
+};
+
+// A simulated cluster hosts every DRust server in this process. All heap regions are mapped
+// locally, remote reads and writes become memcpy through the local transport, and the services
+// normally reached over tarpc are called directly under the target server's context. Everything
+// runs on one thread, and a seeded RNG decides where tasks yield, so a given seed always
+// produces the same interleaving.
+
+// Process-global state that differs between servers, parked here while the server is inactive.
+struct SimContext {
+    server_idx: usize,
+    local_heap_start: usize,
+    ref_map: Option<Arc<DashMap<usize, (usize, usize)>>>,
+    mutex_cache: Option<DashMap<usize, bool>>,
+    ready_map: Option<Arc<DashMap<usize, bool>>>,
+}
+
+struct SimState {
+    contexts: Vec<SimContext>,
+    current: usize,
+    rng: StdRng,
+    yield_permille: u32,
+}
+
+static mut SIM: Option<SimState> = None;
+
+pub fn is_active() -> bool {
+    unsafe { SIM.is_some() }
+}
+
+fn state() -> &'static mut SimState {
+    unsafe { SIM.as_mut().expect("simulated cluster is not running") }
+}
+
+// Makes `server_idx` the active server and returns the server that was active before.
+fn enter(server_idx: usize) -> usize {
+    let state = state();
+    let prev = state.current;
+    if prev == server_idx {
+        return prev;
+    }
+    unsafe {
+        let ctx = &mut state.contexts[prev];
+        ctx.ref_map = REF_MAP.take();
+        ctx.mutex_cache = LOCAL_MUTEX_CACHE.take();
+        ctx.ready_map = READY_MAP.take();
+
+        let ctx = &mut state.contexts[server_idx];
+        SERVER_INDEX = ctx.server_idx;
+        LOCAL_HEAP_START = ctx.local_heap_start;
+        REF_MAP = ctx.ref_map.take();
+        LOCAL_MUTEX_CACHE = ctx.mutex_cache.take();
+        READY_MAP = ctx.ready_map.take();
+    }
+    state.current = server_idx;
+    prev
+}
+
+struct ServerGuard(usize);
+
+impl ServerGuard {
+    fn enter(server_idx: usize) -> Self {
+        ServerGuard(enter(server_idx))
+    }
+}
+
+impl Drop for ServerGuard {
+    fn drop(&mut self) {
+        enter(self.0);
+    }
+}
+
+// Runs `f` as server `server_idx`. This is how the simulated DAllocator calls are served.
+pub fn on_server<R>(server_idx: usize, f: impl FnOnce() -> R) -> R {
+    let _guard = ServerGuard::enter(server_idx);
+    f()
+}
+
+fn should_yield() -> bool {
+    let state = state();
+    state.yield_permille > 0 && state.rng.gen_range(0..1000) < state.yield_permille
+}
+
+// Polls the inner future as a fixed server. Outside a simulation it is a plain pass-through.
+pub struct SimTask<F: Future> {
+    server_idx: Option<usize>,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> SimTask<F> {
+    pub fn on(server_idx: usize, future: F) -> Self {
+        SimTask {
+            server_idx: if is_active() { Some(server_idx) } else { None },
+            inner: Box::pin(future),
+        }
+    }
+
+    pub fn current(future: F) -> Self {
+        SimTask::on(unsafe { SERVER_INDEX }, future)
+    }
+}
+
+impl<F: Future> Future for SimTask<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let server_idx = match self.server_idx {
+            Some(server_idx) => server_idx,
+            None => return self.inner.as_mut().poll(cx),
+        };
+        if should_yield() {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let _guard = ServerGuard::enter(server_idx);
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+pub(crate) fn all_ready(idx: usize) -> bool {
+    let state = state();
+    let current = state.current;
+    state.contexts.iter().enumerate().all(|(i, ctx)| {
+        let map = if i == current {
+            unsafe { READY_MAP.as_ref() }
+        } else {
+            ctx.ready_map.as_ref()
+        };
+        map.is_some_and(|m| m.contains_key(&idx))
+    })
+}
+
+pub(crate) async fn wait_all_ready(idx: usize) {
+    while !all_ready(idx) {
+        tokio::task::yield_now().await;
+    }
+}
+
+pub(crate) fn shutdown_all() {
+    let state = state();
+    let (num_servers, current) = (state.contexts.len(), state.current);
+    for i in (0..num_servers).filter(|&i| i != current) {
+        on_server(i, || unsafe {
+            READY_MAP.as_ref().unwrap().insert(SHUTDOWN_INDEX, true);
+        });
+    }
+}
+
+pub struct SimCluster {
+    num_servers: usize,
+    unit_heap_size_gb: usize,
+    seed: u64,
+    yield_permille: u32,
+}
+
+impl SimCluster {
+    pub fn new(num_servers: usize) -> Self {
+        SimCluster {
+            num_servers,
+            unit_heap_size_gb: DEFAULT_UNIT_HEAP_SIZE_GB,
+            seed: 0,
+            yield_permille: 0,
+        }
+    }
+
+    pub fn unit_heap_size_gb(mut self, unit_heap_size_gb: usize) -> Self {
+        self.unit_heap_size_gb = unit_heap_size_gb;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Probability, in 1/1000, that a task yields before each poll.
+    pub fn yield_permille(mut self, yield_permille: u32) -> Self {
+        self.yield_permille = yield_permille;
+        self
+    }
+
+    // Boots all virtual servers and runs `main` on server 0 until it completes.
+    pub fn run<F>(self, main: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        assert!(!is_active(), "a simulated cluster is already running");
+        init_cluster(self.num_servers, self.unit_heap_size_gb, DEFAULT_GLOBAL_HEAP_START);
+        comm::init_transport(TransportKind::Local, Vec::new());
+        let (heap_start, unit_size) = unsafe { (GLOBAL_HEAP_START, WORKER_UNIT_SIZE) };
+        comm::map_heap(heap_start, unsafe { WORKER_HEAP_SIZE });
+
+        let mut allocators = Vec::with_capacity(self.num_servers);
+        for _ in 0..self.num_servers {
+            allocators.push(HeapAllocator::empty());
+        }
+        let mut contexts = Vec::with_capacity(self.num_servers);
+        for i in 0..self.num_servers {
+            contexts.push(SimContext {
+                server_idx: i,
+                local_heap_start: heap_start + i * unit_size,
+                ref_map: Some(Arc::new(DashMap::new())),
+                mutex_cache: Some(DashMap::new()),
+                ready_map: Some(Arc::new(DashMap::new())),
+            });
+        }
+        unsafe {
+            // The allocators must not move once initialized, so init them in their final place.
+            SIM_ALLOCATORS = Some(allocators);
+            let allocators = SIM_ALLOCATORS.as_ref().unwrap();
+            for (i, allocator) in allocators.iter().enumerate() {
+                allocator.init(heap_start + i * unit_size, unit_size);
+            }
+            REMOTE_ALLOCATORS = Some(allocators.iter().map(|a| a as *const _ as usize).collect());
+
+            let first = &mut contexts[0];
+            SERVER_INDEX = 0;
+            LOCAL_HEAP_START = first.local_heap_start;
+            REF_MAP = first.ref_map.take();
+            LOCAL_MUTEX_CACHE = first.mutex_cache.take();
+            READY_MAP = first.ready_map.take();
+            SIM = Some(SimState {
+                contexts,
+                current: 0,
+                rng: StdRng::seed_from_u64(self.seed),
+                yield_permille: self.yield_permille,
+            });
+        }
+        // Declared before the runtime, so the tasks left in it are dropped while the cluster is
+        // still up, also when `main` panics.
+        let _teardown = Teardown;
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(SimTask::on(0, main));
+    }
+}
+
+// Resets the process-global state when a simulation ends, so another one can run.
+struct Teardown;
+
+impl Drop for Teardown {
+    fn drop(&mut self) {
+        unsafe {
+            SIM = None;
+            REF_MAP = None;
+            LOCAL_MUTEX_CACHE = None;
+            READY_MAP = None;
+            REMOTE_ALLOCATORS = None;
+            SIM_ALLOCATORS = None;
+            SERVER_INDEX = 0;
+            LOCAL_HEAP_START = 0;
+        }
+        reclaim::reset();
+        slab::reset();
+    }
+}
+
+// Simulations share process-global state, so the tests that run one take turns. Tests that set
+// up that state themselves take a turn as well.
+#[cfg(test)]
+static TEST_TURN: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[cfg(test)]
+pub(crate) fn test_turn() -> std::sync::MutexGuard<'static, ()> {
+    // A test that failed during its turn poisons the lock; the state is set up again anyway.
+    TEST_TURN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// A small cluster for tests.
+#[cfg(test)]
+pub(crate) fn test_cluster(num_servers: usize) -> SimCluster {
+    SimCluster::new(num_servers).unit_heap_size_gb(1)
+}
+
+#[cfg(test)]
+impl SimCluster {
+    // Like run, but waits until no other test runs a simulation.
+    pub(crate) fn run_test<F>(self, main: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let _turn = test_turn();
+        self.run(main);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::*;
+
+    #[test]
+    fn on_server_switches_and_restores_the_active_server() {
+        test_cluster(3).run_test(async {
+            let heap_of = |i: usize| unsafe { GLOBAL_HEAP_START + i * WORKER_UNIT_SIZE };
+            assert_eq!(unsafe { (SERVER_INDEX, LOCAL_HEAP_START) }, (0, heap_of(0)));
+            let seen = on_server(2, || {
+                let nested = on_server(1, || unsafe { SERVER_INDEX });
+                (nested, unsafe { (SERVER_INDEX, LOCAL_HEAP_START) })
+            });
+            assert_eq!(seen, (1, (2, heap_of(2))));
+            assert_eq!(unsafe { (SERVER_INDEX, LOCAL_HEAP_START) }, (0, heap_of(0)));
+        });
+    }
+
+    #[test]
+    fn cluster_can_run_again_after_main_panicked() {
+        let failed =
+            panic::catch_unwind(|| test_cluster(2).run_test(async { panic!("main failed") }));
+        assert!(failed.is_err());
+        test_cluster(2).run_test(async {});
+    }
+}
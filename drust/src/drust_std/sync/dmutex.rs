@@ -1,7 +1,7 @@
 use std::{alloc::{Allocator, Layout}, mem, ptr, sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize}, thread::{self, current}};
 use dashmap::{mapref::entry::Entry, DashMap};
 use futures::lock::Mutex;
-use crate::{dassert, drust_std::{alloc::LOCAL_ALLOCATOR, comm::{drust_atomic_cmp_exchg_sync, drust_read_sync, drust_write_sync}, primitives::{current_place, dbox::DBox, DRust, Destination}, GLOBAL_HEAP_START, LOCAL_HEAP_START, NUM_SERVERS, SERVER_INDEX}, exclude};
+use crate::{dassert, drust_std::{alloc::{local_allocate, local_allocator}, comm::{drust_atomic_cmp_exchg_sync, drust_read_sync, drust_write_sync}, primitives::{current_place, dbox::DBox, DRust, Destination}, GLOBAL_HEAP_START, LOCAL_HEAP_START, NUM_SERVERS, SERVER_INDEX}, exclude};
 
 // TODO: DMutex currently requires mlx 4.
 // TODO: DMutex currently has lock priority issues.
@@ -70,9 +70,7 @@ impl <T: DRust + Sized> InnerMutex<T>{
         let current_addr = self.inner as usize;
         if current_place(current_addr) != Destination::Local {
             let new_addr = unsafe {
-                LOCAL_ALLOCATOR
-                    .allocate(Layout::new::<T>())
-                    .unwrap()
+                local_allocate(Layout::new::<T>())
                     .as_mut_ptr() as *mut T
             };
             unsafe {drust_read_sync(new_addr as usize - LOCAL_HEAP_START, 
@@ -1 +1,2 @@
+#[allow(unused, clippy::all)]
 pub mod dmutex;
\ No newline at end of file
@@ -0,0 +1,172 @@
+use std::{
+    net::SocketAddr,
+    sync::RwLock,
+    thread::available_parallelism,
+    time::{Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+use futures::future;
+use serde::{Deserialize, Serialize};
+use tarpc::{client, context};
+
+use super::steal;
+use crate::{
+    conf::*,
+    dprintln,
+    drust_std::{
+        connect::{
+            bootstrap::{connect_transport, phase_timeout, Phase},
+            config::CONFIG,
+            dsafepoint::DSafePointClient,
+        },
+        sim,
+        utils::{ResourceManager, SimpleResourceManager},
+    },
+};
+
+// What a server can run, as it reports it to the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capacity {
+    pub cpus: usize,
+    pub worker_threads: usize,
+    // Tasks the server takes at once: compute_slots from the config, else one per worker thread.
+    pub slots: usize,
+    // Pooled tasks queued or running on the server when it reported.
+    pub queue_depth: usize,
+}
+
+// Reports of all servers, by index, from the last discover.
+static CLUSTER: RwLock<Vec<Capacity>> = RwLock::new(Vec::new());
+
+// This server's capacity.
+pub fn local() -> Capacity {
+    let cpus = available_parallelism().map_or(1, |n| n.get());
+    let config = unsafe { CONFIG.as_ref() };
+    let worker_threads = config.and_then(|c| c.worker_threads).unwrap_or(cpus);
+    let slots = config
+        .and_then(|c| c.compute_slots[unsafe { SERVER_INDEX }])
+        .unwrap_or(worker_threads);
+    Capacity {
+        cpus,
+        worker_threads,
+        slots,
+        queue_depth: steal::queued() + steal::running(),
+    }
+}
+
+async fn ask(server_idx: usize, addr: SocketAddr, deadline: Instant) -> anyhow::Result<Capacity> {
+    let transport = connect_transport(addr, "safepoint server", server_idx, deadline).await?;
+    let client = DSafePointClient::new(client::Config::default(), transport).spawn();
+    let mut ctx = context::current();
+    ctx.deadline = SystemTime::now() + deadline.saturating_duration_since(Instant::now());
+    client
+        .capacity(ctx)
+        .await
+        .map_err(|e| anyhow!("server {} did not report its capacity: {}", server_idx, e))
+}
+
+// Collects the capacity of every server, and sizes this server's task pool from its own report.
+// Runs at startup; call it again to see current queue depths.
+pub async fn discover(safepoint_addrs: &[SocketAddr]) -> anyhow::Result<Vec<Capacity>> {
+    let reports = if sim::is_active() {
+        (0..unsafe { NUM_SERVERS }).map(|i| sim::on_server(i, local)).collect()
+    } else {
+        let num_servers = unsafe { NUM_SERVERS };
+        if safepoint_addrs.len() != num_servers {
+            bail!("{} safepoint addresses for {} servers", safepoint_addrs.len(), num_servers);
+        }
+        let deadline = Instant::now() + phase_timeout(Phase::AppClientsConnected);
+        let asks = safepoint_addrs
+            .iter()
+            .enumerate()
+            .map(|(i, addr)| ask(i, *addr, deadline));
+        let mut reports = Vec::with_capacity(safepoint_addrs.len());
+        let mut errors = Vec::new();
+        for result in future::join_all(asks).await {
+            match result {
+                Ok(report) => reports.push(report),
+                Err(e) => errors.push(format!("{:#}", e)),
+            }
+        }
+        if !errors.is_empty() {
+            bail!("{}", errors.join("; "));
+        }
+        reports
+    };
+    for (i, report) in reports.iter().enumerate() {
+        dprintln!(
+            "server {}: {} cpus, {} worker threads, {} slots, {} pooled tasks",
+            i, report.cpus, report.worker_threads, report.slots, report.queue_depth
+        );
+    }
+    steal::set_capacity(reports[unsafe { SERVER_INDEX }].slots);
+    *CLUSTER.write().unwrap() = reports.clone();
+    Ok(reports)
+}
+
+// Compute slots of `server_idx`, as last reported. Until discover has run, e.g. when it failed at
+// startup, this server's own slots stand in for every server, so pools sized from them assume a
+// cluster of equal servers.
+pub fn slots(server_idx: usize) -> usize {
+    match CLUSTER.read().unwrap().get(server_idx) {
+        Some(report) => report.slots,
+        None => local().slots,
+    }
+}
+
+// A pool for COMPUTES with the reported slots of every server.
+pub fn shared_computes() -> ResourceManager {
+    let slots: Vec<usize> = (0..unsafe { NUM_SERVERS }).map(slots).collect();
+    ResourceManager::with_slots(&slots)
+}
+
+// Pools for SIMPLE_COMPUTES, one per server with its reported slots.
+pub fn per_server_computes() -> Vec<SimpleResourceManager> {
+    (0..unsafe { NUM_SERVERS })
+        .map(|i| SimpleResourceManager::new(slots(i)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drust_std::{
+        comm::TransportKind,
+        connect::config::{ClusterConfig, ConfigFile},
+    };
+
+    fn config(json: &str) -> ClusterConfig {
+        let file: ConfigFile = serde_json::from_str(json).unwrap();
+        file.validate(TransportKind::Tcp).unwrap()
+    }
+
+    #[test]
+    fn discover_sizes_the_pool_from_the_configured_slots() {
+        sim::test_cluster(2).run_test(async {
+            let servers = r#"[{"host": "10.0.0.1", "compute_slots": 3}, {"host": "10.0.0.2"}]"#;
+            unsafe { CONFIG = Some(config(&format!(r#"{{"servers": {}}}"#, servers))) };
+            let reports = discover(&[]).await.unwrap();
+            assert_eq!(reports[0].slots, 3);
+            assert_eq!(reports[1].slots, reports[1].worker_threads);
+            assert_eq!(steal::capacity(), 3);
+            assert_eq!(slots(0), 3);
+
+            // What other tests see again.
+            unsafe { CONFIG = None };
+            discover(&[]).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn discover_needs_an_address_per_server() {
+        let _turn = sim::test_turn();
+        let num_servers = unsafe { NUM_SERVERS };
+        unsafe { NUM_SERVERS = 2 };
+        let addrs = ["127.0.0.1:1".parse().unwrap()];
+        let result = tokio::runtime::Runtime::new().unwrap().block_on(discover(&addrs));
+        unsafe { NUM_SERVERS = num_servers };
+        let error = result.unwrap_err().to_string();
+        assert_eq!(error, "1 safepoint addresses for 2 servers");
+    }
+}
@@ -1,8 +1,10 @@
 use std::{
-    mem::transmute,
+    mem::{self, transmute},
     net::SocketAddr,
+    panic::AssertUnwindSafe,
     pin::Pin,
-    ptr::{self, copy_nonoverlapping, from_mut},
+    ptr::{self, copy_nonoverlapping},
+    slice,
     sync::Arc,
 };
 
@@ -11,12 +13,20 @@ use tarpc::{
     client::{self, Config},
     context, server,
     server::{incoming::Incoming, Channel},
-    tokio_serde::formats::Json,
 };
 use tokio::runtime::Runtime;
 
+use super::{
+    join::{caught_panic, install_panic_hook, Capturing, TaskPanic},
+    steal::{self, StolenJob},
+};
 use crate::{
-    app::{dataframe::prelude::Chunk, gemm::par_strassen::{par_strassen_mul, single_strassen_mul}, socialnet::media::Image}, dprintln, drun_server, drust_std::{alloc::LOCAL_ALLOCATOR, collections::dvec::*}
+    drun_server,
+    drust_std::{
+        connect::bootstrap::{set_phase, Phase},
+        primitives::DRust,
+        sim,
+    },
 };
 
 #[tarpc::service]
@@ -24,8 +34,15 @@ pub trait DRustWorld {
     async fn remote_spawn(
         ptr: (usize, usize),
         future_bytes: Vec<u8>,
-        typeid: u32,
-    ) -> Vec<u8>;
+        runner: usize,
+    ) -> Result<Vec<u8>, TaskPanic>;
+    // Hands up to `max` queued pooled tasks to `thief`.
+    async fn steal(thief: usize, max: usize) -> Vec<StolenJob>;
+    // Confirms stolen tasks before `thief` runs them. Returns the ids it may run.
+    async fn steal_ack(thief: usize, ids: Vec<u64>) -> Vec<u64>;
+    async fn steal_done(id: u64, result: Result<Vec<u8>, TaskPanic>);
+    // Drops a stolen task this server runs, after its handle on the victim was aborted.
+    async fn cancel_stolen(id: u64);
 }
 
 // This is the type that implements the generated World trait. It is the business logic
@@ -40,191 +57,111 @@ impl DRustWorld for DRustServer {
         _: context::Context,
         ptr: (usize, usize),
         future_bytes: Vec<u8>,
-        typeid: u32,
-    ) -> Vec<u8> {
-        let siz = future_bytes.len();
-        let mut new_ptr = ptr;
-        unsafe {
-            let new_addr = vec![0u8; siz];
-            let (new_addr, new_len, new_cap) = new_addr.into_raw_parts();
-            unsafe { copy_nonoverlapping(future_bytes.as_ptr(), new_addr, siz) };
-            new_ptr.0 = new_addr as usize;
-            // println!("new_ptr: ({}, {}), siz: {}", new_ptr.0, new_ptr.1, siz);
-        }
-        if (typeid & 0xFF) == 1 {
-            let sub_typeid = typeid >> 8;
-            if (sub_typeid & 0xFF) == 1 {
-                let subsub_typeid = sub_typeid >> 8;
-                if subsub_typeid == 19 {
-                    let p = unsafe {
-                        transmute::<(usize, usize), Box<dyn Future<Output = DVec<DVec<u8>>> + Send>>(new_ptr)
-                    };
-                    let f = Pin::from(p);
-                    let mut v = f.await;
-                    let siz = std::mem::size_of_val(&v);
-                    let mut return_vals = Vec::with_capacity(siz);
-                    unsafe {
-                        let (addr, len, cap) = return_vals.into_raw_parts();
-                        let orig_addr = &v as *const DVec<DVec<u8>> as *const u8;
-                        let _ = v.into_raw_parts();
-                        copy_nonoverlapping(orig_addr, addr, siz);
-                        return_vals = Vec::from_raw_parts(addr, siz, cap);
-                    }
-                    return_vals
-
-                } else {
-                    panic!("Unsupported subsub type id: {}", subsub_typeid);
-                }
-            }
-            else if sub_typeid == 17 {
-                let p = unsafe {
-                    transmute::<(usize, usize), Box<dyn Future<Output = DVec<i32>> + Send>>(new_ptr)
-                };
-                let f = Pin::from(p);
-                let mut v = f.await;
-                let siz = std::mem::size_of_val(&v);
-                let mut return_vals = Vec::with_capacity(siz);
-                unsafe {
-                    let (addr, len, cap) = return_vals.into_raw_parts();
-                    let orig_addr = &v as *const DVec<i32> as *const u8;
-                    let _ = v.into_raw_parts();
-                    copy_nonoverlapping(orig_addr, addr, siz);
-                    return_vals = Vec::from_raw_parts(addr, siz, cap);
-                }
-                return_vals
-            } 
-            else if sub_typeid == 4 {
-                let p = unsafe {
-                    transmute::<(usize, usize), Box<dyn Future<Output = DVec<Chunk>> + Send>>(new_ptr)
-                };
-                let f = Pin::from(p);
-                let mut v = f.await;
-                let siz = std::mem::size_of_val(&v);
-                let mut return_vals = Vec::with_capacity(siz);
-                unsafe {
-                    let (addr, len, cap) = return_vals.into_raw_parts();
-                    let orig_addr = &v as *const DVec<Chunk> as *const u8;
-                    let _ = v.into_raw_parts();
-                    copy_nonoverlapping(orig_addr, addr, siz);
-                    return_vals = Vec::from_raw_parts(addr, siz, cap);
-                }
-                return_vals
-            }
-            else if (sub_typeid & 0xFF) == 16 {
-                let tuple_len = (sub_typeid >> 8);
-                if tuple_len == 2 {
-                    let p = unsafe {
-                        transmute::<(usize, usize), Box<dyn Future<Output = (DVec<usize>, DVec<usize>)> + Send>>(new_ptr)
-                    };
-                    let f = Pin::from(p);
-                    let mut v = f.await;
-                    let siz = std::mem::size_of_val(&v);
-                    let mut return_vals = Vec::with_capacity(siz);
-                    unsafe {
-                        let (addr, len, cap) = return_vals.into_raw_parts();
-                        let orig_addr = &v as *const (DVec<usize>, DVec<usize>) as *const u8;
-                        let (v1, v2) = v;
-                        let _ = v1.into_raw_parts();
-                        let _ = v2.into_raw_parts();
-                        copy_nonoverlapping(orig_addr, addr, siz);
-                        return_vals = Vec::from_raw_parts(addr, siz, cap);
-                    }
-                    return_vals
-                }
-                else if tuple_len == 3 {
-                    let p = unsafe {
-                        transmute::<(usize, usize), Box<dyn Future<Output = (DVec<usize>, DVec<usize>, DVec<usize>)> + Send>>(new_ptr)
-                    };
-                    let f = Pin::from(p);
-                    let mut v = f.await;
-                    let siz = std::mem::size_of_val(&v);
-                    let mut return_vals = Vec::with_capacity(siz);
-                    unsafe {
-                        let (addr, len, cap) = return_vals.into_raw_parts();
-                        let orig_addr = &v as *const (DVec<usize>, DVec<usize>, DVec<usize>) as *const u8;
-                        let (v1, v2, v3) = v;
-                        let _ = v1.into_raw_parts();
-                        let _ = v2.into_raw_parts();
-                        let _ = v3.into_raw_parts();
-                        copy_nonoverlapping(orig_addr, addr, siz);
-                        return_vals = Vec::from_raw_parts(addr, siz, cap);
-                    }
-                    return_vals
-                } else {
-                    panic!("Unsupported tuple length: {}", tuple_len)
-
-                }
-                
-            } else {
-                panic!("Unsupported sub type id: {}", sub_typeid);
-            }
-        } else if (typeid & 0xFF) == 2 {
-            let sub_typeid = typeid >> 8;
-            if sub_typeid == 21 {
-                let p = unsafe {
-                    transmute::<(usize, usize), Box<dyn Future<Output = DVecRef<'static, Image>> + Send>>(new_ptr)
-                };
-                let f = Pin::from(p);
-                let mut v = f.await;
-                dprintln!("media images v.len(): {} copy_exists: {}", v.len(), v.copy_exists);
-                v.drop_copy();
-                dprintln!("media images after drop copy v.len(): {} {}", v.len(), v.copy_exists);
-                let siz = std::mem::size_of_val(&v);
-                let mut return_vals = Vec::with_capacity(siz);
-                unsafe {
-                    let (addr, len, cap) = return_vals.into_raw_parts();
-                    let orig_addr = &v as *const DVecRef<'static, Image> as *const u8;
-                    copy_nonoverlapping(orig_addr, addr, siz);
-                    return_vals = Vec::from_raw_parts(addr, siz, cap);
-                }
-                
-                dprintln!("media images after copy v.len(): {}", v.len());
-                return_vals
-            } else {
-                panic!("Unsupported sub type id: {}", sub_typeid);
-            }
-        }
-         else if (typeid & 0xFF) == 64 {
-            let p = unsafe {
-                transmute::<(usize, usize), Box<dyn Future<Output = ()> + Send>>(new_ptr)
-            };
-            let f = Pin::from(p);
-            let mut v = f.await;
-            let siz = std::mem::size_of_val(&v);
-            let mut return_vals = Vec::with_capacity(siz);
-            unsafe {
-                let (addr, len, cap) = return_vals.into_raw_parts();
-                let orig_addr = &v as *const () as *const u8;
-                copy_nonoverlapping(orig_addr, addr, siz);
-                return_vals = Vec::from_raw_parts(addr, siz, cap);
-            }
-            return_vals
-        } else if (typeid & 0xFF) == 16 {
-            let p = unsafe {
-                transmute::<(usize, usize), Box<dyn Future<Output = usize> + Send>>(new_ptr)
-            };
-            let f = Pin::from(p);
-            let mut v = f.await;
-            let siz = std::mem::size_of_val(&v);
-            let mut return_vals = Vec::with_capacity(siz);
-            unsafe {
-                let (addr, len, cap) = return_vals.into_raw_parts();
-                let orig_addr = &v as *const usize as *const u8;
-                copy_nonoverlapping(orig_addr, addr, siz);
-                return_vals = Vec::from_raw_parts(addr, siz, cap);
-            }
-            return_vals
-        } else {
-            panic!("Unsupported type id: {}", typeid);
-        }
+        runner: usize,
+    ) -> Result<Vec<u8>, TaskPanic> {
+        execute_spawn(ptr, future_bytes, runner).await
+    }
+
+    async fn steal(self, _: context::Context, thief: usize, max: usize) -> Vec<StolenJob> {
+        steal::give_jobs(thief, max)
+    }
+
+    async fn steal_ack(self, _: context::Context, thief: usize, ids: Vec<u64>) -> Vec<u64> {
+        steal::confirm_jobs(thief, ids)
+    }
+
+    async fn steal_done(
+        self,
+        _: context::Context,
+        id: u64,
+        result: Result<Vec<u8>, TaskPanic>,
+    ) {
+        steal::finish_job(id, result)
+    }
+
+    async fn cancel_stolen(self, _: context::Context, id: u64) {
+        steal::abort_stolen(id)
+    }
+}
+
+// Polls a shipped future and returns the raw bytes of its output. The spawning side sends the
+// address of the instance for the future's output type, so servers need no table of supported
+// types. Like the future's vtable, the address is only valid because every server runs the same
+// binary.
+pub(crate) type Runner = fn((usize, usize)) -> Pin<Box<dyn Future<Output = Vec<u8>> + Send>>;
+
+pub(crate) fn runner<T: DRust + Send + 'static>() -> usize {
+    run_shipped::<T> as Runner as usize
+}
+
+fn run_shipped<T: DRust + Send + 'static>(
+    ptr: (usize, usize),
+) -> Pin<Box<dyn Future<Output = Vec<u8>> + Send>> {
+    Box::pin(async move {
+        let p = unsafe { transmute::<(usize, usize), Box<dyn Future<Output = T> + Send>>(ptr) };
+        let f = Pin::from(p);
+        let mut v = f.await;
+        v.drop_local_copies();
+        let return_vals = unsafe {
+            slice::from_raw_parts(&v as *const T as *const u8, mem::size_of::<T>()).to_vec()
+        };
+        mem::forget(v);
+        return_vals
+    })
+}
+
+// Takes over a value sent back by run_shipped.
+pub(crate) fn read_output<T>(return_vals: Vec<u8>) -> T {
+    assert!(return_vals.len() == mem::size_of::<T>());
+    unsafe { ptr::read_unaligned(return_vals.as_ptr() as *const T) }
+}
+
+// Rebuilds the shipped future from its bytes, runs it and returns the raw bytes of its output, or
+// the panic that stopped it.
+pub(crate) async fn execute_spawn(
+    ptr: (usize, usize),
+    future_bytes: Vec<u8>,
+    runner: usize,
+) -> Result<Vec<u8>, TaskPanic> {
+    let siz = future_bytes.len();
+    let mut new_ptr = ptr;
+    unsafe {
+        let new_addr = vec![0u8; siz];
+        let (new_addr, new_len, new_cap) = new_addr.into_raw_parts();
+        unsafe { copy_nonoverlapping(future_bytes.as_ptr(), new_addr, siz) };
+        new_ptr.0 = new_addr as usize;
+        // println!("new_ptr: ({}, {}), siz: {}", new_ptr.0, new_ptr.1, siz);
     }
+    let run = unsafe { transmute::<usize, Runner>(runner) };
+    install_panic_hook();
+    AssertUnwindSafe(Capturing::new(run(new_ptr)))
+        .catch_unwind()
+        .await
+        .map_err(caught_panic)
 }
 
 pub async fn run_server(server_addr: SocketAddr) {
-    drun_server!(server_addr, DRustServer);
+    drun_server!(server_addr, DRustServer, {
+        set_phase(Phase::AppServerUp);
+    });
 }
 
 pub static mut DCLIENTS: Option<Vec<Arc<DRustWorldClient>>> = None;
 pub fn get_dclient(server_idx: usize) -> Arc<DRustWorldClient> {
     unsafe { Arc::clone(DCLIENTS.as_ref().unwrap().get(server_idx).unwrap()) }
 }
+
+pub async fn dremote_spawn(
+    server_idx: usize,
+    ctx: context::Context,
+    ptr: (usize, usize),
+    future_bytes: Vec<u8>,
+    runner: usize,
+) -> Result<Result<Vec<u8>, TaskPanic>, client::RpcError> {
+    if sim::is_active() {
+        return Ok(sim::SimTask::on(server_idx, execute_spawn(ptr, future_bytes, runner)).await);
+    }
+    get_dclient(server_idx)
+        .remote_spawn(ctx, ptr, future_bytes, runner)
+        .await
+}
@@ -0,0 +1,299 @@
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
+    fmt,
+    future::Future,
+    panic,
+    pin::Pin,
+    sync::Once,
+    task::{ready, Context, Poll},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::conf::*;
+
+// A panic of a spawned task, caught on the server that ran it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaskPanic {
+    pub server_idx: usize,
+    pub message: String,
+    pub backtrace: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum JoinError {
+    Panic(TaskPanic),
+    // The task or its result was lost on the way, e.g. the server is down or the deadline passed.
+    Rpc { server_idx: usize, message: String },
+    // The task missed its deadline and was cancelled.
+    Timeout { server_idx: usize },
+    Cancelled,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panic(panic) => {
+                write!(f, "task panicked on server {}: {}", panic.server_idx, panic.message)?;
+                if !panic.backtrace.is_empty() {
+                    write!(f, "\nremote backtrace:\n{}", panic.backtrace)?;
+                }
+                Ok(())
+            }
+            JoinError::Rpc { server_idx, message } => {
+                write!(f, "task on server {} failed: {}", server_idx, message)
+            }
+            JoinError::Timeout { server_idx } => {
+                write!(f, "task on server {} missed its deadline", server_idx)
+            }
+            JoinError::Cancelled => write!(f, "task was cancelled"),
+        }
+    }
+}
+
+// Same as Display, so `handle.await.unwrap()` prints the remote backtrace readably.
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+// Result of dspawn and friends. Awaiting it gives the task's output, or why there is none.
+// Dropping the handle lets the task run on, unless abort_on_drop was set.
+pub struct DJoinHandle<T> {
+    inner: JoinHandle<Result<T, JoinError>>,
+    abort_on_drop: bool,
+}
+
+impl<T: Send + 'static> DJoinHandle<T> {
+    pub(crate) fn spawn<F>(task: F) -> Self
+    where
+        F: Future<Output = Result<T, JoinError>> + Send + 'static,
+    {
+        DJoinHandle {
+            inner: tokio::spawn(task),
+            abort_on_drop: false,
+        }
+    }
+}
+
+impl<T> DJoinHandle<T> {
+    pub fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+
+    // Stops the task. Its pending remote_spawn request is dropped, and tarpc sends a cancel
+    // message for it: the remote server drops the future at its next await point, so the DMut and
+    // DRef guards it holds run their normal drop logic. A pooled task still in the queue is
+    // removed, and one a peer stole is dropped there through cancel_stolen. Awaiting the handle
+    // gives Cancelled.
+    pub fn abort(&self) {
+        self.inner.abort();
+    }
+
+    // Aborts the task when the handle is dropped, e.g. by tokio::time::timeout.
+    pub fn abort_on_drop(mut self) -> Self {
+        self.abort_on_drop = true;
+        self
+    }
+}
+
+impl<T> Drop for DJoinHandle<T> {
+    fn drop(&mut self) {
+        if self.abort_on_drop {
+            self.inner.abort();
+        }
+    }
+}
+
+impl<T> Future for DJoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(match ready!(Pin::new(&mut self.inner).poll(cx)) {
+            Ok(result) => result,
+            Err(e) if e.is_cancelled() => Err(JoinError::Cancelled),
+            // The local task that waits for the remote result panicked.
+            Err(e) => Err(JoinError::Panic(TaskPanic {
+                server_idx: unsafe { SERVER_INDEX },
+                message: panic_message(e.into_panic().as_ref()),
+                backtrace: String::new(),
+            })),
+        })
+    }
+}
+
+thread_local! {
+    // Set while a spawned task is polled on this thread.
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+    static LAST_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+// Makes a panic inside a Capturing task leave its backtrace for caught_panic. Other panics are
+// left alone, and the previous hook runs for every panic, so it is logged on the server where it
+// happened as well.
+pub(crate) fn install_panic_hook() {
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CAPTURING.with(Cell::get) {
+                LAST_BACKTRACE.with(|last| *last.borrow_mut() = Some(Backtrace::force_capture()));
+            }
+            previous(info);
+        }));
+    });
+}
+
+// Polls a spawned task with backtrace capture on. The flag is per thread and per poll, since the
+// task may move to another worker between polls.
+pub(crate) struct Capturing<F: Future> {
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Capturing<F> {
+    pub(crate) fn new(future: F) -> Self {
+        Capturing { inner: Box::pin(future) }
+    }
+}
+
+impl<F: Future> Future for Capturing<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Restores the flag also when the poll unwinds.
+        struct Restore(bool);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CAPTURING.with(|capturing| capturing.set(self.0));
+            }
+        }
+        let _restore = Restore(CAPTURING.with(|capturing| capturing.replace(true)));
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+// Turns the payload of a panic caught on this thread into a TaskPanic.
+pub(crate) fn caught_panic(payload: Box<dyn Any + Send>) -> TaskPanic {
+    let backtrace = LAST_BACKTRACE.with(|last| last.borrow_mut().take());
+    TaskPanic {
+        server_idx: unsafe { SERVER_INDEX },
+        message: panic_message(payload.as_ref()),
+        backtrace: backtrace.map(|b| b.to_string()).unwrap_or_default(),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::AssertUnwindSafe,
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+
+    use futures::FutureExt;
+    use tokio::time::sleep;
+
+    use super::*;
+    use crate::drust_std::{
+        alloc::Placement,
+        sim,
+        thread::{dspawn_to_relaxed, dspawn_with, ResourceClass, SpawnOptions},
+    };
+
+    #[test]
+    #[allow(clippy::unnecessary_literal_unwrap)]
+    fn panic_comes_back_with_its_server() {
+        sim::test_cluster(2).run_test(async {
+            let handle = dspawn_to_relaxed(async { None::<usize>.expect("nothing to return") }, 1);
+            match handle.await {
+                Err(JoinError::Panic(panic)) => {
+                    assert_eq!(panic.server_idx, 1);
+                    assert_eq!(panic.message, "nothing to return");
+                    assert!(!panic.backtrace.is_empty());
+                }
+                other => panic!("expected a panic, got {:?}", other.map(|_| ())),
+            }
+        });
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_literal_unwrap)]
+    fn only_task_panics_capture_a_backtrace() {
+        install_panic_hook();
+        std::thread::spawn(|| {
+            let _ = panic::catch_unwind(|| None::<()>.expect("outside a task"));
+            assert!(LAST_BACKTRACE.with(|last| last.borrow().is_none()));
+
+            let task = Capturing::new(async { None::<()>.expect("inside a task") });
+            let _ = futures::executor::block_on(AssertUnwindSafe(task).catch_unwind());
+            assert!(LAST_BACKTRACE.with(|last| last.borrow().is_some()));
+            assert!(!CAPTURING.with(Cell::get));
+        })
+        .join()
+        .unwrap();
+    }
+
+    static STARTED: AtomicBool = AtomicBool::new(false);
+    static DROPPED: AtomicBool = AtomicBool::new(false);
+
+    struct SetOnDrop(&'static AtomicBool);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn abort_drops_the_remote_task() {
+        sim::test_cluster(2).run_test(async {
+            let handle = dspawn_to_relaxed(
+                async {
+                    let _dropped = SetOnDrop(&DROPPED);
+                    STARTED.store(true, Ordering::SeqCst);
+                    sleep(Duration::from_secs(60)).await;
+                    0usize
+                },
+                1,
+            );
+            while !STARTED.load(Ordering::SeqCst) {
+                tokio::task::yield_now().await;
+            }
+            handle.abort();
+            assert!(matches!(handle.await, Err(JoinError::Cancelled)));
+            assert!(DROPPED.load(Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn missed_deadline_times_out() {
+        sim::test_cluster(2).run_test(async {
+            let options = SpawnOptions::new()
+                .deadline(Duration::from_millis(10))
+                .on(Placement::On(1))
+                .resource_class(ResourceClass::Unmetered);
+            let handle = dspawn_with(options, || async {
+                sleep(Duration::from_secs(60)).await;
+                0usize
+            });
+            assert!(matches!(handle.await, Err(JoinError::Timeout { server_idx: 1 })));
+        });
+    }
+}
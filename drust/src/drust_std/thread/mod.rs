@@ -1,125 +1,160 @@
+pub mod capacity;
+#[allow(unused, clippy::all)]
 pub mod dlink;
+pub mod join;
+pub mod options;
+pub mod scheduler;
+pub mod steal;
 
 use std::{
     mem::transmute,
-    ptr::{self, copy_nonoverlapping}, time::{Duration, SystemTime},
+    ptr::copy_nonoverlapping,
+    time::{Duration, SystemTime},
 };
 
-use crate::drust_std::{NUM_SERVERS, RPC_WAIT};
+use crate::drust_std::{NUM_SERVERS, RPC_WAIT, SERVER_INDEX};
 use futures::{Future, FutureExt};
-use tarpc::context;
-use tokio::task::JoinHandle;
+use tarpc::{client::RpcError, context};
+use tokio::time::{sleep, timeout_at, Instant};
 
-use self::dlink::get_dclient;
+use self::dlink::{dremote_spawn, read_output, runner};
+pub use self::join::{DJoinHandle, JoinError, TaskPanic};
+pub use self::options::{ResourceClass, SpawnOptions};
+pub use self::scheduler::Footprint;
 
-use super::{collections::dvec::DVec, primitives::DRust, Resource, SimpleResource, COMPUTES, GLOBAL_HEAP_START, SIMPLE_COMPUTES, WORKER_UNIT_SIZE};
+use super::{
+    primitives::DRust, sim::SimTask, Resource, SimpleResource, COMPUTES, GLOBAL_HEAP_START,
+    SIMPLE_COMPUTES, WORKER_UNIT_SIZE,
+};
 
-pub fn inner_spawn<F, T>(future: F, resource: Resource) -> JoinHandle<T>
+// Moves `future` out of this process: returns the fat pointer of the boxed future and a copy of
+// its bytes. The box itself is leaked, as the remote server takes over the captured state.
+fn ship<F>(future: F) -> ((usize, usize), Vec<u8>)
 where
-    T: DRust + Send + Default + 'static,
     F: Future + Send,
-    F::Output: Send,
 {
-    let mut f = future.boxed();
+    let f = future.boxed();
     let siz = std::mem::size_of_val(&(*f));
-    let mut values = unsafe { transmute::<_, (usize, usize)>(f.pointer) };
+    let values = unsafe { transmute::<_, (usize, usize)>(f.pointer) };
 
     let mut stack_vec = vec![0u8; siz];
     unsafe {
-        let (addr, mut len, cap) = stack_vec.into_raw_parts();
+        let (addr, _, cap) = stack_vec.into_raw_parts();
         let orig_addr = values.0 as *mut u8;
         copy_nonoverlapping(orig_addr, addr, siz);
-        len = siz;
-        stack_vec = Vec::from_raw_parts(addr, len, cap);
+        stack_vec = Vec::from_raw_parts(addr, siz, cap);
     }
-    tokio::spawn(async move {
-        let client = get_dclient(resource.id % NUM_SERVERS);
-        let mut ctx = context::current();
-        ctx.deadline = SystemTime::now() + Duration::from_secs(RPC_WAIT);
-        let p = client.remote_spawn(ctx, values, stack_vec, T::static_typeid());
-        let return_vec = p.await.unwrap();
-        let mut return_vals = T::default();
-        let siz = std::mem::size_of_val(&return_vals);
-        assert!(siz == return_vec.len());
-        unsafe {
-            let addr = &mut return_vals as *mut T as *mut u8;
-            copy_nonoverlapping(return_vec.as_ptr(), addr, siz);
-        }
-        resource.release();
-        return_vals
-    })
+    (values, stack_vec)
+}
 
+fn default_deadline() -> Instant {
+    Instant::now() + Duration::from_secs(RPC_WAIT)
 }
 
-pub fn inner_spawn_strict<F, T>(future: F, resource: Option<SimpleResource>, server_idx: usize) -> JoinHandle<T>
+// Runs a shipped future on `server_idx` and takes over its output. Past `deadline` the call is
+// dropped, which cancels the task on the remote server.
+async fn call_remote<T>(
+    server_idx: usize,
+    values: (usize, usize),
+    stack_vec: Vec<u8>,
+    deadline: Instant,
+) -> Result<T, JoinError>
 where
-    T: DRust + Send + Default + 'static,
-    F: Future + Send,
-    F::Output: Send,
+    T: DRust + Send + 'static,
 {
-    let mut f = future.boxed();
-    let siz = std::mem::size_of_val(&(*f));
-    let mut values = unsafe { transmute::<_, (usize, usize)>(f.pointer) };
-    let mut stack_vec = vec![0u8; siz];
-    unsafe {
-        let (addr, mut len, cap) = stack_vec.into_raw_parts();
-        let orig_addr = values.0 as *mut u8;
-        copy_nonoverlapping(orig_addr, addr, siz);
-        len = siz;
-        stack_vec = Vec::from_raw_parts(addr, len, cap);
+    let mut ctx = context::current();
+    ctx.deadline = SystemTime::now() + deadline.saturating_duration_since(Instant::now());
+    let call = dremote_spawn(server_idx, ctx, values, stack_vec, runner::<T>());
+    match timeout_at(deadline, call).await {
+        Ok(Ok(Ok(return_vec))) => Ok(read_output::<T>(return_vec)),
+        Ok(Ok(Err(panic))) => Err(JoinError::Panic(panic)),
+        Err(_) | Ok(Err(RpcError::DeadlineExceeded)) => Err(JoinError::Timeout { server_idx }),
+        Ok(Err(e)) => Err(JoinError::Rpc {
+            server_idx,
+            message: e.to_string(),
+        }),
     }
-    tokio::spawn(async move {
-        let client = get_dclient(server_idx % NUM_SERVERS);
-        let mut ctx = context::current();
-        ctx.deadline = SystemTime::now() + Duration::from_secs(RPC_WAIT);
-        let p = client.remote_spawn(ctx, values, stack_vec, T::static_typeid());
-        let return_vec = p.await.unwrap();
-        let mut return_vals = T::default();
-        let siz = std::mem::size_of_val(&return_vals);
-        assert!(siz == return_vec.len());
-        unsafe {
-            let addr = &mut return_vals as *mut T as *mut u8;
-            copy_nonoverlapping(return_vec.as_ptr(), addr, siz);
-        }
-        match resource {
-            Some(resource) => resource.release(),
-            None => {}
+}
+
+// Releases a compute resource when the task finishes, fails or is dropped.
+struct OnDrop<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> Drop for OnDrop<F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.0.take() {
+            f();
         }
-        return_vals
-    })
+    }
+}
+
+pub fn inner_spawn<F, T>(future: F, resource: Resource) -> DJoinHandle<T>
+where
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
+{
+    let (values, stack_vec) = ship(future);
+    spawn_shipped(values, stack_vec, resource)
 }
 
+fn spawn_shipped<T>(
+    values: (usize, usize),
+    stack_vec: Vec<u8>,
+    resource: Resource,
+) -> DJoinHandle<T>
+where
+    T: DRust + Send + 'static,
+{
+    let server_idx = resource.server_idx();
+    DJoinHandle::spawn(SimTask::current(async move {
+        let _release = OnDrop(Some(move || resource.release()));
+        call_remote::<T>(server_idx, values, stack_vec, default_deadline()).await
+    }))
+}
 
-pub fn dspawn<F, T>(future: F) -> JoinHandle<T>
+pub fn inner_spawn_strict<F, T>(future: F, resource: Option<SimpleResource>, server_idx: usize) -> DJoinHandle<T>
 where
-    T: DRust + Send + Default + 'static,
-    F: Future + Send,
-    F::Output: Send,
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
 {
-    
+    let (values, stack_vec) = ship(future);
+    let server_idx = server_idx % unsafe { NUM_SERVERS };
+    DJoinHandle::spawn(SimTask::current(async move {
+        let _release = OnDrop(resource.map(|resource| move || resource.release()));
+        call_remote::<T>(server_idx, values, stack_vec, default_deadline()).await
+    }))
+}
+
+
+// Runs `future` on a slot of COMPUTES next to the heap data it captures, as scheduler::place
+// picks it for the handles found in the future's state. dspawn_near weighs the data by its size.
+pub fn dspawn<F, T>(future: F) -> DJoinHandle<T>
+where
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
+{
+    let (values, stack_vec) = ship(future);
+    let server_idx = scheduler::place(&scheduler::Captured(&stack_vec));
     let thread_manager = unsafe { COMPUTES.as_ref().unwrap() };
-    let resource = thread_manager.get_resource(0);
-    inner_spawn(future, resource)
+    let resource = thread_manager.get_resource(server_idx);
+    spawn_shipped(values, stack_vec, resource)
 }
 
-pub fn dspawn_to<F, T>(future: F, addr: usize) -> JoinHandle<T>
+pub fn dspawn_to<F, T>(future: F, addr: usize) -> DJoinHandle<T>
 where
-    T: DRust + Send + Default + 'static,
-    F: Future + Send,
-    F::Output: Send,
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
 {
-    let server_idx = (addr - GLOBAL_HEAP_START) / WORKER_UNIT_SIZE;
+    let server_idx = unsafe { (addr - GLOBAL_HEAP_START) / WORKER_UNIT_SIZE };
     let thread_manager = unsafe { COMPUTES.as_ref().unwrap() };
     let resource = thread_manager.get_resource(server_idx);
     inner_spawn(future, resource)
 }
 
 
-pub fn dspawn_to_strictly<F, T>(future: F, server_idx: usize) -> JoinHandle<T>
+pub fn dspawn_to_strictly<F, T>(future: F, server_idx: usize) -> DJoinHandle<T>
 where
-    T: DRust + Send + Default + 'static,
-    F: Future + Send,
-    F::Output: Send,
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
 {
     match unsafe { SIMPLE_COMPUTES.as_ref() } {
         None => {
@@ -133,51 +168,139 @@ where
     }
 }
 
-pub fn dspawn_to_relaxed<F, T>(future: F, server_idx: usize) -> JoinHandle<T>
+pub fn dspawn_to_relaxed<F, T>(future: F, server_idx: usize) -> DJoinHandle<T>
 where
-    T: DRust + Send + Default + 'static,
-    F: Future + Send,
-    F::Output: Send,
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
 {
     inner_spawn_strict(future, None, server_idx)
 }
 
 
 
-pub async fn dscope_spawn<F, T>(future: F) -> T
+pub async fn dscope_spawn<F, T>(future: F) -> Result<T, JoinError>
 where
-    T: DRust + Send + Default,
-    F: Future + Send,
-    F::Output: Send,
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
 {
-    
+    let (values, stack_vec) = ship(future);
     let thread_manager = unsafe { COMPUTES.as_ref().unwrap() };
-    let resource = thread_manager.get_resource(0);
+    let resource = thread_manager.get_resource(scheduler::place(&scheduler::Captured(&stack_vec)));
+    let server_idx = resource.server_idx();
+    let _release = OnDrop(Some(move || resource.release()));
 
-    let mut f = future.boxed();
-    let siz = std::mem::size_of_val(&(*f));
-    let mut values = unsafe { transmute::<_, (usize, usize)>(f.pointer) };
+    call_remote::<T>(server_idx, values, stack_vec, default_deadline()).await
+}
 
-    let mut stack_vec = vec![0u8; siz];
-    unsafe {
-        let (addr, mut len, cap) = stack_vec.into_raw_parts();
-        let orig_addr = values.0 as *mut u8;
-        copy_nonoverlapping(orig_addr, addr, siz);
-        len = siz;
-        stack_vec = Vec::from_raw_parts(addr, len, cap);
-    }
-    let client = get_dclient(resource.id % NUM_SERVERS);
-    let mut ctx = context::current();
-    ctx.deadline = SystemTime::now() + Duration::from_secs(RPC_WAIT);
-    let p = client.remote_spawn(ctx, values, stack_vec, T::static_typeid());
-    let return_vec = p.await.unwrap();
-    let mut return_vals = T::default();
-    let siz = std::mem::size_of_val(&return_vals);
-    assert!(siz == return_vec.len());
-    unsafe {
-        let addr = &mut return_vals as *mut T as *mut u8;
-        copy_nonoverlapping(return_vec.as_ptr(), addr, siz);
+// Queues `future` in this server's task pool. It runs on the first server with a free slot, this
+// one or an idle peer that steals it.
+pub fn dspawn_pooled<F, T>(future: F) -> DJoinHandle<T>
+where
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
+{
+    inner_spawn_pooled(future, None)
+}
+
+// Like dspawn_pooled, but only `server_idx` runs the task.
+pub fn dspawn_pooled_to<F, T>(future: F, server_idx: usize) -> DJoinHandle<T>
+where
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
+{
+    inner_spawn_pooled(future, Some(server_idx % unsafe { NUM_SERVERS }))
+}
+
+fn inner_spawn_pooled<F, T>(future: F, pin: Option<usize>) -> DJoinHandle<T>
+where
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
+{
+    let (values, stack_vec) = ship(future);
+    let result = steal::submit(pin, values, stack_vec, runner::<T>());
+    let server_idx = pin.unwrap_or(unsafe { SERVER_INDEX });
+    DJoinHandle::spawn(SimTask::current(async move {
+        match timeout_at(default_deadline(), result).await {
+            Ok(Ok(Ok(return_vec))) => Ok(read_output::<T>(return_vec)),
+            Ok(Ok(Err(panic))) => Err(JoinError::Panic(panic)),
+            Ok(Err(_)) => Err(JoinError::Rpc {
+                server_idx,
+                message: "the pooled task was lost".to_string(),
+            }),
+            Err(_) => Err(JoinError::Timeout { server_idx }),
+        }
+    }))
+}
+
+// Spawns `make(data)` on the server that holds most of `data`, unless that server is much busier
+// than others. Pass the handles the task works on, e.g. a tuple of DVecRefs, as `data`.
+pub fn dspawn_near<D, M, F, T>(data: D, make: M) -> DJoinHandle<T>
+where
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send,
+    D: Footprint,
+    M: FnOnce(D) -> F,
+{
+    let server_idx = scheduler::place(&data);
+    let thread_manager = unsafe { COMPUTES.as_ref().unwrap() };
+    let resource = thread_manager.get_resource(server_idx);
+    inner_spawn(make(data), resource)
+}
+
+// Spawns the future built by `make` as set up by `options`. `make` runs again for every retry, so
+// each attempt starts from fresh captured state rather than a copy of a future that may have
+// partly run.
+pub fn dspawn_with<M, F, T>(options: SpawnOptions, make: M) -> DJoinHandle<T>
+where
+    T: DRust + Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+    M: Fn() -> F + Send + 'static,
+{
+    let (server_idx, release) = options.acquire();
+    let deadline = Instant::now() + options.deadline;
+    DJoinHandle::spawn(SimTask::current(async move {
+        let _release = OnDrop(Some(release));
+        let mut retries = options.retries;
+        let mut backoff = options.backoff;
+        loop {
+            let (values, stack_vec) = ship(make());
+            match call_remote::<T>(server_idx, values, stack_vec, deadline).await {
+                Err(JoinError::Rpc { .. })
+                    if retries > 0 && Instant::now() + backoff < deadline =>
+                {
+                    retries -= 1;
+                    sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drust_std::{
+        alloc::Placement,
+        primitives::dbox::DBox,
+        sim,
+        utils::ResourceManager,
+    };
+
+    #[test]
+    fn dspawn_runs_next_to_captured_data() {
+        sim::test_cluster(3).run_test(async {
+            unsafe { COMPUTES = Some(ResourceManager::new(3)) };
+            for server_idx in 0..3 {
+                let data = DBox::new_on(server_idx, Placement::On(server_idx));
+                let handle = dspawn(async move {
+                    assert_eq!(*data, server_idx);
+                    unsafe { SERVER_INDEX }
+                });
+                assert_eq!(handle.await.unwrap(), server_idx);
+            }
+            unsafe { COMPUTES = None };
+        });
     }
-    resource.release();
-    return_vals
 }
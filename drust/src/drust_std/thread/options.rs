@@ -0,0 +1,121 @@
+use std::{alloc::Layout, time::Duration};
+
+use crate::{
+    conf::*,
+    drust_std::{
+        alloc::Placement,
+        utils::{COMPUTES, SIMPLE_COMPUTES},
+    },
+};
+
+use super::scheduler::{self, Footprint};
+
+// Which compute slot a spawned task holds while it runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResourceClass {
+    // A slot of the cluster-wide pool, as taken by dspawn and dspawn_to. The task runs on the
+    // server of the slot it gets, which is the target one if that has a free slot.
+    #[default]
+    Shared,
+    // A slot of the target server's own pool, as taken by dspawn_to_strictly.
+    Server,
+    // No slot, as with dspawn_to_relaxed.
+    Unmetered,
+}
+
+// How dspawn_with runs a task, e.g.
+// SpawnOptions::new().deadline(Duration::from_millis(20)).retries(2).on(Placement::Near(addr)).
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnOptions {
+    pub(crate) deadline: Duration,
+    pub(crate) retries: usize,
+    pub(crate) backoff: Duration,
+    pub(crate) placement: Option<Placement>,
+    pub(crate) class: ResourceClass,
+}
+
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        SpawnOptions {
+            deadline: Duration::from_secs(RPC_WAIT),
+            retries: 0,
+            backoff: Duration::from_millis(1),
+            placement: None,
+            class: ResourceClass::Shared,
+        }
+    }
+}
+
+impl SpawnOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Time the task may take, retries included. Past it the task is cancelled and the handle
+    // gives JoinError::Timeout. Defaults to RPC_WAIT.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    // How often a task lost on the way (JoinError::Rpc) is run again. Only for idempotent tasks,
+    // as a lost task may have run partly or fully. Panics, timeouts and aborts are not retried.
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    // Pause before the first retry, doubled for every further one.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    // Server to run the task on. Without it, Shared takes any free slot and the other classes go
+    // round robin.
+    pub fn on(mut self, placement: impl Into<Placement>) -> Self {
+        self.placement = Some(placement.into());
+        self
+    }
+
+    // Runs the task next to `data`, weighed against the load of each server at the time of this
+    // call. See scheduler::schedule.
+    pub fn near(self, data: &(impl Footprint + ?Sized)) -> Self {
+        self.on(scheduler::place(data))
+    }
+
+    pub fn resource_class(mut self, class: ResourceClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    // Picks the server and takes a slot of the resource class. Blocks until one is free. Returns
+    // the server and what gives the slot back.
+    pub(crate) fn acquire(&self) -> (usize, Box<dyn FnOnce() + Send>) {
+        let target = |default: Placement| {
+            self.placement
+                .unwrap_or(default)
+                .server_idx(Layout::new::<()>())
+        };
+        match self.class {
+            ResourceClass::Shared => {
+                let start_id = self.placement.map_or(0, |_| target(Placement::Local));
+                let thread_manager = unsafe { COMPUTES.as_ref().unwrap() };
+                let resource = thread_manager.get_resource(start_id);
+                let server_idx = resource.server_idx();
+                (server_idx, Box::new(move || resource.release()))
+            }
+            ResourceClass::Server => {
+                let server_idx = target(Placement::RoundRobin);
+                match unsafe { SIMPLE_COMPUTES.as_ref() } {
+                    None => (server_idx, Box::new(|| {})),
+                    Some(thread_managers) => {
+                        let resource = thread_managers.get(server_idx).unwrap().get_resource();
+                        (server_idx, Box::new(move || resource.release()))
+                    }
+                }
+            }
+            ResourceClass::Unmetered => (target(Placement::RoundRobin), Box::new(|| {})),
+        }
+    }
+}
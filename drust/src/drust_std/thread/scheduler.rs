@@ -0,0 +1,167 @@
+use std::mem;
+
+use crate::{
+    conf::*,
+    drust_std::{
+        collections::dvec::{DVec, DVecMutRef, DVecRef},
+        primitives::{dbox::DBox, dmut::DMut, dref::DRef, DRust},
+        sync::dmutex::DMutex,
+        utils::{COMPUTES, SIMPLE_COMPUTES},
+    },
+};
+
+// A busy compute slot weighs as much as moving this many bytes to a server.
+pub const SLOT_COST: usize = 1 << 20;
+
+// A handle found in a captured future stands for this many bytes, as the size of its data is
+// not known.
+pub const CAPTURED_COST: usize = SLOT_COST;
+
+// Where the heap data behind a value lives, so a task can be scheduled next to it.
+pub trait Footprint {
+    // Calls `visit(addr, bytes)` for every block of heap data the value refers to.
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize));
+}
+
+// Bytes of `data` held by each server. Memory outside the DRust heap is not counted.
+pub fn bytes_per_server(data: &(impl Footprint + ?Sized)) -> Vec<usize> {
+    let (heap_start, heap_size, unit_size, num_servers) =
+        unsafe { (GLOBAL_HEAP_START, WORKER_HEAP_SIZE, WORKER_UNIT_SIZE, NUM_SERVERS) };
+    let mut bytes = vec![0; num_servers];
+    data.footprint(&mut |addr, size| {
+        if addr >= heap_start && addr < heap_start + heap_size {
+            bytes[(addr - heap_start) / unit_size] += size;
+        }
+    });
+    bytes
+}
+
+// Tasks this server runs on each of the servers, as far as its compute pools know.
+pub fn load_per_server() -> Vec<usize> {
+    let num_servers = unsafe { NUM_SERVERS };
+    match unsafe { (COMPUTES.as_ref(), SIMPLE_COMPUTES.as_ref()) } {
+        (Some(thread_manager), _) => (0..num_servers).map(|idx| thread_manager.busy_on(idx)).collect(),
+        (None, Some(thread_managers)) => thread_managers.iter().map(|m| m.busy()).collect(),
+        (None, None) => vec![0; num_servers],
+    }
+}
+
+// Picks the server for a task that touches `bytes` on each server: the one where the bytes to
+// move plus its load cost least. Ties go to this server, then to the lower index.
+pub fn schedule(bytes: &[usize]) -> usize {
+    let load = load_per_server();
+    let total: usize = bytes.iter().sum();
+    let local = unsafe { SERVER_INDEX };
+    let cost = |idx: usize| total - bytes[idx] + load[idx] * SLOT_COST;
+    (0..bytes.len())
+        .min_by_key(|&idx| (cost(idx), idx != local, idx))
+        .unwrap_or(local)
+}
+
+// The server a task using `data` should run on.
+pub fn place(data: &(impl Footprint + ?Sized)) -> usize {
+    schedule(&bytes_per_server(data))
+}
+
+// The captured state of a shipped future. Every word of it that points into the global heap is
+// taken as a handle the task works on, e.g. a captured DBox or DVecRef.
+pub struct Captured<'a>(pub &'a [u8]);
+
+impl Footprint for Captured<'_> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        for word in self.0.chunks_exact(mem::size_of::<usize>()) {
+            visit(usize::from_ne_bytes(word.try_into().unwrap()), CAPTURED_COST);
+        }
+    }
+}
+
+impl<T: DRust> Footprint for DBox<T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        visit(self.get_addr(), mem::size_of::<T>());
+    }
+}
+
+impl<T: DRust> Footprint for DMut<'_, T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        visit(self.orig as *const T as usize, mem::size_of::<T>());
+    }
+}
+
+impl<T: DRust> Footprint for DRef<'_, T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        visit(self.orig as *const T as usize, mem::size_of::<T>());
+    }
+}
+
+impl<T: DRust> Footprint for DMutex<T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        visit(self.orig as usize, mem::size_of::<T>());
+    }
+}
+
+impl<T: DRust> Footprint for DVec<T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        if let Some(vec) = self.internal_vec.as_ref() {
+            visit(vec.as_ptr() as usize, vec.len() * mem::size_of::<T>());
+        }
+    }
+}
+
+impl<T: DRust> Footprint for DVecRef<'_, T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        visit(self.orig_raw.0, self.orig_raw.1 * mem::size_of::<T>());
+    }
+}
+
+impl<T: DRust> Footprint for DVecMutRef<'_, T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        visit(self.orig_raw.0, self.orig_raw.1 * mem::size_of::<T>());
+    }
+}
+
+impl<T: Footprint + ?Sized> Footprint for &T {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        (**self).footprint(visit);
+    }
+}
+
+impl<T: Footprint> Footprint for Option<T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        if let Some(value) = self {
+            value.footprint(visit);
+        }
+    }
+}
+
+impl<T: Footprint> Footprint for [T] {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        for value in self {
+            value.footprint(visit);
+        }
+    }
+}
+
+impl<T: Footprint> Footprint for Vec<T> {
+    fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+        self.as_slice().footprint(visit);
+    }
+}
+
+macro_rules! tuple_footprint {
+    ($($name:ident),+) => {
+        impl<$($name: Footprint),+> Footprint for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn footprint(&self, visit: &mut dyn FnMut(usize, usize)) {
+                let ($($name,)+) = self;
+                $($name.footprint(visit);)+
+            }
+        }
+    };
+}
+
+tuple_footprint!(A);
+tuple_footprint!(A, B);
+tuple_footprint!(A, B, C);
+tuple_footprint!(A, B, C, D);
+tuple_footprint!(A, B, C, D, E);
+tuple_footprint!(A, B, C, D, E, F);
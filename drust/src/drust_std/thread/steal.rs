@@ -0,0 +1,487 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+    thread::available_parallelism,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+};
+use serde::{Deserialize, Serialize};
+use tarpc::{client::RpcError, context};
+use tokio::{runtime::Handle, time::sleep};
+
+use super::{
+    dlink::{execute_spawn, get_dclient},
+    join::TaskPanic,
+};
+use crate::{
+    conf::*,
+    dprintln,
+    drust_std::sim::{self, SimTask},
+};
+
+// Pooled tasks wait in a queue on the server that spawned them. A server runs queued tasks itself
+// while it has free slots. A server with free slots and nothing of its own to run asks its peers
+// for their oldest tasks, so idle servers pick up the backlog of busy ones. Queues are FIFO and
+// steals take from the front, so tasks start in the order they were spawned. A task pinned to a
+// server is only run by that server. A thief confirms the tasks it got with steal_ack before it
+// runs them; tasks it does not confirm in time go back to the victim's queue. Aborting the
+// handle of a stolen task sends cancel_stolen to the thief, which drops it.
+
+// Pause of an idle stealer between steal rounds, doubled after every round that found nothing.
+pub const STEAL_MIN_INTERVAL: Duration = Duration::from_millis(1);
+pub const STEAL_MAX_INTERVAL: Duration = Duration::from_millis(100);
+// A stolen task the thief has not confirmed after this long is queued again, e.g. when the steal
+// reply was lost.
+pub const STEAL_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+// Attempts of a thief to deliver a result with steal_done.
+const STEAL_DONE_ATTEMPTS: usize = 5;
+
+type TaskResult = Result<Vec<u8>, TaskPanic>;
+
+struct Job {
+    id: u64,
+    pin: Option<usize>,
+    ptr: (usize, usize),
+    future_bytes: Vec<u8>,
+    runner: usize,
+    reply: oneshot::Sender<TaskResult>,
+}
+
+// A queued task handed to another server by steal.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StolenJob {
+    pub id: u64,
+    pub ptr: (usize, usize),
+    pub future_bytes: Vec<u8>,
+    pub runner: usize,
+}
+
+enum Stolen {
+    // Sent to `thief`, which has not confirmed it yet.
+    Offered { thief: usize, since: Instant, job: Job },
+    // Confirmed by the thief. steal_done brings the result to forward_stolen.
+    Running { done: oneshot::Sender<TaskResult> },
+}
+
+struct Pool {
+    pending: VecDeque<Job>,
+    // Tasks handed to a thief, by id.
+    stolen: BTreeMap<u64, Stolen>,
+    // Tasks of peers this server runs, by id. Dropping the sender drops the task.
+    running_stolen: BTreeMap<u64, oneshot::Sender<()>>,
+}
+
+const EMPTY_POOL: Mutex<Pool> = Mutex::new(Pool {
+    pending: VecDeque::new(),
+    stolen: BTreeMap::new(),
+    running_stolen: BTreeMap::new(),
+});
+static POOLS: [Mutex<Pool>; MAX_SERVERS] = [EMPTY_POOL; MAX_SERVERS];
+
+const NO_SLOTS: AtomicUsize = AtomicUsize::new(0);
+static RUNNING: [AtomicUsize; MAX_SERVERS] = [NO_SLOTS; MAX_SERVERS];
+// 0 until set_capacity, meaning one slot per CPU.
+static CAPACITY: [AtomicUsize; MAX_SERVERS] = [NO_SLOTS; MAX_SERVERS];
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static RUNTIME: OnceLock<Handle> = OnceLock::new();
+
+// Pooled tasks this server runs at once.
+pub fn capacity() -> usize {
+    server_capacity(unsafe { SERVER_INDEX })
+}
+
+pub fn set_capacity(slots: usize) {
+    assert!(slots > 0, "a server needs at least one slot for pooled tasks");
+    CAPACITY[unsafe { SERVER_INDEX }].store(slots, Ordering::Relaxed);
+}
+
+fn server_capacity(server_idx: usize) -> usize {
+    match CAPACITY[server_idx].load(Ordering::Relaxed) {
+        0 => available_parallelism().map_or(1, |n| n.get()),
+        slots => slots,
+    }
+}
+
+// Pooled tasks running on this server.
+pub fn running() -> usize {
+    RUNNING[unsafe { SERVER_INDEX }].load(Ordering::Relaxed)
+}
+
+// Pooled tasks spawned on this server that have not started yet.
+pub fn queued() -> usize {
+    POOLS[unsafe { SERVER_INDEX }].lock().unwrap().pending.len()
+}
+
+fn free_slots(server_idx: usize) -> usize {
+    server_capacity(server_idx).saturating_sub(RUNNING[server_idx].load(Ordering::SeqCst))
+}
+
+// Tasks of this server run on the runtime that started the stealer, not on the runtime of
+// whoever happened to free a slot, which may be a short-lived one.
+fn runtime() -> Handle {
+    RUNTIME.get().cloned().unwrap_or_else(Handle::current)
+}
+
+// Queues a shipped future. The receiver gets its output once some server ran it.
+pub(crate) fn submit(
+    pin: Option<usize>,
+    ptr: (usize, usize),
+    future_bytes: Vec<u8>,
+    runner: usize,
+) -> oneshot::Receiver<TaskResult> {
+    let server_idx = unsafe { SERVER_INDEX };
+    let (reply, result) = oneshot::channel();
+    let id = (server_idx as u64) << 48 | NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    POOLS[server_idx].lock().unwrap().pending.push_back(Job {
+        id,
+        pin,
+        ptr,
+        future_bytes,
+        runner,
+        reply,
+    });
+    pump(server_idx);
+    result
+}
+
+// Removes up to `max` of the oldest tasks `taker` may run. Tasks whose handle was aborted are
+// dropped on the way.
+fn take(pool: &mut Pool, taker: usize, max: usize) -> Vec<Job> {
+    let mut jobs = Vec::new();
+    let mut i = 0;
+    while jobs.len() < max && i < pool.pending.len() {
+        let job = &pool.pending[i];
+        if job.reply.is_canceled() {
+            pool.pending.remove(i);
+        } else if job.pin.map_or(true, |pin| pin == taker) {
+            jobs.push(pool.pending.remove(i).unwrap());
+        } else {
+            i += 1;
+        }
+    }
+    jobs
+}
+
+// Starts queued tasks of `server_idx` on its free slots.
+fn pump(server_idx: usize) {
+    let free = free_slots(server_idx);
+    if free == 0 {
+        return;
+    }
+    let jobs = take(&mut POOLS[server_idx].lock().unwrap(), server_idx, free);
+    for job in jobs {
+        RUNNING[server_idx].fetch_add(1, Ordering::SeqCst);
+        let mut reply = job.reply;
+        runtime().spawn(SimTask::on(server_idx, async move {
+            let run = Box::pin(execute_spawn(job.ptr, job.future_bytes, job.runner));
+            // An aborted handle drops the task at its next await point, as for dspawn.
+            let result = match future::select(run, reply.cancellation()).await {
+                Either::Left((result, _)) => Some(result),
+                Either::Right(_) => None,
+            };
+            if let Some(result) = result {
+                let _ = reply.send(result);
+            }
+            RUNNING[server_idx].fetch_sub(1, Ordering::SeqCst);
+            pump(server_idx);
+        }));
+    }
+}
+
+// Handler of steal on the victim. The jobs stay here until the thief confirms them.
+pub(crate) fn give_jobs(thief: usize, max: usize) -> Vec<StolenJob> {
+    let mut pool = POOLS[unsafe { SERVER_INDEX }].lock().unwrap();
+    let jobs = take(&mut pool, thief, max);
+    jobs.into_iter()
+        .map(|job| {
+            let stolen = StolenJob {
+                id: job.id,
+                ptr: job.ptr,
+                future_bytes: job.future_bytes.clone(),
+                runner: job.runner,
+            };
+            let since = Instant::now();
+            pool.stolen.insert(job.id, Stolen::Offered { thief, since, job });
+            stolen
+        })
+        .collect()
+}
+
+// Handler of steal_ack on the victim. Returns the ids `thief` may run, leaving out the ones that
+// were queued again meanwhile.
+pub(crate) fn confirm_jobs(thief: usize, ids: Vec<u64>) -> Vec<u64> {
+    let server_idx = unsafe { SERVER_INDEX };
+    let mut pool = POOLS[server_idx].lock().unwrap();
+    ids.into_iter()
+        .filter(|id| match pool.stolen.remove(id) {
+            Some(Stolen::Offered { thief: offered_to, job, .. }) if offered_to == thief => {
+                let (done, result) = oneshot::channel();
+                pool.stolen.insert(*id, Stolen::Running { done });
+                let forward = forward_stolen(server_idx, thief, *id, job.reply, result);
+                runtime().spawn(SimTask::on(server_idx, forward));
+                true
+            }
+            Some(other) => {
+                pool.stolen.insert(*id, other);
+                false
+            }
+            None => false,
+        })
+        .collect()
+}
+
+// Handler of steal_done on the victim.
+pub(crate) fn finish_job(id: u64, result: TaskResult) {
+    let stolen = POOLS[unsafe { SERVER_INDEX }].lock().unwrap().stolen.remove(&id);
+    if let Some(Stolen::Running { done }) = stolen {
+        let _ = done.send(result);
+    }
+}
+
+// Passes the result of a stolen task to its handle. If the handle is aborted first, the thief is
+// told to drop the task.
+async fn forward_stolen(
+    server_idx: usize,
+    thief: usize,
+    id: u64,
+    mut reply: oneshot::Sender<TaskResult>,
+    result: oneshot::Receiver<TaskResult>,
+) {
+    let result = match future::select(reply.cancellation(), result).await {
+        Either::Left(_) => None,
+        Either::Right((result, _)) => Some(result),
+    };
+    match result {
+        Some(Ok(result)) => {
+            let _ = reply.send(result);
+        }
+        // The entry was dropped without a result.
+        Some(Err(_)) => {}
+        None => {
+            POOLS[server_idx].lock().unwrap().stolen.remove(&id);
+            if let Err(e) = cancel_stolen(thief, id).await {
+                dprintln!("cannot cancel task {:x} on server {}: {}", id, thief, e);
+            }
+        }
+    }
+}
+
+// Handler of cancel_stolen on the thief.
+pub(crate) fn abort_stolen(id: u64) {
+    POOLS[unsafe { SERVER_INDEX }].lock().unwrap().running_stolen.remove(&id);
+}
+
+// Puts tasks whose thief did not confirm them in time back at the front of the queue.
+fn requeue_unconfirmed(server_idx: usize) {
+    let mut pool = POOLS[server_idx].lock().unwrap();
+    let expired: Vec<u64> = pool
+        .stolen
+        .iter()
+        .filter(|(_, stolen)| {
+            matches!(stolen, Stolen::Offered { since, .. } if since.elapsed() >= STEAL_ACK_TIMEOUT)
+        })
+        .map(|(id, _)| *id)
+        .collect();
+    // Ids grow in spawn order, so pushing the newest first keeps the oldest at the front.
+    for id in expired.into_iter().rev() {
+        if let Some(Stolen::Offered { thief, job, .. }) = pool.stolen.remove(&id) {
+            dprintln!("server {} did not confirm task {:x}, queueing it again", thief, id);
+            pool.pending.push_front(job);
+        }
+    }
+}
+
+async fn steal(victim: usize, thief: usize, max: usize) -> Result<Vec<StolenJob>, RpcError> {
+    if sim::is_active() {
+        return Ok(sim::on_server(victim, || give_jobs(thief, max)));
+    }
+    get_dclient(victim).steal(context::current(), thief, max).await
+}
+
+async fn steal_ack(victim: usize, thief: usize, ids: Vec<u64>) -> Result<Vec<u64>, RpcError> {
+    if sim::is_active() {
+        return Ok(sim::on_server(victim, || confirm_jobs(thief, ids)));
+    }
+    get_dclient(victim).steal_ack(context::current(), thief, ids).await
+}
+
+async fn cancel_stolen(thief: usize, id: u64) -> Result<(), RpcError> {
+    if sim::is_active() {
+        sim::on_server(thief, || abort_stolen(id));
+        return Ok(());
+    }
+    get_dclient(thief).cancel_stolen(context::current(), id).await
+}
+
+async fn steal_done(victim: usize, id: u64, result: TaskResult) -> Result<(), RpcError> {
+    if sim::is_active() {
+        sim::on_server(victim, || finish_job(id, result));
+        return Ok(());
+    }
+    get_dclient(victim).steal_done(context::current(), id, result).await
+}
+
+// Runs a task taken from `victim` and sends the result back, unless cancel_stolen drops it first.
+fn run_stolen(server_idx: usize, victim: usize, job: StolenJob) {
+    RUNNING[server_idx].fetch_add(1, Ordering::SeqCst);
+    let (abort, aborted) = oneshot::channel::<()>();
+    POOLS[server_idx].lock().unwrap().running_stolen.insert(job.id, abort);
+    runtime().spawn(SimTask::on(server_idx, async move {
+        let run = Box::pin(execute_spawn(job.ptr, job.future_bytes, job.runner));
+        // Dropped at its next await point, as an aborted dspawn task.
+        let result = match future::select(run, aborted).await {
+            Either::Left((result, _)) => Some(result),
+            Either::Right(_) => None,
+        };
+        POOLS[server_idx].lock().unwrap().running_stolen.remove(&job.id);
+        RUNNING[server_idx].fetch_sub(1, Ordering::SeqCst);
+        pump(server_idx);
+        let Some(result) = result else {
+            dprintln!("task {:x} of server {} was cancelled", job.id, victim);
+            return;
+        };
+        let mut backoff = STEAL_MIN_INTERVAL;
+        for attempt in 1..=STEAL_DONE_ATTEMPTS {
+            match steal_done(victim, job.id, result.clone()).await {
+                Ok(()) => return,
+                Err(e) if attempt == STEAL_DONE_ATTEMPTS => eprintln!(
+                    "drust: cannot return stolen task {:x} to server {}: {}",
+                    job.id, victim, e
+                ),
+                Err(_) => {
+                    sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+        }
+    }));
+}
+
+// Asks the peers, starting at a different one each round, for work until one has some. Returns
+// whether anything was stolen.
+async fn steal_round(server_idx: usize, round: usize) -> bool {
+    let num_servers = unsafe { NUM_SERVERS };
+    for offset in 0..num_servers - 1 {
+        let free = free_slots(server_idx);
+        if free == 0 {
+            return false;
+        }
+        let victim = (server_idx + 1 + (round + offset) % (num_servers - 1)) % num_servers;
+        match steal(victim, server_idx, free).await {
+            Ok(jobs) if !jobs.is_empty() => {
+                dprintln!("server {} stole {} tasks from server {}", server_idx, jobs.len(), victim);
+                let ids = jobs.iter().map(|job| job.id).collect();
+                // Without a confirmation the victim queues the tasks again after STEAL_ACK_TIMEOUT.
+                let confirmed = match steal_ack(victim, server_idx, ids).await {
+                    Ok(confirmed) => confirmed,
+                    Err(e) => {
+                        dprintln!("confirming tasks of server {} failed: {}", victim, e);
+                        continue;
+                    }
+                };
+                for job in jobs.into_iter().filter(|job| confirmed.contains(&job.id)) {
+                    run_stolen(server_idx, victim, job);
+                }
+                return true;
+            }
+            Ok(_) => {}
+            Err(e) => dprintln!("steal from server {} failed: {}", victim, e),
+        }
+    }
+    false
+}
+
+// Runs queued tasks of this server and steals from peers whenever slots are free. Started once
+// per server, on the runtime pooled tasks should run on.
+pub async fn run_stealer() {
+    let _ = RUNTIME.set(Handle::current());
+    let server_idx = unsafe { SERVER_INDEX };
+    let mut interval = STEAL_MIN_INTERVAL;
+    let mut round = 0;
+    loop {
+        sleep(interval).await;
+        requeue_unconfirmed(server_idx);
+        pump(server_idx);
+        round += 1;
+        // A busy server keeps the short interval, to steal as soon as a slot frees up.
+        interval = if free_slots(server_idx) == 0 || steal_round(server_idx, round).await {
+            STEAL_MIN_INTERVAL
+        } else {
+            (interval * 2).min(STEAL_MAX_INTERVAL)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drust_std::thread::{dspawn_pooled, DJoinHandle};
+
+    // Fills the slots of server 0 and queues a task there, which stays queued.
+    fn queue_on_busy_server() -> DJoinHandle<usize> {
+        set_capacity(1);
+        RUNNING[0].fetch_add(1, Ordering::SeqCst);
+        let handle = dspawn_pooled(async { 7usize });
+        assert_eq!(queued(), 1);
+        handle
+    }
+
+    fn reset(num_servers: usize) {
+        RUNNING[0].fetch_sub(1, Ordering::SeqCst);
+        for capacity in &CAPACITY[..num_servers] {
+            capacity.store(0, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn idle_server_steals_and_returns_the_result() {
+        sim::test_cluster(2).run_test(async {
+            let handle = queue_on_busy_server();
+            sim::on_server(1, || set_capacity(1));
+            assert!(SimTask::on(1, steal_round(1, 0)).await);
+            assert_eq!(queued(), 0);
+
+            // The thief runs the task and reports it with steal_done.
+            assert_eq!(handle.await.unwrap(), 7);
+            assert!(POOLS[0].lock().unwrap().stolen.is_empty());
+            assert!(POOLS[1].lock().unwrap().running_stolen.is_empty());
+            assert_eq!(RUNNING[1].load(Ordering::SeqCst), 0);
+            reset(2);
+        });
+    }
+
+    #[test]
+    fn unconfirmed_steal_is_queued_again() {
+        sim::test_cluster(3).run_test(async {
+            let handle = queue_on_busy_server();
+            let ids: Vec<u64> = give_jobs(1, 1).into_iter().map(|job| job.id).collect();
+            assert_eq!((ids.len(), queued()), (1, 0));
+            // Only the thief the task was offered to may confirm it.
+            assert!(confirm_jobs(2, ids.clone()).is_empty());
+
+            // The guard is scoped, so the future stays Send.
+            {
+                let mut pool = POOLS[0].lock().unwrap();
+                if let Some(Stolen::Offered { since, .. }) = pool.stolen.get_mut(&ids[0]) {
+                    *since -= STEAL_ACK_TIMEOUT;
+                }
+            }
+            requeue_unconfirmed(0);
+            assert_eq!(queued(), 1);
+            // A confirmation that comes too late does not run it twice.
+            assert!(confirm_jobs(1, ids).is_empty());
+
+            reset(3);
+            pump(0);
+            assert_eq!(handle.await.unwrap(), 7);
+        });
+    }
+}
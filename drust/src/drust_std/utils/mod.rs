@@ -1,8 +1,8 @@
 use std::sync::{atomic::AtomicBool, Condvar, Mutex};
 
-use clap::Parser;
+use clap::{builder::PossibleValuesParser, Parser};
 
-use super::NUM_SERVERS;
+use super::{application::app_names, comm::TransportKind, NUM_SERVERS};
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
@@ -10,16 +10,31 @@ use super::NUM_SERVERS;
 pub struct Args {
     /// server index
     #[arg(short, long)]
-    serverid: u8,
+    pub serverid: u8,
 
     /// application name
+    #[arg(short, long, value_parser = PossibleValuesParser::new(app_names()))]
+    pub application: String,
+
+    /// transport for one-sided remote memory access
+    #[arg(short, long, value_enum, default_value_t = TransportKind::default())]
+    pub transport: TransportKind,
+
+    /// number of servers, defaults to all servers listed in the config
     #[arg(short, long)]
-    application: String,
+    pub num_servers: Option<usize>,
+
+    /// heap size of each server in GB, overrides the config
+    #[arg(long)]
+    pub heap_size_gb: Option<usize>,
+
+    /// path of the cluster config
+    #[arg(short, long, default_value = "drust.json")]
+    pub config: String,
 }
 
-pub fn get_args() -> (String, usize) {
-    let args = Args::parse();
-    (args.application, args.serverid as usize)
+pub fn get_args() -> Args {
+    Args::parse()
 }
 
 pub struct Resource {
@@ -28,6 +43,11 @@ pub struct Resource {
 }
 
 impl Resource {
+    // Server the task holding this slot runs on.
+    pub fn server_idx(&self) -> usize {
+        self.manager.servers[self.id]
+    }
+
     pub fn release(self) {
         self.manager.release_resource(self);
     }
@@ -39,10 +59,32 @@ pub struct ResourceManager {
     resource_num: usize,
     avail_num: Mutex<usize>,
     avail_resources: Vec<AtomicBool>,
+    // Server of each slot.
+    servers: Vec<usize>,
 }
 
 impl ResourceManager {
     pub fn new(num: usize) -> Self {
+        let num_servers = unsafe { NUM_SERVERS };
+        Self::with_servers((0..num).map(|id| id % num_servers).collect())
+    }
+
+    // A pool with `slots[i]` slots on server i. Slots are interleaved across servers, so slot i
+    // is on server i as long as every server has one, as with new.
+    pub fn with_slots(slots: &[usize]) -> Self {
+        let mut left = slots.to_vec();
+        let mut servers = Vec::with_capacity(slots.iter().sum());
+        while left.iter().any(|&n| n > 0) {
+            for (server_idx, n) in left.iter_mut().enumerate().filter(|(_, n)| **n > 0) {
+                servers.push(server_idx);
+                *n -= 1;
+            }
+        }
+        Self::with_servers(servers)
+    }
+
+    fn with_servers(servers: Vec<usize>) -> Self {
+        let num = servers.len();
         let mut avail_resources = Vec::with_capacity(num);
         for _ in 0..num {
             avail_resources.push(AtomicBool::new(true));
@@ -52,6 +94,7 @@ impl ResourceManager {
             resource_num: num,
             avail_num: Mutex::new(num),
             avail_resources,
+            servers,
         }
     }
 
@@ -83,6 +126,17 @@ impl ResourceManager {
         }
     }
 
+    // Taken slots that run their task on `server_idx`.
+    pub fn busy_on(&self, server_idx: usize) -> usize {
+        self.avail_resources
+            .iter()
+            .zip(&self.servers)
+            .filter(|(avail, &server)| {
+                server == server_idx && !avail.load(std::sync::atomic::Ordering::Relaxed)
+            })
+            .count()
+    }
+
     pub fn release_resource(&self, res: Resource) {
         self.avail_resources[res.id].store(true, std::sync::atomic::Ordering::SeqCst);
         let mut lock = self.avail_num.lock().unwrap();
@@ -134,6 +188,10 @@ impl SimpleResourceManager {
         }
     }
 
+    pub fn busy(&self) -> usize {
+        self.resource_num - *self.avail_num.lock().unwrap()
+    }
+
     pub fn release_resource(&self, res: SimpleResource) {
         let mut lock = self.avail_num.lock().unwrap();
         *lock += 1;
@@ -0,0 +1,32 @@
+#![feature(allocator_api)]
+#![feature(slice_ptr_get)]
+#![feature(vec_into_raw_parts)]
+#![feature(thread_id_value)]
+#![feature(concat_idents)]
+#![feature(async_fn_in_trait)]
+#![feature(unsafe_pin_internals)]
+#![feature(core_intrinsics)]
+#![feature(ptr_from_ref)]
+// The per-server tables are arrays of locks built from a const item, and unsafe functions are
+// described by plain comments like everything else here.
+#![allow(clippy::declare_interior_mutable_const, clippy::missing_safety_doc)]
+
+// Lets #[derive(DRust)] refer to ::drust inside this crate too.
+extern crate self as drust;
+
+// The bundled workloads stay in the library for now: spec.rs still refers to their types by id.
+#[allow(unused, clippy::all)]
+pub mod app;
+pub mod conf;
+pub mod drust_std;
+pub mod prelude;
+#[allow(unused, clippy::all)]
+pub mod spec;
+
+// Used by the exported macros, so applications don't need their own tarpc/futures versions.
+#[doc(hidden)]
+pub use futures;
+#[doc(hidden)]
+pub use inventory;
+#[doc(hidden)]
+pub use tarpc;
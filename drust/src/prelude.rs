@@ -0,0 +1,20 @@
+pub use crate::{
+    conf::{NUM_SERVERS, SERVER_INDEX},
+    dassert, dprintln,
+    drust_std::{
+        alloc::Placement,
+        application::{AppFuture, DrustApp},
+        collections::{
+            dstring::DString,
+            dpar::DParIter,
+            dvec::{DVec, DVecMutRef, DVecRef},
+        },
+        connect::config::{dataset_dir, log_dir},
+        primitives::{dbox::DBox, dmut::DMut, dref::DRef, tbox::TBox, DRust},
+        sync::dmutex::DMutex,
+        thread::{
+            dscope_spawn, dspawn, dspawn_to, dspawn_to_relaxed, dspawn_to_strictly, dspawn_with,
+            dspawn_near, dspawn_pooled, dspawn_pooled_to, DJoinHandle, Footprint, JoinError, ResourceClass, SpawnOptions,
+        },
+    },
+};
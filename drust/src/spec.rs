@@ -1,18 +1,35 @@
 use core::panic;
+use std::alloc::Allocator;
 
-use crate::{app::{dataframe::prelude::{AnyType, Chunk, DataType, CHUNK_SIZE}, kv::entry::GlobalEntry, socialnet::media::Image}, drust_std::{alloc::*, collections::dvec::DVec, primitives::*}, exclude};
+use crate::{drust_std::{alloc::*, collections::dvec::DVec, primitives::{registry, *}}, exclude};
+#[cfg(feature = "dataframe")]
+use crate::app::dataframe::prelude::{AnyType, Chunk, DataType, CHUNK_SIZE};
+#[cfg(feature = "kv")]
+use crate::app::kv::entry::GlobalEntry;
+#[cfg(feature = "socialnet")]
+use crate::app::socialnet::media::Image;
+
+// Ids below registry::DERIVED are assigned here by hand; types with #[derive(DRust)] are looked up
+// in the registry instead and need no entry. The ids of the bundled apps stay reserved when their
+// feature is off.
 
 // use crate::{app::{dataframe::prelude::{Chunk, CHUNK_SIZE}, kvstore::GlobalEntry, sequential::{CHUNK_NUM, ELEMENT_UNIT_NUM}, socialnet::utils::FRAME_SIZE}, drust_std::alloc::LOCAL_ALLOCATOR, prelude::*};
 
 exclude!(usize, 16);
 exclude!(i32, 17);
 exclude!(u8, 19);
+#[cfg(feature = "dataframe")]
 exclude!(DataType, 20);
+#[cfg(feature = "dataframe")]
 exclude!([u8; CHUNK_SIZE], 3);
+#[cfg(feature = "dataframe")]
 exclude!(Chunk, 4);
+#[cfg(feature = "dataframe")]
 exclude!(AnyType, 7);
+#[cfg(feature = "kv")]
 exclude!(GlobalEntry, 6);
 exclude!((), 64);
+#[cfg(feature = "socialnet")]
 exclude!(Image, 21);
 
 
@@ -28,6 +45,11 @@ impl<T> DRust for (DVec<T>, DVec<T>, DVec<T>)
     fn migrate(&mut self, _dst: Destination) -> bool {
         false
     }
+    fn drop_local_copies(&mut self) {
+        self.0.drop_local_copies();
+        self.1.drop_local_copies();
+        self.2.drop_local_copies();
+    }
 }
 
 impl<T> DRust for (DVec<T>, DVec<T>) 
@@ -43,9 +65,17 @@ impl<T> DRust for (DVec<T>, DVec<T>)
     fn migrate(&mut self, _dst: Destination) -> bool {
         false
     }
+    fn drop_local_copies(&mut self) {
+        self.0.drop_local_copies();
+        self.1.drop_local_copies();
+    }
 }
 
 pub fn drop_vec_with_id(type_id: u32, addr: usize, capacity: usize, len: usize) {
+    drop_vec_with_id_in(type_id, addr, capacity, len, local_allocator());
+}
+
+pub fn drop_vec_with_id_in<A: Allocator>(type_id: u32, addr: usize, capacity: usize, len: usize, alloc: A) {
     match (type_id & 0xFF) {
         1 => {
             panic!("does not support drop a vector of disjoint pointer to remote vec!");
@@ -53,32 +83,36 @@ pub fn drop_vec_with_id(type_id: u32, addr: usize, capacity: usize, len: usize)
         2 => {
             panic!("does not support drop a vector of disjoint pointer to remote vecref!");
         },
+        #[cfg(feature = "dataframe")]
         3 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut [u8; CHUNK_SIZE], len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut [u8; CHUNK_SIZE], len, capacity, alloc)};
             drop(v);
         },
+        #[cfg(feature = "dataframe")]
         4 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut Chunk, len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut Chunk, len, capacity, alloc)};
             drop(v);
         },
         // 5 => {
         //     let v = unsafe {Vec::from_raw_parts_in(addr as *mut [u8; FRAME_SIZE], len, capacity, &LOCAL_ALLOCATOR)};
         //     drop(v);
         // },
+        #[cfg(feature = "kv")]
         6 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut GlobalEntry, len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut GlobalEntry, len, capacity, alloc)};
             drop(v);
         },
+        #[cfg(feature = "dataframe")]
         7 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut AnyType, len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut AnyType, len, capacity, alloc)};
             drop(v);
         },
         16 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut usize, len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut usize, len, capacity, alloc)};
             drop(v);
         },
         17 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut i32, len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut i32, len, capacity, alloc)};
             drop(v);
         },
         // // 18 => {
@@ -86,17 +120,22 @@ pub fn drop_vec_with_id(type_id: u32, addr: usize, capacity: usize, len: usize)
         // //     drop(v);
         // // },
         19 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut u8, len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut u8, len, capacity, alloc)};
             drop(v);
         },
+        #[cfg(feature = "dataframe")]
         20 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut DataType, len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut DataType, len, capacity, alloc)};
             drop(v);
         },
+        #[cfg(feature = "socialnet")]
         21 => {
-            let v = unsafe {Vec::from_raw_parts_in(addr as *mut Image, len, capacity, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Vec::from_raw_parts_in(addr as *mut Image, len, capacity, alloc)};
             drop(v);
         },
+        registry::DERIVED => {
+            unsafe { registry::lookup(type_id).drop_vec_in(addr, capacity, len, alloc) };
+        },
         _ => {
             panic!("unknown vec type id: {}", type_id);
         }
@@ -106,7 +145,11 @@ pub fn drop_vec_with_id(type_id: u32, addr: usize, capacity: usize, len: usize)
 pub fn from_id_to_type(
     type_id: u32,
     addr: usize,
-) -> Box<dyn DRust, &'static good_memory_allocator::SpinLockedAllocator> {
+) -> Box<dyn DRust, &'static HeapAllocator> {
+    from_id_to_type_in(type_id, addr, local_allocator())
+}
+
+pub fn from_id_to_type_in<A: Allocator>(type_id: u32, addr: usize, alloc: A) -> Box<dyn DRust, A> {
     match (type_id & 0xFF) {
         1 => {
             panic!("does not support drop disjoint pointer to remote vec!");
@@ -114,13 +157,15 @@ pub fn from_id_to_type(
         2 => {
             panic!("does not support drop disjoint pointer to remote vecref!");
         },
+        #[cfg(feature = "dataframe")]
         3 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut [u8; CHUNK_SIZE], &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut [u8; CHUNK_SIZE], alloc)};
             debug_assert_eq!(v.typeid(), 2, "type id: {}, compared value: {}", v.typeid(), 2);
             v
         },
+        #[cfg(feature = "dataframe")]
         4 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut Chunk, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut Chunk, alloc)};
             debug_assert_eq!(v.typeid(), 3, "type id: {}, compared value: {}", v.typeid(), 3);
             v
         },
@@ -129,23 +174,25 @@ pub fn from_id_to_type(
         //     debug_assert_eq!(v.typeid(), 5, "type id: {}, compared value: {}", v.typeid(), 5);
         //     v
         // },
+        #[cfg(feature = "kv")]
         6 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut GlobalEntry, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut GlobalEntry, alloc)};
             debug_assert_eq!(v.typeid(), 6, "type id: {}, compared value: {}", v.typeid(), 6);
             v
         },
+        #[cfg(feature = "dataframe")]
         7 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut AnyType, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut AnyType, alloc)};
             debug_assert_eq!(v.typeid(), 7, "type id: {}, compared value: {}", v.typeid(), 7);
             v
         },
         16 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut usize, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut usize, alloc)};
             debug_assert_eq!(v.typeid(), 16, "type id: {}, compared value: {}", v.typeid(), 16);
             v
         },
         17 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut i32, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut i32, alloc)};
             debug_assert_eq!(v.typeid(), 17, "type id: {}, compared value: {}", v.typeid(), 17);
             v
         },
@@ -155,20 +202,25 @@ pub fn from_id_to_type(
         // //     v
         // // },
         19 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut u8, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut u8, alloc)};
             debug_assert_eq!(v.typeid(), 19, "type id: {}, compared value: {}", v.typeid(), 19);
             v
         },
+        #[cfg(feature = "dataframe")]
         20 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut DataType, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut DataType, alloc)};
             debug_assert_eq!(v.typeid(), 20, "type id: {}, compared value: {}", v.typeid(), 20);
             v
         },
+        #[cfg(feature = "socialnet")]
         21 => {
-            let v = unsafe {Box::from_raw_in(addr as *mut Image, &LOCAL_ALLOCATOR)};
+            let v = unsafe {Box::from_raw_in(addr as *mut Image, alloc)};
             debug_assert_eq!(v.typeid(), 21, "type id: {}, compared value: {}", v.typeid(), 21);
             v
         },
+        registry::DERIVED => {
+            registry::lookup(type_id).from_addr_in(addr, alloc)
+        },
         _ => {
             panic!("unknown type id: {}", type_id);
         }
@@ -202,10 +254,16 @@ macro_rules! dassert {
 }
 
 
+// Trace output, enabled with "log_level": "debug" in drust.json.
 #[macro_export]
 macro_rules! dprintln {
     ($($arg:tt)*) => {
         {
+            if unsafe { $crate::drust_std::connect::config::LOG_LEVEL }
+                >= $crate::drust_std::connect::config::LogLevel::Debug
+            {
+                println!($($arg)*);
+            }
         }
     }
 }